@@ -0,0 +1,150 @@
+/* Generated by cbindgen from src/ffi; do not edit. */
+
+#ifndef FHE_AES_H
+#define FHE_AES_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// Result of every `fhe_aes_*` function
+typedef enum FheAesStatus {
+  FHE_AES_STATUS_OK = 0,
+  // A required pointer argument was null
+  FHE_AES_STATUS_NULL_POINTER = 1,
+  // A name or length argument was not accepted
+  FHE_AES_STATUS_INVALID_ARGUMENT = 2,
+  // Bytes could not be deserialized, or belong to another parameter set
+  FHE_AES_STATUS_INVALID_ARTIFACT = 3,
+  // Blocks were requested before `fhe_aes_expand_key`
+  FHE_AES_STATUS_KEY_NOT_EXPANDED = 4,
+  // The evaluation was cancelled with `fhe_aes_cancel`
+  FHE_AES_STATUS_CANCELLED = 5,
+  // The library panicked; the handle involved should be freed and not used again
+  FHE_AES_STATUS_PANIC = 6,
+} FheAesStatus;
+
+// AES-128 evaluator holding a server key and, once expanded, an AES key
+typedef struct FheAes FheAes;
+
+// Client key, used to encrypt inputs and decrypt outputs
+typedef struct FheAesClientKey FheAesClientKey;
+
+// Bytes allocated by the library, released with `fhe_aes_buffer_free`
+typedef struct FheAesBuffer {
+  uint8_t *data;
+  size_t len;
+} FheAesBuffer;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Message of the last failed call on this thread, empty after a successful one.
+// The string is owned by the library and valid until the next call on this thread.
+const char *fhe_aes_last_error(void);
+
+// Creates an evaluator from a server key serialized as an `Artifact<ServerKey>`.
+// `parameter_set` and `circuit` are names as accepted by the CLI; null selects
+// `default` and `scale-mamba`.
+//
+// # Safety
+// `parameter_set` and `circuit` must be null or NUL-terminated strings,
+// `server_key` must point to `server_key_len` readable bytes and `out` must be writable.
+enum FheAesStatus fhe_aes_new(const char *parameter_set,
+                              const char *circuit,
+                              const uint8_t *server_key,
+                              size_t server_key_len,
+                              struct FheAes **out);
+
+// Destroys an evaluator; null is ignored
+//
+// # Safety
+// `fhe_aes` must be null or a handle from `fhe_aes_new` that is not used afterwards.
+void fhe_aes_free(struct FheAes *fhe_aes);
+
+// Expands the 128 encrypted AES key bits produced by `fhe_aes_encrypt_key`,
+// replacing any previously expanded key
+//
+// # Safety
+// `fhe_aes` must be a live handle not used concurrently, and `key` must point to
+// `key_len` readable bytes.
+enum FheAesStatus fhe_aes_expand_key(struct FheAes *fhe_aes, const uint8_t *key, size_t key_len);
+
+// Computes `count` CTR blocks from the encrypted counter produced by
+// `fhe_aes_encrypt_iv` and stores them in `out`, serialized like `export_outputs`
+//
+// # Safety
+// `fhe_aes` must be a live handle, `iv` must point to `iv_len` readable bytes and
+// `out` must be writable. Concurrent calls on the same handle are allowed.
+enum FheAesStatus fhe_aes_ctr_blocks(const struct FheAes *fhe_aes,
+                                     const uint8_t *iv,
+                                     size_t iv_len,
+                                     size_t count,
+                                     struct FheAesBuffer *out);
+
+// Makes running and future evaluations on `fhe_aes` fail with `Cancelled`.
+// Safe to call from another thread while an evaluation is running.
+//
+// # Safety
+// `fhe_aes` must be null or a live handle.
+void fhe_aes_cancel(const struct FheAes *fhe_aes);
+
+// Releases a buffer filled by the library; an empty buffer is ignored
+//
+// # Safety
+// `buffer` must come from the library and not be used afterwards.
+void fhe_aes_buffer_free(struct FheAesBuffer buffer);
+
+// Generates a client key for `parameter_set` (null selects `default`) and stores
+// the matching server key in `server_key`, serialized for `fhe_aes_new`
+//
+// # Safety
+// `parameter_set` must be null or a NUL-terminated string; `out` and
+// `server_key` must be writable.
+enum FheAesStatus fhe_aes_client_key_generate(const char *parameter_set,
+                                              struct FheAesClientKey **out,
+                                              struct FheAesBuffer *server_key);
+
+// Destroys a client key; null is ignored
+//
+// # Safety
+// `client_key` must be null or a handle from `fhe_aes_client_key_generate` that
+// is not used afterwards.
+void fhe_aes_client_key_free(struct FheAesClientKey *client_key);
+
+// Encrypts the 16-byte AES `key` for `fhe_aes_expand_key`
+//
+// # Safety
+// `client_key` must be a live handle, `key` must point to 16 readable bytes and
+// `out` must be writable.
+enum FheAesStatus fhe_aes_encrypt_key(const struct FheAesClientKey *client_key,
+                                      const uint8_t *key,
+                                      struct FheAesBuffer *out);
+
+// Encrypts the 16-byte initial counter block for `fhe_aes_ctr_blocks`
+//
+// # Safety
+// `client_key` must be a live handle, `iv` must point to 16 readable bytes and
+// `out` must be writable.
+enum FheAesStatus fhe_aes_encrypt_iv(const struct FheAesClientKey *client_key,
+                                     const uint8_t *iv,
+                                     struct FheAesBuffer *out);
+
+// Decrypts blocks serialized by `fhe_aes_ctr_blocks` into 16 bytes per block
+//
+// # Safety
+// `client_key` must be a live handle, `outputs` must point to `outputs_len`
+// readable bytes and `out` must be writable.
+enum FheAesStatus fhe_aes_decrypt_outputs(const struct FheAesClientKey *client_key,
+                                          const uint8_t *outputs,
+                                          size_t outputs_len,
+                                          struct FheAesBuffer *out);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* FHE_AES_H */
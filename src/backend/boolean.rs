@@ -0,0 +1,61 @@
+//! tfhe boolean backend
+
+use super::{BooleanBackend, ClientBackend};
+use crate::params::ParameterSet;
+use tfhe::boolean::prelude::*;
+
+impl BooleanBackend for ServerKey {
+    type Ciphertext = Ciphertext;
+
+    fn and(&self, a: &Ciphertext, b: &Ciphertext) -> Ciphertext {
+        BinaryBooleanGates::and(self, a, b)
+    }
+
+    fn xor(&self, a: &Ciphertext, b: &Ciphertext) -> Ciphertext {
+        BinaryBooleanGates::xor(self, a, b)
+    }
+
+    fn not(&self, a: &Ciphertext) -> Ciphertext {
+        ServerKey::not(self, a)
+    }
+
+    fn trivial(&self, value: bool) -> Ciphertext {
+        Ciphertext::Trivial(value)
+    }
+
+    fn or(&self, a: &Ciphertext, b: &Ciphertext) -> Ciphertext {
+        BinaryBooleanGates::or(self, a, b)
+    }
+
+    fn nand(&self, a: &Ciphertext, b: &Ciphertext) -> Ciphertext {
+        BinaryBooleanGates::nand(self, a, b)
+    }
+
+    fn nor(&self, a: &Ciphertext, b: &Ciphertext) -> Ciphertext {
+        BinaryBooleanGates::nor(self, a, b)
+    }
+
+    fn xnor(&self, a: &Ciphertext, b: &Ciphertext) -> Ciphertext {
+        BinaryBooleanGates::xnor(self, a, b)
+    }
+
+    fn mux(&self, select: &Ciphertext, if_true: &Ciphertext, if_false: &Ciphertext) -> Ciphertext {
+        ServerKey::mux(self, select, if_true, if_false)
+    }
+}
+
+impl ClientBackend for ServerKey {
+    type ClientKey = ClientKey;
+
+    fn gen_keys(parameter_set: &ParameterSet) -> (ClientKey, ServerKey) {
+        parameter_set.gen_keys()
+    }
+
+    fn encrypt(client_key: &ClientKey, value: bool) -> Ciphertext {
+        client_key.encrypt(value)
+    }
+
+    fn decrypt(client_key: &ClientKey, ciphertext: &Ciphertext) -> bool {
+        client_key.decrypt(ciphertext)
+    }
+}
@@ -0,0 +1,66 @@
+//! Cleartext backend operating on plain booleans
+
+use super::{BooleanBackend, ClientBackend};
+use crate::params::ParameterSet;
+use serde::{Deserialize, Serialize};
+
+/// Evaluates gates on plain `bool`s, for testing and debugging circuits
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+pub struct Cleartext;
+
+impl BooleanBackend for Cleartext {
+    type Ciphertext = bool;
+
+    fn and(&self, a: &bool, b: &bool) -> bool {
+        a & b
+    }
+
+    fn xor(&self, a: &bool, b: &bool) -> bool {
+        a ^ b
+    }
+
+    fn not(&self, a: &bool) -> bool {
+        !a
+    }
+
+    fn trivial(&self, value: bool) -> bool {
+        value
+    }
+
+    fn or(&self, a: &bool, b: &bool) -> bool {
+        a | b
+    }
+
+    fn nand(&self, a: &bool, b: &bool) -> bool {
+        !(a & b)
+    }
+
+    fn nor(&self, a: &bool, b: &bool) -> bool {
+        !(a | b)
+    }
+
+    fn xnor(&self, a: &bool, b: &bool) -> bool {
+        !(a ^ b)
+    }
+
+    fn mux(&self, select: &bool, if_true: &bool, if_false: &bool) -> bool {
+        if *select { *if_true } else { *if_false }
+    }
+}
+
+/// Inputs travel in the clear, so the client key is the backend itself
+impl ClientBackend for Cleartext {
+    type ClientKey = Cleartext;
+
+    fn gen_keys(_: &ParameterSet) -> (Cleartext, Cleartext) {
+        (Cleartext, Cleartext)
+    }
+
+    fn encrypt(_: &Cleartext, value: bool) -> bool {
+        value
+    }
+
+    fn decrypt(_: &Cleartext, ciphertext: &bool) -> bool {
+        *ciphertext
+    }
+}
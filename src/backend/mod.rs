@@ -0,0 +1,189 @@
+//! Boolean evaluation backends for the gate engine
+//!
+//! A [`BooleanBackend`] provides the gate operations the scheduler needs, so the
+//! same circuit evaluation code can run on tfhe boolean ciphertexts, tfhe shortint
+//! blocks, cleartext booleans or a gate-counting tracer.
+
+mod boolean;
+mod cleartext;
+mod shortint;
+mod tracer;
+
+use crate::params::ParameterSet;
+
+pub use cleartext::Cleartext;
+pub use tracer::{GateCounts, Tracer};
+
+/// Common interface for boolean gate evaluation
+pub trait BooleanBackend: Send + Sync {
+    /// Value carried on a single wire
+    type Ciphertext: Clone + Send + Sync;
+
+    /// Computes `a AND b`
+    fn and(&self, a: &Self::Ciphertext, b: &Self::Ciphertext) -> Self::Ciphertext;
+
+    /// Computes `a XOR b`
+    fn xor(&self, a: &Self::Ciphertext, b: &Self::Ciphertext) -> Self::Ciphertext;
+
+    /// Computes `NOT a`
+    fn not(&self, a: &Self::Ciphertext) -> Self::Ciphertext;
+
+    /// Creates a trivial (unencrypted) wire value
+    fn trivial(&self, value: bool) -> Self::Ciphertext;
+
+    /// Copies a wire value
+    fn clone_ciphertext(&self, a: &Self::Ciphertext) -> Self::Ciphertext {
+        a.clone()
+    }
+
+    /// XORs the public `bits` into `a`, pairwise up to the shorter of the two: a NOT
+    /// where the bit is set and a copy elsewhere, so no gate mixes in public data
+    fn xor_public(&self, a: &[Self::Ciphertext], bits: &[bool]) -> Vec<Self::Ciphertext> {
+        a.iter()
+            .zip(bits)
+            .map(|(a, &bit)| match bit {
+                true => self.not(a),
+                false => self.clone_ciphertext(a),
+            })
+            .collect()
+    }
+
+    /// Computes `a OR b`, built from AND and XOR unless the backend has a native gate
+    fn or(&self, a: &Self::Ciphertext, b: &Self::Ciphertext) -> Self::Ciphertext {
+        let a_xor_b = self.xor(a, b);
+        let a_and_b = self.and(a, b);
+        self.xor(&a_xor_b, &a_and_b)
+    }
+
+    /// Computes `NOT (a AND b)`
+    fn nand(&self, a: &Self::Ciphertext, b: &Self::Ciphertext) -> Self::Ciphertext {
+        self.not(&self.and(a, b))
+    }
+
+    /// Computes `NOT (a OR b)`
+    fn nor(&self, a: &Self::Ciphertext, b: &Self::Ciphertext) -> Self::Ciphertext {
+        self.not(&self.or(a, b))
+    }
+
+    /// Computes `NOT (a XOR b)`
+    fn xnor(&self, a: &Self::Ciphertext, b: &Self::Ciphertext) -> Self::Ciphertext {
+        self.not(&self.xor(a, b))
+    }
+
+    /// Computes `if select { if_true } else { if_false }`, built from AND and XOR unless
+    /// the backend has a native gate
+    fn mux(
+        &self,
+        select: &Self::Ciphertext,
+        if_true: &Self::Ciphertext,
+        if_false: &Self::Ciphertext,
+    ) -> Self::Ciphertext {
+        let diff = self.xor(if_true, if_false);
+        self.xor(if_false, &self.and(select, &diff))
+    }
+}
+
+/// A backend whose wire values are encrypted under a client key, as used by the
+/// `client::Client` of the evaluation server
+pub trait ClientBackend: BooleanBackend + Sized {
+    /// Key encrypting inputs for the backend and decrypting its outputs
+    type ClientKey;
+
+    /// Generates a client key and the matching backend for `parameter_set`
+    fn gen_keys(parameter_set: &ParameterSet) -> (Self::ClientKey, Self);
+
+    fn encrypt(client_key: &Self::ClientKey, value: bool) -> Self::Ciphertext;
+
+    fn decrypt(client_key: &Self::ClientKey, ciphertext: &Self::Ciphertext) -> bool;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fhe_aes::BoolFheAes;
+    use crate::circuit::{bytes_to_wires, wires_to_bytes, Circuit};
+
+    const KEY: [u8; 16] = [
+        0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e,
+        0x0f,
+    ];
+    const PLAINTEXT: [u8; 16] = [
+        0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xaa, 0xbb, 0xcc, 0xdd, 0xee,
+        0xff,
+    ];
+    const CIPHERTEXT: [u8; 16] = [
+        0x69, 0xc4, 0xe0, 0xd8, 0x6a, 0x7b, 0x04, 0x30, 0xd8, 0xcd, 0xb7, 0x80, 0x70, 0xb4, 0xc5,
+        0x5a,
+    ];
+
+    fn to_array(bytes: &[u8]) -> [bool; 128] {
+        bytes_to_wires(bytes).try_into().unwrap()
+    }
+
+    #[test]
+    fn test_cleartext_backend_fips197() {
+        let fhe_aes = BoolFheAes::new(Cleartext);
+        fhe_aes.expand_key(to_array(&KEY));
+        let output = fhe_aes.execute(to_array(&PLAINTEXT));
+        assert_eq!(wires_to_bytes(&output), CIPHERTEXT);
+    }
+
+    #[test]
+    fn test_tracer_counts_gates() {
+        let fhe_aes = BoolFheAes::new(Tracer::new(Cleartext));
+        fhe_aes.expand_key(to_array(&KEY));
+        let key_counts = fhe_aes.backend().counts();
+        assert!(key_counts.and > 0);
+
+        fhe_aes.backend().reset();
+        let output = fhe_aes.execute(to_array(&PLAINTEXT));
+        assert_eq!(wires_to_bytes(&output), CIPHERTEXT);
+
+        // The block phase evaluates exactly the gates that are not key-reachable and
+        // key expansion evaluates the rest
+        let stats = Circuit::aes_128().stats();
+        let block_counts = fhe_aes.backend().counts();
+        assert_eq!(block_counts.bootstraps(), stats.block_bootstraps);
+        let binary_gates = stats.and_gates
+            + stats.xor_gates
+            + stats.mand_ands
+            + stats.mxor_xors
+            + stats.or_gates
+            + stats.nand_gates
+            + stats.nor_gates
+            + stats.xnor_gates
+            + 2 * stats.mux_gates;
+        assert_eq!(key_counts.bootstraps() + block_counts.bootstraps(), binary_gates);
+    }
+
+    #[test]
+    fn test_default_gates() {
+        // Default methods built from AND, XOR and NOT
+        struct Minimal;
+        impl BooleanBackend for Minimal {
+            type Ciphertext = bool;
+            fn and(&self, a: &bool, b: &bool) -> bool {
+                a & b
+            }
+            fn xor(&self, a: &bool, b: &bool) -> bool {
+                a ^ b
+            }
+            fn not(&self, a: &bool) -> bool {
+                !a
+            }
+            fn trivial(&self, value: bool) -> bool {
+                value
+            }
+        }
+
+        for (a, b, c) in (0..8).map(|i| (i & 1 == 1, i & 2 == 2, i & 4 == 4)) {
+            assert_eq!(Minimal.or(&a, &b), a | b);
+            assert_eq!(Minimal.nand(&a, &b), !(a & b));
+            assert_eq!(Minimal.nor(&a, &b), !(a | b));
+            assert_eq!(Minimal.xnor(&a, &b), !(a ^ b));
+            assert_eq!(Minimal.mux(&a, &b, &c), if a { b } else { c });
+        }
+        let bits = [false, true, true, false];
+        assert_eq!(Minimal.xor_public(&bits, &[true, true, false]), [true, false, true]);
+    }
+}
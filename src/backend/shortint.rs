@@ -0,0 +1,28 @@
+//! tfhe shortint backend with booleans encoded as 0/1 message blocks
+
+use super::BooleanBackend;
+use tfhe::shortint::{Ciphertext, ServerKey};
+
+impl BooleanBackend for ServerKey {
+    type Ciphertext = Ciphertext;
+
+    fn and(&self, a: &Ciphertext, b: &Ciphertext) -> Ciphertext {
+        self.bitand(a, b)
+    }
+
+    fn xor(&self, a: &Ciphertext, b: &Ciphertext) -> Ciphertext {
+        self.bitxor(a, b)
+    }
+
+    fn not(&self, a: &Ciphertext) -> Ciphertext {
+        self.scalar_bitxor(a, 1)
+    }
+
+    fn trivial(&self, value: bool) -> Ciphertext {
+        self.create_trivial(value as u64)
+    }
+
+    fn or(&self, a: &Ciphertext, b: &Ciphertext) -> Ciphertext {
+        self.bitor(a, b)
+    }
+}
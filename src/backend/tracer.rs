@@ -0,0 +1,158 @@
+//! Gate-counting tracer backend
+
+use super::{BooleanBackend, Cleartext};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Number of gate operations recorded by a [`Tracer`]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct GateCounts {
+    pub and: usize,
+    pub xor: usize,
+    pub or: usize,
+    pub nand: usize,
+    pub nor: usize,
+    pub xnor: usize,
+    pub mux: usize,
+    pub not: usize,
+    pub trivial: usize,
+    pub clone: usize,
+}
+
+impl GateCounts {
+    /// Number of bootstraps under the tfhe boolean backend; a MUX needs two
+    pub fn bootstraps(&self) -> usize {
+        self.and + self.xor + self.or + self.nand + self.nor + self.xnor + 2 * self.mux
+    }
+}
+
+/// Wraps another backend and counts every gate operation forwarded to it
+#[derive(Debug, Default)]
+pub struct Tracer<B = Cleartext> {
+    inner: B,
+    and: AtomicUsize,
+    xor: AtomicUsize,
+    or: AtomicUsize,
+    nand: AtomicUsize,
+    nor: AtomicUsize,
+    xnor: AtomicUsize,
+    mux: AtomicUsize,
+    not: AtomicUsize,
+    trivial: AtomicUsize,
+    clone: AtomicUsize,
+}
+
+impl<B> Tracer<B> {
+    pub fn new(inner: B) -> Self {
+        Self {
+            inner,
+            and: AtomicUsize::new(0),
+            xor: AtomicUsize::new(0),
+            or: AtomicUsize::new(0),
+            nand: AtomicUsize::new(0),
+            nor: AtomicUsize::new(0),
+            xnor: AtomicUsize::new(0),
+            mux: AtomicUsize::new(0),
+            not: AtomicUsize::new(0),
+            trivial: AtomicUsize::new(0),
+            clone: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns the wrapped backend
+    pub fn inner(&self) -> &B {
+        &self.inner
+    }
+
+    /// Returns the operations counted so far
+    pub fn counts(&self) -> GateCounts {
+        GateCounts {
+            and: self.and.load(Ordering::Relaxed),
+            xor: self.xor.load(Ordering::Relaxed),
+            or: self.or.load(Ordering::Relaxed),
+            nand: self.nand.load(Ordering::Relaxed),
+            nor: self.nor.load(Ordering::Relaxed),
+            xnor: self.xnor.load(Ordering::Relaxed),
+            mux: self.mux.load(Ordering::Relaxed),
+            not: self.not.load(Ordering::Relaxed),
+            trivial: self.trivial.load(Ordering::Relaxed),
+            clone: self.clone.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Resets all counters to zero
+    pub fn reset(&self) {
+        for counter in [
+            &self.and,
+            &self.xor,
+            &self.or,
+            &self.nand,
+            &self.nor,
+            &self.xnor,
+            &self.mux,
+            &self.not,
+            &self.trivial,
+            &self.clone,
+        ] {
+            counter.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+impl<B: BooleanBackend> BooleanBackend for Tracer<B> {
+    type Ciphertext = B::Ciphertext;
+
+    fn and(&self, a: &B::Ciphertext, b: &B::Ciphertext) -> B::Ciphertext {
+        self.and.fetch_add(1, Ordering::Relaxed);
+        self.inner.and(a, b)
+    }
+
+    fn xor(&self, a: &B::Ciphertext, b: &B::Ciphertext) -> B::Ciphertext {
+        self.xor.fetch_add(1, Ordering::Relaxed);
+        self.inner.xor(a, b)
+    }
+
+    fn not(&self, a: &B::Ciphertext) -> B::Ciphertext {
+        self.not.fetch_add(1, Ordering::Relaxed);
+        self.inner.not(a)
+    }
+
+    fn trivial(&self, value: bool) -> B::Ciphertext {
+        self.trivial.fetch_add(1, Ordering::Relaxed);
+        self.inner.trivial(value)
+    }
+
+    fn clone_ciphertext(&self, a: &B::Ciphertext) -> B::Ciphertext {
+        self.clone.fetch_add(1, Ordering::Relaxed);
+        self.inner.clone_ciphertext(a)
+    }
+
+    fn or(&self, a: &B::Ciphertext, b: &B::Ciphertext) -> B::Ciphertext {
+        self.or.fetch_add(1, Ordering::Relaxed);
+        self.inner.or(a, b)
+    }
+
+    fn nand(&self, a: &B::Ciphertext, b: &B::Ciphertext) -> B::Ciphertext {
+        self.nand.fetch_add(1, Ordering::Relaxed);
+        self.inner.nand(a, b)
+    }
+
+    fn nor(&self, a: &B::Ciphertext, b: &B::Ciphertext) -> B::Ciphertext {
+        self.nor.fetch_add(1, Ordering::Relaxed);
+        self.inner.nor(a, b)
+    }
+
+    fn xnor(&self, a: &B::Ciphertext, b: &B::Ciphertext) -> B::Ciphertext {
+        self.xnor.fetch_add(1, Ordering::Relaxed);
+        self.inner.xnor(a, b)
+    }
+
+    fn mux(
+        &self,
+        select: &B::Ciphertext,
+        if_true: &B::Ciphertext,
+        if_false: &B::Ciphertext,
+    ) -> B::Ciphertext {
+        self.mux.fetch_add(1, Ordering::Relaxed);
+        self.inner.mux(select, if_true, if_false)
+    }
+}
@@ -0,0 +1,421 @@
+//! Programmatic construction of boolean circuits
+//!
+//! Words are slices of wires, least significant bit first, as elsewhere in the crate.
+
+use super::{Circuit, Gate};
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+
+/// Builds a `Circuit` gate by gate.
+///
+/// Inputs are declared first and occupy the first wires. Gates get a fresh wire per
+/// output, and `build` moves the declared outputs to the last wires, so the result
+/// has the layout `CircuitParser` produces and runs directly in the evaluator.
+#[derive(Debug, Default)]
+pub struct CircuitBuilder {
+    gates: Vec<Gate>,
+    next_wire: u32,
+    inputs: Vec<u32>,
+    outputs: Vec<Vec<u32>>,
+    constants: [Option<u32>; 2],
+    /// Wires of the key input, if one was declared
+    key_wires: Range<u32>,
+}
+
+impl CircuitBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Declares an input of `width` bits and returns its wires.
+    /// Panics if gates were already added.
+    pub fn input(&mut self, width: u32) -> Vec<u32> {
+        assert!(self.gates.is_empty(), "Inputs must be declared before any gate");
+        self.inputs.push(width);
+        (0..width).map(|_| self.fresh()).collect()
+    }
+
+    /// Declares the AES key input of `width` bits and returns its wires. Gates
+    /// depending only on the key are key expansion, evaluated once per key.
+    /// Panics if other inputs were already declared.
+    pub fn key_input(&mut self, width: u32) -> Vec<u32> {
+        assert!(self.inputs.is_empty(), "The key must be the first input");
+        let wires = self.input(width);
+        self.key_wires = 0..width;
+        wires
+    }
+
+    /// Declares `word` as the next output of the circuit
+    pub fn output(&mut self, word: &[u32]) {
+        self.outputs.push(word.to_vec());
+    }
+
+    fn fresh(&mut self) -> u32 {
+        self.next_wire += 1;
+        self.next_wire - 1
+    }
+
+    pub fn and(&mut self, a: u32, b: u32) -> u32 {
+        let output = self.fresh();
+        self.gates.push(Gate::And { input1: a, input2: b, output });
+        output
+    }
+
+    pub fn xor(&mut self, a: u32, b: u32) -> u32 {
+        let output = self.fresh();
+        self.gates.push(Gate::Xor { input1: a, input2: b, output });
+        output
+    }
+
+    pub fn not(&mut self, a: u32) -> u32 {
+        let output = self.fresh();
+        self.gates.push(Gate::Inv { input: a, output });
+        output
+    }
+
+    pub fn or(&mut self, a: u32, b: u32) -> u32 {
+        let output = self.fresh();
+        self.gates.push(Gate::Or { input1: a, input2: b, output });
+        output
+    }
+
+    pub fn nand(&mut self, a: u32, b: u32) -> u32 {
+        let output = self.fresh();
+        self.gates.push(Gate::Nand { input1: a, input2: b, output });
+        output
+    }
+
+    pub fn nor(&mut self, a: u32, b: u32) -> u32 {
+        let output = self.fresh();
+        self.gates.push(Gate::Nor { input1: a, input2: b, output });
+        output
+    }
+
+    pub fn xnor(&mut self, a: u32, b: u32) -> u32 {
+        let output = self.fresh();
+        self.gates.push(Gate::Xnor { input1: a, input2: b, output });
+        output
+    }
+
+    /// `if select { if_true } else { if_false }` as a single MUX gate (two bootstraps,
+    /// against three for the AND/XOR form)
+    pub fn mux(&mut self, select: u32, if_true: u32, if_false: u32) -> u32 {
+        let output = self.fresh();
+        self.gates.push(Gate::Mux { select, if_true, if_false, output });
+        output
+    }
+
+    fn copy(&mut self, a: u32) -> u32 {
+        let output = self.fresh();
+        self.gates.push(Gate::Eqw { input: a, output });
+        output
+    }
+
+    /// Returns a wire holding `value`, derived from the first input wire.
+    /// Panics if no input was declared.
+    pub fn constant(&mut self, value: bool) -> u32 {
+        if let Some(wire) = self.constants[value as usize] {
+            return wire;
+        }
+        assert!(!self.inputs.is_empty(), "Constants need at least one input wire");
+        let wire = match value {
+            false => self.xor(0, 0),
+            true => {
+                let zero = self.constant(false);
+                self.not(zero)
+            }
+        };
+        self.constants[value as usize] = Some(wire);
+        wire
+    }
+
+    /// Returns `width` wires holding `value`
+    pub fn constant_word(&mut self, value: u128, width: usize) -> Vec<u32> {
+        (0..width).map(|bit| self.constant(value >> bit & 1 == 1)).collect()
+    }
+
+    pub fn xor_word(&mut self, a: &[u32], b: &[u32]) -> Vec<u32> {
+        assert_eq!(a.len(), b.len(), "Word widths differ");
+        a.iter().zip(b).map(|(a, b)| self.xor(*a, *b)).collect()
+    }
+
+    pub fn and_word(&mut self, a: &[u32], b: &[u32]) -> Vec<u32> {
+        assert_eq!(a.len(), b.len(), "Word widths differ");
+        a.iter().zip(b).map(|(a, b)| self.and(*a, *b)).collect()
+    }
+
+    pub fn not_word(&mut self, a: &[u32]) -> Vec<u32> {
+        a.iter().map(|a| self.not(*a)).collect()
+    }
+
+    /// Selects `if_true` or `if_false` word by word
+    pub fn mux_word(&mut self, select: u32, if_true: &[u32], if_false: &[u32]) -> Vec<u32> {
+        assert_eq!(if_true.len(), if_false.len(), "Word widths differ");
+        if_true.iter().zip(if_false).map(|(t, f)| self.mux(select, *t, *f)).collect()
+    }
+
+    /// Ripple-carry addition modulo `2^n`, one AND per bit
+    pub fn add(&mut self, a: &[u32], b: &[u32]) -> Vec<u32> {
+        assert_eq!(a.len(), b.len(), "Word widths differ");
+        let width = a.len();
+        let mut sum = Vec::with_capacity(width);
+        let mut carry = None;
+
+        for (i, (a, b)) in a.iter().zip(b).enumerate() {
+            let last = i == width - 1;
+            match carry {
+                None => {
+                    sum.push(self.xor(*a, *b));
+                    if !last {
+                        carry = Some(self.and(*a, *b));
+                    }
+                }
+                // carry' = carry ^ ((a ^ carry) & (b ^ carry))
+                Some(c) => {
+                    let a_c = self.xor(*a, c);
+                    let b_c = self.xor(*b, c);
+                    sum.push(self.xor(a_c, *b));
+                    if !last {
+                        let generate = self.and(a_c, b_c);
+                        carry = Some(self.xor(c, generate));
+                    }
+                }
+            }
+        }
+        sum
+    }
+
+    /// Adds one modulo `2^n`, one AND per bit
+    pub fn increment(&mut self, a: &[u32]) -> Vec<u32> {
+        let mut sum = Vec::with_capacity(a.len());
+        let mut carry: Option<u32> = None;
+
+        for (i, bit) in a.iter().enumerate() {
+            match carry {
+                None => {
+                    sum.push(self.not(*bit));
+                    carry = Some(*bit);
+                }
+                Some(c) => {
+                    sum.push(self.xor(*bit, c));
+                    if i < a.len() - 1 {
+                        carry = Some(self.and(*bit, c));
+                    }
+                }
+            }
+        }
+        sum
+    }
+
+    /// Returns a wire that is set if the words are equal
+    pub fn equal(&mut self, a: &[u32], b: &[u32]) -> u32 {
+        assert_eq!(a.len(), b.len(), "Word widths differ");
+        let same: Vec<u32> = a.iter().zip(b).map(|(a, b)| self.xnor(*a, *b)).collect();
+        let (first, rest) = same.split_first().expect("Cannot compare empty words");
+        rest.iter().fold(*first, |acc, bit| self.and(acc, *bit))
+    }
+
+    /// Returns a wire that is set if `a < b` as unsigned integers
+    pub fn less_than(&mut self, a: &[u32], b: &[u32]) -> u32 {
+        assert_eq!(a.len(), b.len(), "Word widths differ");
+        let mut less = self.constant(false);
+        // From the least significant bit: where the bits differ, b decides
+        for (a, b) in a.iter().zip(b) {
+            let differ = self.xor(*a, *b);
+            less = self.mux(differ, *b, less);
+        }
+        less
+    }
+
+    /// Rotates towards the most significant bit; only moves wires
+    pub fn rotate_left(&self, a: &[u32], n: usize) -> Vec<u32> {
+        let mut rotated = a.to_vec();
+        rotated.rotate_right(n % a.len().max(1));
+        rotated
+    }
+
+    /// Rotates towards the least significant bit; only moves wires
+    pub fn rotate_right(&self, a: &[u32], n: usize) -> Vec<u32> {
+        let mut rotated = a.to_vec();
+        rotated.rotate_left(n % a.len().max(1));
+        rotated
+    }
+
+    /// Shifts towards the most significant bit, filling with zeros
+    pub fn shift_left(&mut self, a: &[u32], n: usize) -> Vec<u32> {
+        let n = n.min(a.len());
+        let mut shifted = vec![self.constant(false); n];
+        shifted.extend_from_slice(&a[..a.len() - n]);
+        shifted
+    }
+
+    /// Shifts towards the least significant bit, filling with zeros
+    pub fn shift_right(&mut self, a: &[u32], n: usize) -> Vec<u32> {
+        let n = n.min(a.len());
+        let mut shifted = a[n..].to_vec();
+        shifted.extend(vec![self.constant(false); n]);
+        shifted
+    }
+
+    /// Finishes the circuit: the declared outputs move to the last wires, in order.
+    /// Outputs that are input wires or repeat another output are copied with an EQW.
+    pub fn build(mut self) -> Circuit {
+        let input_count: u32 = self.inputs.iter().sum();
+        let output_widths = self.outputs.iter().map(|word| word.len() as u32).collect();
+        let mut seen = HashSet::new();
+        let mut output_wires = Vec::new();
+        for wire in std::mem::take(&mut self.outputs).concat() {
+            if wire < input_count || !seen.insert(wire) {
+                let copy = self.copy(wire);
+                seen.insert(copy);
+                output_wires.push(copy);
+            } else {
+                output_wires.push(wire);
+            }
+        }
+
+        let internal: Vec<u32> = self
+            .gates
+            .iter()
+            .flat_map(|gate| gate.wires())
+            .map(|(_, output)| output)
+            .filter(|output| !seen.contains(output))
+            .collect();
+        let output_start = input_count + internal.len() as u32;
+        let output_end = output_start + output_wires.len() as u32;
+
+        let mut mapping: HashMap<u32, u32> = (0..input_count).map(|wire| (wire, wire)).collect();
+        for (i, wire) in internal.iter().enumerate() {
+            mapping.insert(*wire, input_count + i as u32);
+        }
+        for (i, wire) in output_wires.iter().enumerate() {
+            mapping.insert(*wire, output_start + i as u32);
+        }
+
+        let gates = self
+            .gates
+            .iter()
+            .map(|gate| gate.map_wires(|wire| mapping[&wire]))
+            .collect();
+        Circuit::from_gates(gates, self.key_wires, self.inputs, output_widths, output_end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bits(value: u64, width: usize) -> Vec<bool> {
+        (0..width).map(|bit| value >> bit & 1 == 1).collect()
+    }
+
+    fn value(bits: &[bool]) -> u64 {
+        bits.iter().enumerate().fold(0, |acc, (bit, set)| acc | (*set as u64) << bit)
+    }
+
+    /// Builds a circuit over two 8-bit inputs and checks it on every input pair
+    fn check_binary(
+        build: impl Fn(&mut CircuitBuilder, &[u32], &[u32]) -> Vec<u32>,
+        expected: impl Fn(u64, u64) -> u64,
+    ) {
+        let mut builder = CircuitBuilder::new();
+        let (a, b) = (builder.input(8), builder.input(8));
+        let output = build(&mut builder, &a, &b);
+        builder.output(&output);
+        let circuit = builder.build();
+
+        for x in 0..256 {
+            for y in 0..256 {
+                let mut inputs = bits(x, 8);
+                inputs.extend(bits(y, 8));
+                let output = value(&circuit.evaluate_outputs(&inputs));
+                assert_eq!(output, expected(x, y), "inputs {} and {}", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn test_gates() {
+        check_binary(|builder, a, b| vec![builder.or(a[0], b[0])], |x, y| (x | y) & 1);
+        check_binary(|builder, a, b| vec![builder.nand(a[0], b[0])], |x, y| !(x & y) & 1);
+        check_binary(|builder, a, b| vec![builder.nor(a[0], b[0])], |x, y| !(x | y) & 1);
+        check_binary(|builder, a, b| vec![builder.xnor(a[0], b[0])], |x, y| !(x ^ y) & 1);
+        check_binary(
+            |builder, a, b| builder.mux_word(a[0], &a[1..], &b[1..]),
+            |x, y| if x & 1 == 1 { x >> 1 } else { y >> 1 },
+        );
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        check_binary(|builder, a, b| builder.add(a, b), |x, y| (x + y) % 256);
+        check_binary(|builder, a, _| builder.increment(a), |x, _| (x + 1) % 256);
+        check_binary(|builder, a, b| vec![builder.equal(a, b)], |x, y| (x == y) as u64);
+        check_binary(|builder, a, b| vec![builder.less_than(a, b)], |x, y| (x < y) as u64);
+    }
+
+    #[test]
+    fn test_rotate_and_shift() {
+        check_binary(|builder, a, _| builder.rotate_left(a, 3), |x, _| {
+            (x as u8).rotate_left(3) as u64
+        });
+        check_binary(|builder, a, _| builder.rotate_right(a, 3), |x, _| {
+            (x as u8).rotate_right(3) as u64
+        });
+        check_binary(|builder, a, _| builder.shift_left(a, 3), |x, _| (x << 3) % 256);
+        check_binary(|builder, a, _| builder.shift_right(a, 3), |x, _| x >> 3);
+    }
+
+    #[test]
+    fn test_build_layout() {
+        let mut builder = CircuitBuilder::new();
+        let a = builder.input(4);
+        let b = builder.input(4);
+        let sum = builder.add(&a, &b);
+        // An input and a repeated wire as outputs are copied
+        builder.output(&sum);
+        builder.output(&[a[0], sum[0]]);
+        let circuit = builder.build();
+
+        assert_eq!(circuit.inputs, vec![4, 4]);
+        assert_eq!(circuit.outputs, vec![4, 2]);
+        let written: Vec<u32> = circuit
+            .gates
+            .iter()
+            .flat_map(|gate| gate.wires())
+            .map(|(_, output)| output)
+            .collect();
+        assert!(circuit.output_wires().all(|wire| written.contains(&wire)));
+
+        let mut inputs = bits(5, 4);
+        inputs.extend(bits(6, 4));
+        assert_eq!(circuit.evaluate_outputs(&inputs), [bits(11, 4), vec![true, true]].concat());
+
+        let reparsed = crate::circuit::CircuitParser::parse(&circuit.to_bristol());
+        assert_eq!(reparsed.evaluate_outputs(&inputs), circuit.evaluate_outputs(&inputs));
+    }
+
+    #[test]
+    fn test_key_input() {
+        let build = |key: bool| {
+            let mut builder = CircuitBuilder::new();
+            let a = if key { builder.key_input(2) } else { builder.input(2) };
+            let b = builder.input(2);
+            let key_gate = builder.xor(a[0], a[1]);
+            let one = builder.constant(true);
+            let mixed = builder.and(key_gate, b[0]);
+            builder.output(&[mixed, one]);
+            builder.build()
+        };
+
+        // Without a key input nothing is key expansion, constants included
+        let circuit = build(false);
+        assert!(circuit.key_expand_reachable.is_empty() && circuit.key_wires().is_empty());
+        assert_eq!(circuit.stats().key_reachable_gates, 0);
+
+        let circuit = build(true);
+        assert_eq!(circuit.key_wires(), 0..2);
+        assert!(circuit.stats().key_reachable_gates > 0);
+        assert!(!circuit.key_expand_reachable.contains(&circuit.output_wires().start));
+    }
+}
@@ -0,0 +1,137 @@
+//! Multiplicative depth and AES round attribution for circuit gates
+
+use super::{Circuit, Gate};
+use crate::utils::constants::{AES_128_KEY_SIZE_BITS, AES_BLOCK_SIZE_BITS};
+
+impl Circuit {
+    /// Returns the multiplicative (AND) depth of every wire, indexed by wire number
+    pub fn and_depths(&self) -> Vec<u32> {
+        and_depths(&self.gates, self.output_end)
+    }
+
+    /// Returns the AES round each gate belongs to, indexed by gate position
+    pub fn gate_rounds(&self) -> Vec<u32> {
+        gate_rounds(&self.gates, self.output_end)
+    }
+}
+
+/// Computes the AND depth of every wire, counting every non-linear gate as an AND;
+/// input wires have depth 0
+pub(crate) fn and_depths(gates: &[Gate], wire_count: u32) -> Vec<u32> {
+    let mut depths = vec![0; wire_count as usize];
+
+    for gate in gates {
+        let cost = u32::from(!gate.is_linear());
+        for (inputs, output) in gate.wires() {
+            depths[output as usize] =
+                inputs.iter().map(|wire| depths[*wire as usize]).max().unwrap_or(0) + cost;
+        }
+    }
+
+    depths
+}
+
+/// Computes the dependency level of every wire counting every gate; input wires
+/// are at level 0
+pub(crate) fn wire_levels(gates: &[Gate], wire_count: u32) -> Vec<u32> {
+    let mut levels = vec![0; wire_count as usize];
+
+    for gate in gates {
+        for (inputs, output) in gate.wires() {
+            levels[output as usize] =
+                inputs.iter().map(|wire| levels[*wire as usize]).max().unwrap_or(0) + 1;
+        }
+    }
+
+    levels
+}
+
+/// Attributes each gate to an AES round from the wire ranges of the AES-128
+/// layout: round keys derive from the key wires `0..128` only and the state from
+/// the block wires `128..256`.
+///
+/// An XOR of a round-key wire into a state wire is an AddRoundKey and closes its
+/// round, so the initial AddRoundKey is round 0 and round `r` ends with the
+/// AddRoundKey of round key `r`. Key schedule gates belong to the earliest round
+/// using their output.
+pub(crate) fn gate_rounds(gates: &[Gate], wire_count: u32) -> Vec<u32> {
+    let key_end = AES_128_KEY_SIZE_BITS;
+    let block_end = key_end + AES_BLOCK_SIZE_BITS;
+    let mut key_wires = vec![false; wire_count as usize];
+    key_wires[..key_end].fill(true);
+    // Round of every state wire, and whether an AddRoundKey closed it
+    let mut state_rounds: Vec<Option<(u32, bool)>> = vec![None; wire_count as usize];
+    state_rounds[key_end..block_end].fill(Some((0, false)));
+
+    let mut rounds = vec![0; gates.len()];
+    for (i, gate) in gates.iter().enumerate() {
+        for (inputs, output) in gate.wires() {
+            if inputs.iter().all(|wire| key_wires[*wire as usize]) {
+                key_wires[output as usize] = true;
+                continue;
+            }
+            let round = inputs
+                .iter()
+                .filter_map(|wire| state_rounds[*wire as usize])
+                .map(|(round, closed)| round + u32::from(closed))
+                .max()
+                .unwrap_or(0);
+            let add_round_key = matches!(gate, Gate::Xor { .. } | Gate::Mxor { .. })
+                && inputs.iter().any(|wire| key_wires[*wire as usize]);
+            state_rounds[output as usize] = Some((round, add_round_key));
+            rounds[i] = rounds[i].max(round);
+        }
+    }
+
+    // Earliest round reading each key schedule wire, walking the gates backwards
+    let mut key_rounds: Vec<Option<u32>> = vec![None; wire_count as usize];
+    for (i, gate) in gates.iter().enumerate().rev() {
+        for (inputs, output) in gate.wires() {
+            let round = match state_rounds[output as usize] {
+                Some((round, _)) => round,
+                None => {
+                    let round = key_rounds[output as usize].unwrap_or(0);
+                    rounds[i] = rounds[i].max(round);
+                    round
+                }
+            };
+            for wire in inputs.iter().filter(|wire| key_wires[**wire as usize]) {
+                let used = &mut key_rounds[*wire as usize];
+                *used = Some(used.map_or(round, |used| used.min(round)));
+            }
+        }
+    }
+
+    rounds
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::constants::{AES_128_OUTPUT_BITSIZE, AES_128_ROUNDS, AND_DEPTH_PER_ROUND};
+
+    #[test]
+    fn test_aes_output_depth_and_rounds() {
+        let circuit = Circuit::aes_128();
+        let depths = circuit.and_depths();
+        let output_start = circuit.output_end as usize - AES_128_OUTPUT_BITSIZE;
+        assert!(depths[output_start..]
+            .iter()
+            .all(|d| *d == AND_DEPTH_PER_ROUND * AES_128_ROUNDS as u32));
+
+        let rounds = circuit.gate_rounds();
+        assert_eq!(rounds[0], 0);
+        assert_eq!(rounds.iter().max(), Some(&(AES_128_ROUNDS as u32)));
+        // The output block is written by the last AddRoundKey, and round 0 is the
+        // initial AddRoundKey alone on the state side
+        for (gate, round) in circuit.gates.iter().zip(&rounds) {
+            let wires = gate.wires();
+            if wires.iter().any(|(_, output)| *output as usize >= output_start) {
+                assert_eq!(*round, AES_128_ROUNDS as u32);
+            }
+            if *round == 0 && wires.iter().any(|(_, output)| !circuit.key_expand_reachable.contains(output)) {
+                assert!(matches!(gate, Gate::Xor { .. } | Gate::Mxor { .. }));
+            }
+        }
+    }
+}
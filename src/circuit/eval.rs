@@ -0,0 +1,199 @@
+//! Cleartext circuit evaluator operating on plain booleans
+
+use super::depth::wire_levels;
+use super::{Circuit, Gate};
+use crate::backend::{BooleanBackend, Cleartext};
+use crate::utils::constants::{AES_128_KEY_SIZE, AES_128_OUTPUT_BITSIZE, AES_BLOCK_SIZE};
+use bit_vec::BitVec;
+use rayon::prelude::*;
+
+impl Circuit {
+    /// Evaluates the circuit on cleartext `inputs`, assigned to wires `0..inputs.len()`.
+    ///
+    /// Returns the value of every wire indexed by wire number, so the result can be
+    /// compared wire by wire with a decrypted FHE evaluation. Wires that are never
+    /// written are left `false`.
+    pub fn evaluate(&self, inputs: &[bool]) -> Vec<bool> {
+        evaluate_gates(&self.gates, self.output_end, inputs)
+    }
+
+    /// Evaluates the circuit on cleartext `inputs` and returns the output wires
+    pub fn evaluate_outputs(&self, inputs: &[bool]) -> Vec<bool> {
+        let values = self.evaluate(inputs);
+        self.output_wires().map(|wire| values[wire as usize]).collect()
+    }
+
+    /// Evaluates the circuit on `backend`, with `inputs` assigned to wires
+    /// `0..inputs.len()`, and returns the output wires. Gates of the same level run
+    /// in parallel.
+    pub fn evaluate_on<B: BooleanBackend>(&self, backend: &B, inputs: &[B::Ciphertext]) -> Vec<B::Ciphertext> {
+        assert_eq!(inputs.len(), self.input_count() as usize, "Wrong number of inputs");
+        let wire_levels = wire_levels(&self.gates, self.output_end);
+        let mut levels: Vec<Vec<(&Gate, Vec<u32>, u32)>> = Vec::new();
+        for gate in &self.gates {
+            for (inputs, output) in gate.wires() {
+                let level = wire_levels[output as usize] as usize;
+                if levels.len() < level {
+                    levels.resize_with(level, Vec::new);
+                }
+                levels[level - 1].push((gate, inputs, output));
+            }
+        }
+
+        let mut values: Vec<Option<B::Ciphertext>> = vec![None; self.output_end as usize];
+        for (i, input) in inputs.iter().enumerate() {
+            values[i] = Some(input.clone());
+        }
+        for level in levels {
+            let outputs: Vec<(u32, B::Ciphertext)> = level
+                .par_iter()
+                .map(|(gate, inputs, output)| {
+                    let inputs: Vec<B::Ciphertext> = inputs
+                        .iter()
+                        .map(|wire| values[*wire as usize].clone().expect("Missing input value"))
+                        .collect();
+                    (*output, gate.apply(backend, &inputs))
+                })
+                .collect();
+            for (wire, value) in outputs {
+                values[wire as usize] = Some(value);
+            }
+        }
+
+        self.output_wires()
+            .map(|wire| values[wire as usize].take().expect("Output wire was never written"))
+            .collect()
+    }
+
+    /// Evaluates the AES-128 circuit on a cleartext `key` and `block`, using the same
+    /// wire layout as `BoolFheAes`, and returns the output block.
+    pub fn evaluate_aes(
+        &self,
+        key: &[u8; AES_128_KEY_SIZE],
+        block: &[u8; AES_BLOCK_SIZE],
+    ) -> [u8; AES_BLOCK_SIZE] {
+        let mut inputs = bytes_to_wires(key);
+        inputs.extend(bytes_to_wires(block));
+
+        let values = self.evaluate(&inputs);
+        let output_start = (self.output_end as usize) - AES_128_OUTPUT_BITSIZE;
+        wires_to_bytes(&values[output_start..self.output_end as usize])
+    }
+}
+
+/// Evaluates `gates` in order on cleartext `inputs`, returning all `wire_count` wire values
+pub(crate) fn evaluate_gates(gates: &[Gate], wire_count: u32, inputs: &[bool]) -> Vec<bool> {
+    let mut values: Vec<Option<bool>> = vec![None; wire_count as usize];
+    for (i, bit) in inputs.iter().enumerate() {
+        values[i] = Some(*bit);
+    }
+
+    for gate in gates {
+        evaluate_gate(gate, &mut values);
+    }
+
+    values.into_iter().map(|v| v.unwrap_or(false)).collect()
+}
+
+/// Evaluates a single gate, reading its inputs from and writing its outputs to `values`
+fn evaluate_gate(gate: &Gate, values: &mut [Option<bool>]) {
+    match gate {
+        Gate::And { input1, input2, output }
+        | Gate::Xor { input1, input2, output }
+        | Gate::Or { input1, input2, output }
+        | Gate::Nand { input1, input2, output }
+        | Gate::Nor { input1, input2, output }
+        | Gate::Xnor { input1, input2, output } => {
+            let inputs = [get_value(*input1, values), get_value(*input2, values)];
+            values[*output as usize] = Some(gate.apply(&Cleartext, &inputs));
+        }
+        Gate::Inv { input, output } | Gate::Eqw { input, output } => {
+            values[*output as usize] = Some(gate.apply(&Cleartext, &[get_value(*input, values)]));
+        }
+        Gate::Mux { select, if_true, if_false, output } => {
+            let inputs = [
+                get_value(*select, values),
+                get_value(*if_true, values),
+                get_value(*if_false, values),
+            ];
+            values[*output as usize] = Some(gate.apply(&Cleartext, &inputs));
+        }
+        Gate::Mand { gates } | Gate::Mxor { gates } => {
+            for (input1, input2, output) in gates {
+                let inputs = [get_value(*input1, values), get_value(*input2, values)];
+                values[*output as usize] = Some(gate.apply(&Cleartext, &inputs));
+            }
+        }
+        Gate::Minv { gates } => {
+            for (input, output) in gates {
+                values[*output as usize] = Some(gate.apply(&Cleartext, &[get_value(*input, values)]));
+            }
+        }
+    }
+}
+
+fn get_value(wire: u32, values: &[Option<bool>]) -> bool {
+    values[wire as usize].expect("Missing input value")
+}
+
+/// Converts bytes to wire values, least significant bit first (the order used by
+/// `BoolFheAes::encrypt_key` and `BoolFheAes::encrypt_iv`)
+pub fn bytes_to_wires(bytes: &[u8]) -> Vec<bool> {
+    BitVec::from_bytes(bytes).iter().rev().collect()
+}
+
+/// Converts wire values, least significant bit first, back to bytes
+pub fn wires_to_bytes(wires: &[bool]) -> [u8; AES_BLOCK_SIZE] {
+    let bits: BitVec = wires.iter().rev().copied().collect();
+    bits.to_bytes().try_into().unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encrypt_reference_aes128;
+    use rand::Rng;
+
+    #[test]
+    fn test_evaluate_aes_fips197() {
+        let circuit = Circuit::aes_128();
+        let key: [u8; 16] = core::array::from_fn(|i| i as u8);
+        let block = [
+            0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xaa, 0xbb, 0xcc, 0xdd,
+            0xee, 0xff,
+        ];
+        let expected = [
+            0x69, 0xc4, 0xe0, 0xd8, 0x6a, 0x7b, 0x04, 0x30, 0xd8, 0xcd, 0xb7, 0x80, 0x70, 0xb4,
+            0xc5, 0x5a,
+        ];
+        assert_eq!(circuit.evaluate_aes(&key, &block), expected);
+    }
+
+    #[test]
+    fn test_evaluate_aes_matches_reference() {
+        let circuit = Circuit::aes_128();
+        let mut rng = rand::thread_rng();
+
+        for _ in 0..8 {
+            let key: [u8; 16] = rng.gen();
+            let block: [u8; 16] = rng.gen();
+            let expected = encrypt_reference_aes128(vec![block], key);
+            assert_eq!(circuit.evaluate_aes(&key, &block).as_slice(), expected[0].as_slice());
+        }
+    }
+
+    #[test]
+    fn test_evaluate_on_backend_matches_cleartext() {
+        let circuit = Circuit::aes_128();
+        let mut rng = rand::thread_rng();
+        let inputs: Vec<bool> = (0..256).map(|_| rng.gen()).collect();
+        assert_eq!(circuit.evaluate_on(&Cleartext, &inputs), circuit.evaluate_outputs(&inputs));
+    }
+
+    #[test]
+    fn test_evaluate_returns_all_wires() {
+        let circuit = Circuit::aes_128();
+        let values = circuit.evaluate(&[false; 256]);
+        assert_eq!(values.len(), circuit.output_end as usize);
+    }
+}
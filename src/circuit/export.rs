@@ -0,0 +1,143 @@
+//! Graphviz DOT and JSON export of circuits
+
+use super::{depth, Circuit, Gate};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// How gates are grouped into clusters in DOT output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum DotClustering {
+    /// No clusters
+    #[default]
+    None,
+    /// One cluster per AES round, see `Circuit::gate_rounds`
+    Round,
+    /// One cluster per dependency level
+    Level,
+}
+
+/// JSON representation of a circuit: declarations plus the gate list
+#[derive(Serialize)]
+struct CircuitJson<'a> {
+    wires: u32,
+    inputs: &'a [u32],
+    outputs: &'a [u32],
+    gates: &'a [Gate],
+}
+
+impl Circuit {
+    /// Exports the circuit as a Graphviz DOT graph with one node per wire.
+    ///
+    /// Input wires are boxes, gate outputs are labelled with their opcode and the
+    /// circuit outputs are drawn with a double border.
+    pub fn to_dot(&self, clustering: DotClustering) -> String {
+        let mut dot = String::from("digraph circuit {\n    rankdir=LR;\n");
+
+        for wire in 0..self.input_count() {
+            writeln!(dot, "    w{} [shape=box, label=\"in {}\"];", wire, wire).unwrap();
+        }
+
+        let outputs = self.output_wires();
+        let node = |gate: &Gate, output: u32| {
+            let peripheries = if outputs.contains(&output) { 2 } else { 1 };
+            format!(
+                "w{} [label=\"{} {}\", peripheries={}];",
+                output,
+                gate.name(),
+                output,
+                peripheries
+            )
+        };
+
+        let clusters: Vec<u32> = match clustering {
+            DotClustering::None => vec![0; self.gates.len()],
+            DotClustering::Round => depth::gate_rounds(&self.gates, self.output_end),
+            DotClustering::Level => {
+                let levels = depth::wire_levels(&self.gates, self.output_end);
+                self.gates
+                    .iter()
+                    .map(|gate| gate.wires().iter().map(|(_, out)| levels[*out as usize]).max().unwrap_or(0))
+                    .collect()
+            }
+        };
+
+        let mut grouped: BTreeMap<u32, Vec<String>> = BTreeMap::new();
+        for (gate, cluster) in self.gates.iter().zip(clusters) {
+            for (_, output) in gate.wires() {
+                grouped.entry(cluster).or_default().push(node(gate, output));
+            }
+        }
+
+        let label = match clustering {
+            DotClustering::None => "",
+            DotClustering::Round => "round",
+            DotClustering::Level => "level",
+        };
+        for (cluster, nodes) in grouped {
+            if clustering == DotClustering::None {
+                for node in nodes {
+                    writeln!(dot, "    {}", node).unwrap();
+                }
+                continue;
+            }
+            writeln!(dot, "    subgraph cluster_{} {{", cluster).unwrap();
+            writeln!(dot, "        label=\"{} {}\";", label, cluster).unwrap();
+            for node in nodes {
+                writeln!(dot, "        {}", node).unwrap();
+            }
+            writeln!(dot, "    }}").unwrap();
+        }
+
+        for gate in &self.gates {
+            for (inputs, output) in gate.wires() {
+                for input in inputs {
+                    writeln!(dot, "    w{} -> w{};", input, output).unwrap();
+                }
+            }
+        }
+
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Exports the circuit as a JSON wire/gate list
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(&CircuitJson {
+            wires: self.output_end,
+            inputs: &self.inputs,
+            outputs: &self.outputs,
+            gates: &self.gates,
+        })
+        .expect("Circuit is serializable")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::circuit::CircuitParser;
+
+    const CIRCUIT: &str = "3 6\n2 2 1\n1 1\n\n2 1 0 1 3 AND\n1 1 3 4 INV\n2 1 4 2 5 XOR\n";
+
+    #[test]
+    fn test_dot_clustered_by_level() {
+        let dot = CircuitParser::parse(CIRCUIT).to_dot(DotClustering::Level);
+
+        assert!(dot.starts_with("digraph circuit {"));
+        assert!(dot.contains("subgraph cluster_3 {"));
+        assert!(dot.contains("w5 [label=\"XOR 5\", peripheries=2];"));
+        assert!(dot.contains("w0 -> w3;"));
+    }
+
+    #[test]
+    fn test_json_gate_list() {
+        let json: serde_json::Value =
+            serde_json::from_str(&CircuitParser::parse(CIRCUIT).to_json()).unwrap();
+
+        assert_eq!(json["wires"], 6);
+        assert_eq!(json["gates"][0]["op"], "AND");
+        assert_eq!(json["gates"][1]["input"], 3);
+        assert_eq!(json["gates"][2]["output"], 5);
+    }
+}
@@ -0,0 +1,39 @@
+//! Stable circuit fingerprint
+//!
+//! The fingerprint is the SHA-256 of the circuit written back in extended Bristol
+//! format, so it depends only on the parsed gates and wire layout, not on the
+//! spacing of the file it was read from.
+
+use super::Circuit;
+use sha2::{Digest, Sha256};
+
+impl Circuit {
+    /// Returns the hex-encoded SHA-256 of the canonical Bristol form of the circuit
+    pub fn fingerprint(&self) -> String {
+        hex::encode(Sha256::digest(self.to_bristol().as_bytes()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::circuit::{CircuitParser, Gate};
+    use crate::utils::constants::AES_128_CIRCUIT_FINGERPRINT;
+
+    #[test]
+    fn test_embedded_fingerprint() {
+        assert_eq!(Circuit::aes_128().fingerprint(), AES_128_CIRCUIT_FINGERPRINT);
+    }
+
+    #[test]
+    fn test_fingerprint_ignores_formatting() {
+        let text = "2 4\n1 2\n1 1\n\n2 1 0 1 2 AND\n1 1 2 3 INV\n";
+        let spaced = "2  4\n1 2 \n1 1\n\n\n2 1 0 1 2   AND\n\n1 1 2 3 INV\n";
+        let circuit = CircuitParser::parse(text);
+        assert_eq!(circuit.fingerprint(), CircuitParser::parse(spaced).fingerprint());
+
+        let mut changed = circuit.clone();
+        changed.gates[0] = Gate::Xor { input1: 0, input2: 1, output: 2 };
+        assert_ne!(changed.fingerprint(), circuit.fingerprint());
+    }
+}
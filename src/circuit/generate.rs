@@ -0,0 +1,454 @@
+//! Generator for AES-128 circuits built around a chosen S-box circuit
+//!
+//! Generated circuits use the wire layout of the embedded circuit: key on wires
+//! 0..128 and block on wires 128..256, least significant bit first, and the output
+//! block on the last 128 wires. Key expansion only depends on the key wires, so
+//! `BoolFheAes::expand_key` computes it once.
+
+use super::{Circuit, CircuitBuilder, Pass};
+use crate::utils::constants::{
+    AES_128_KEY_SIZE_BITS, AES_128_ROUNDS, AES_BLOCK_SIZE, AES_BLOCK_SIZE_BITS, RCON,
+};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// A byte as wires, least significant bit first
+type Byte = [u32; 8];
+
+/// Published S-box circuits
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SboxCircuit {
+    /// Boyar-Peralta, 32 ANDs and AND depth 6
+    BoyarPeralta,
+    /// Boyar-Peralta depth-16 circuit, 34 ANDs and AND depth 4
+    BoyarPeraltaDepth16,
+}
+
+impl SboxCircuit {
+    /// Returns the straight-line program of the S-box
+    fn netlist(&self) -> &'static str {
+        match self {
+            SboxCircuit::BoyarPeralta => include_str!("./sbox_bp.txt"),
+            SboxCircuit::BoyarPeraltaDepth16 => include_str!("./sbox_bp_depth16.txt"),
+        }
+    }
+
+    /// Parses the netlist into `(output, input1, op, input2)` lines
+    fn lines(&self) -> Vec<(&'static str, &'static str, &'static str, &'static str)> {
+        self.netlist()
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| match line.split_whitespace().collect::<Vec<_>>()[..] {
+                [output, "=", input1, op, input2] => (output, input1, op, input2),
+                _ => panic!("Invalid S-box line: {}", line),
+            })
+            .collect()
+    }
+}
+
+/// AES-128 circuits `BoolFheAes` can evaluate
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AesCircuit {
+    /// The embedded SCALE-MAMBA circuit
+    #[default]
+    ScaleMamba,
+    /// Generated from the 32-AND Boyar-Peralta S-box
+    BoyarPeralta,
+    /// Generated from the depth-16 Boyar-Peralta S-box
+    BoyarPeraltaDepth16,
+}
+
+impl AesCircuit {
+    pub const ALL: [AesCircuit; 3] = [
+        AesCircuit::ScaleMamba,
+        AesCircuit::BoyarPeralta,
+        AesCircuit::BoyarPeraltaDepth16,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            AesCircuit::ScaleMamba => "scale-mamba",
+            AesCircuit::BoyarPeralta => "bp",
+            AesCircuit::BoyarPeraltaDepth16 => "bp-depth16",
+        }
+    }
+
+    /// Returns the circuit; generated circuits are grouped into MAND, MXOR and MINV batches
+    pub fn circuit(&self) -> Circuit {
+        match self {
+            AesCircuit::ScaleMamba => Circuit::aes_128(),
+            AesCircuit::BoyarPeralta => {
+                Pass::Group.run(&Circuit::generate_aes_128(SboxCircuit::BoyarPeralta))
+            }
+            AesCircuit::BoyarPeraltaDepth16 => {
+                Pass::Group.run(&Circuit::generate_aes_128(SboxCircuit::BoyarPeraltaDepth16))
+            }
+        }
+    }
+}
+
+impl fmt::Display for AesCircuit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for AesCircuit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        AesCircuit::ALL
+            .into_iter()
+            .find(|circuit| circuit.name() == s)
+            .ok_or_else(|| {
+                let names: Vec<_> = AesCircuit::ALL.iter().map(AesCircuit::name).collect();
+                format!("Unknown circuit '{}', expected one of: {}", s, names.join(", "))
+            })
+    }
+}
+
+impl Circuit {
+    /// Generates an AES-128 circuit using `sbox` for SubBytes and SubWord
+    pub fn generate_aes_128(sbox: SboxCircuit) -> Circuit {
+        let mut generator = Generator { builder: CircuitBuilder::new(), sbox: sbox.lines() };
+
+        let key = to_bytes(&generator.builder.key_input(AES_128_KEY_SIZE_BITS as u32));
+        let mut state = to_bytes(&generator.builder.input(AES_BLOCK_SIZE_BITS as u32));
+        let round_keys = generator.expand_key(key);
+
+        state = generator.add_round_key(&state, &round_keys[0]);
+        for round_key in &round_keys[1..AES_128_ROUNDS] {
+            state = state.map(|byte| generator.sbox(byte));
+            state = shift_rows(&state);
+            state = generator.mix_columns(&state);
+            state = generator.add_round_key(&state, round_key);
+        }
+        state = state.map(|byte| generator.sbox(byte));
+        state = shift_rows(&state);
+        state = generator.add_round_key(&state, &round_keys[AES_128_ROUNDS]);
+
+        generator.builder.output(&from_bytes(&state));
+        generator.builder.build()
+    }
+
+    /// Generates an AES-128 decryption circuit using `sbox` for the key schedule and,
+    /// between two inverse affine maps, for InvSubBytes. It has the same wire layout
+    /// with the ciphertext block in and the plaintext block out.
+    pub fn generate_aes_128_decrypt(sbox: SboxCircuit) -> Circuit {
+        let mut generator = Generator { builder: CircuitBuilder::new(), sbox: sbox.lines() };
+
+        let key = to_bytes(&generator.builder.key_input(AES_128_KEY_SIZE_BITS as u32));
+        let mut state = to_bytes(&generator.builder.input(AES_BLOCK_SIZE_BITS as u32));
+        let round_keys = generator.expand_key(key);
+
+        state = generator.add_round_key(&state, &round_keys[AES_128_ROUNDS]);
+        for round_key in round_keys[1..AES_128_ROUNDS].iter().rev() {
+            state = inv_shift_rows(&state);
+            state = state.map(|byte| generator.inv_sbox(byte));
+            state = generator.add_round_key(&state, round_key);
+            state = generator.inv_mix_columns(&state);
+        }
+        state = inv_shift_rows(&state);
+        state = state.map(|byte| generator.inv_sbox(byte));
+        state = generator.add_round_key(&state, &round_keys[0]);
+
+        generator.builder.output(&from_bytes(&state));
+        generator.builder.build()
+    }
+}
+
+/// Splits a 128-bit word, least significant bit first, into its 16 bytes, first byte first
+fn to_bytes(word: &[u32]) -> [Byte; AES_BLOCK_SIZE] {
+    std::array::from_fn(|byte| {
+        std::array::from_fn(|bit| word[(AES_BLOCK_SIZE - 1 - byte) * 8 + bit])
+    })
+}
+
+/// Inverse of `to_bytes`
+fn from_bytes(bytes: &[Byte; AES_BLOCK_SIZE]) -> Vec<u32> {
+    bytes.iter().rev().flatten().copied().collect()
+}
+
+/// Rotates row `r` of the column-major state left by `r` bytes; only moves wires
+fn shift_rows(state: &[Byte; AES_BLOCK_SIZE]) -> [Byte; AES_BLOCK_SIZE] {
+    std::array::from_fn(|i| {
+        let (row, column) = (i % 4, i / 4);
+        state[row + 4 * ((column + row) % 4)]
+    })
+}
+
+/// Inverse of `shift_rows`
+fn inv_shift_rows(state: &[Byte; AES_BLOCK_SIZE]) -> [Byte; AES_BLOCK_SIZE] {
+    std::array::from_fn(|i| {
+        let (row, column) = (i % 4, i / 4);
+        state[row + 4 * ((column + 4 - row) % 4)]
+    })
+}
+
+struct Generator {
+    builder: CircuitBuilder,
+    sbox: Vec<(&'static str, &'static str, &'static str, &'static str)>,
+}
+
+impl Generator {
+    fn xor_bytes(&mut self, a: &Byte, b: &Byte) -> Byte {
+        std::array::from_fn(|bit| self.builder.xor(a[bit], b[bit]))
+    }
+
+    /// XORs the constant `value` into `byte` with inverters
+    fn xor_constant(&mut self, byte: &Byte, value: u8) -> Byte {
+        std::array::from_fn(|bit| {
+            if value >> bit & 1 == 1 {
+                self.builder.not(byte[bit])
+            } else {
+                byte[bit]
+            }
+        })
+    }
+
+    /// Multiplication by x in GF(2^8): a shift, which only moves wires, and the
+    /// reduction by x^8 + x^4 + x^3 + x + 1
+    fn xtime(&mut self, byte: Byte) -> Byte {
+        std::array::from_fn(|bit| match bit {
+            0 => byte[7],
+            1 | 3 | 4 => self.builder.xor(byte[bit - 1], byte[7]),
+            _ => byte[bit - 1],
+        })
+    }
+
+    /// Evaluates the S-box netlist on `byte`
+    fn sbox(&mut self, byte: Byte) -> Byte {
+        // U0 is the most significant bit
+        let inputs = ["U7", "U6", "U5", "U4", "U3", "U2", "U1", "U0"];
+        let mut wires: HashMap<&str, u32> = inputs.into_iter().zip(byte).collect();
+
+        for (output, input1, op, input2) in &self.sbox {
+            let (a, b) = (wires[input1], wires[input2]);
+            let wire = match *op {
+                "+" => self.builder.xor(a, b),
+                "x" => self.builder.and(a, b),
+                "XNOR" => self.builder.xnor(a, b),
+                _ => panic!("Unknown S-box operator: {}", op),
+            };
+            wires.insert(output, wire);
+        }
+
+        std::array::from_fn(|bit| wires[format!("S{}", 7 - bit).as_str()])
+    }
+
+    /// Inverse of the S-box affine map, `b_i = a_{i+2} ^ a_{i+5} ^ a_{i+7} ^ 0x05`
+    fn inv_affine(&mut self, byte: Byte) -> Byte {
+        let linear = std::array::from_fn(|bit| {
+            let a = self.builder.xor(byte[(bit + 2) % 8], byte[(bit + 5) % 8]);
+            self.builder.xor(a, byte[(bit + 7) % 8])
+        });
+        self.xor_constant(&linear, 0x05)
+    }
+
+    /// Inverse S-box. With `S(x) = A(x^-1)`, `S^-1(y) = A^-1(S(A^-1(y)))`, so the
+    /// forward netlist is reused and only linear gates are added.
+    fn inv_sbox(&mut self, byte: Byte) -> Byte {
+        let byte = self.inv_affine(byte);
+        let byte = self.sbox(byte);
+        self.inv_affine(byte)
+    }
+
+    /// Expands `key` into the 11 round keys
+    fn expand_key(&mut self, key: [Byte; AES_BLOCK_SIZE]) -> Vec<[Byte; AES_BLOCK_SIZE]> {
+        let mut words: Vec<[Byte; 4]> =
+            key.chunks(4).map(|word| word.try_into().unwrap()).collect();
+
+        for i in 4..4 * (AES_128_ROUNDS + 1) {
+            let previous = words[i - 1];
+            let temp = if i % 4 == 0 {
+                let mut temp: [Byte; 4] = std::array::from_fn(|j| self.sbox(previous[(j + 1) % 4]));
+                temp[0] = self.xor_constant(&temp[0], RCON[i / 4]);
+                temp
+            } else {
+                previous
+            };
+            let word = std::array::from_fn(|j| self.xor_bytes(&words[i - 4][j], &temp[j]));
+            words.push(word);
+        }
+
+        words
+            .chunks(4)
+            .map(|round| std::array::from_fn(|i| round[i / 4][i % 4]))
+            .collect()
+    }
+
+    fn add_round_key(
+        &mut self,
+        state: &[Byte; AES_BLOCK_SIZE],
+        round_key: &[Byte; AES_BLOCK_SIZE],
+    ) -> [Byte; AES_BLOCK_SIZE] {
+        std::array::from_fn(|i| self.xor_bytes(&state[i], &round_key[i]))
+    }
+
+    /// Mixes each column as `b_i = a_i ^ t ^ xtime(a_i ^ a_{i+1})` with
+    /// `t = a_0 ^ a_1 ^ a_2 ^ a_3`
+    fn mix_columns(&mut self, state: &[Byte; AES_BLOCK_SIZE]) -> [Byte; AES_BLOCK_SIZE] {
+        let mut mixed = *state;
+        for (c, column) in state.chunks(4).enumerate() {
+            let pairs: [Byte; 4] =
+                std::array::from_fn(|i| self.xor_bytes(&column[i], &column[(i + 1) % 4]));
+            let t = self.xor_bytes(&pairs[0], &pairs[2]);
+            for i in 0..4 {
+                let doubled = self.xtime(pairs[i]);
+                let partial = self.xor_bytes(&column[i], &t);
+                mixed[4 * c + i] = self.xor_bytes(&partial, &doubled);
+            }
+        }
+        mixed
+    }
+
+    /// InvMixColumns as `mix_columns` after adding `u = 4 (a_0 ^ a_2)` to `a_0, a_2`
+    /// and `v = 4 (a_1 ^ a_3)` to `a_1, a_3`
+    fn inv_mix_columns(&mut self, state: &[Byte; AES_BLOCK_SIZE]) -> [Byte; AES_BLOCK_SIZE] {
+        let mut prepared = *state;
+        for c in 0..4 {
+            for i in 0..2 {
+                let sum = self.xor_bytes(&state[4 * c + i], &state[4 * c + i + 2]);
+                let doubled = self.xtime(sum);
+                let quadrupled = self.xtime(doubled);
+                prepared[4 * c + i] = self.xor_bytes(&state[4 * c + i], &quadrupled);
+                prepared[4 * c + i + 2] = self.xor_bytes(&state[4 * c + i + 2], &quadrupled);
+            }
+        }
+        self.mix_columns(&prepared)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encrypt_reference_aes128;
+    use crate::utils::constants::{INV_S_BOX, S_BOX};
+    use rand::Rng;
+
+    #[test]
+    fn test_sbox_circuits() {
+        for sbox in [SboxCircuit::BoyarPeralta, SboxCircuit::BoyarPeraltaDepth16] {
+            let mut generator = Generator { builder: CircuitBuilder::new(), sbox: sbox.lines() };
+            let input = generator.builder.input(8);
+            let output = generator.sbox(input.try_into().unwrap());
+            generator.builder.output(&output);
+            let circuit = generator.builder.build();
+
+            for byte in 0..=255u8 {
+                let inputs: Vec<bool> = (0..8).map(|bit| byte >> bit & 1 == 1).collect();
+                let outputs = circuit.evaluate_outputs(&inputs);
+                let output = (0..8).fold(0, |acc, bit| acc | (outputs[bit] as u8) << bit);
+                assert_eq!(output, S_BOX[byte as usize], "{:?} S-box of {:#04x}", sbox, byte);
+            }
+        }
+    }
+
+    #[test]
+    fn test_inv_sbox_circuit() {
+        let sbox = SboxCircuit::BoyarPeralta.lines();
+        let mut generator = Generator { builder: CircuitBuilder::new(), sbox };
+        let input = generator.builder.input(8);
+        let output = generator.inv_sbox(input.try_into().unwrap());
+        generator.builder.output(&output);
+        let circuit = generator.builder.build();
+
+        for byte in 0..=255u8 {
+            let inputs: Vec<bool> = (0..8).map(|bit| byte >> bit & 1 == 1).collect();
+            let outputs = circuit.evaluate_outputs(&inputs);
+            let output = (0..8).fold(0, |acc, bit| acc | (outputs[bit] as u8) << bit);
+            assert_eq!(output, INV_S_BOX[byte as usize], "inverse S-box of {:#04x}", byte);
+        }
+    }
+
+    #[test]
+    fn test_generated_decrypt_circuit_matches_reference() {
+        use aes::cipher::{generic_array::GenericArray, BlockDecrypt, KeyInit};
+
+        let mut rng = rand::thread_rng();
+        let circuit = Circuit::generate_aes_128_decrypt(SboxCircuit::BoyarPeralta);
+        assert_eq!(circuit.stats().and_gates, 200 * 32);
+        for _ in 0..4 {
+            let key: [u8; 16] = rng.gen();
+            let block: [u8; 16] = rng.gen();
+            let mut expected = GenericArray::from(block);
+            aes::Aes128::new(&GenericArray::from(key)).decrypt_block(&mut expected);
+            assert_eq!(circuit.evaluate_aes(&key, &block).as_slice(), expected.as_slice());
+        }
+    }
+
+    #[test]
+    fn test_generated_circuits_match_reference() {
+        let mut rng = rand::thread_rng();
+        for aes_circuit in AesCircuit::ALL {
+            let circuit = aes_circuit.circuit();
+            for _ in 0..4 {
+                let key: [u8; 16] = rng.gen();
+                let block: [u8; 16] = rng.gen();
+                let expected = encrypt_reference_aes128(vec![block], key);
+                assert_eq!(
+                    circuit.evaluate_aes(&key, &block).as_slice(),
+                    expected[0].as_slice(),
+                    "{} circuit",
+                    aes_circuit
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_bool_fhe_aes_with_generated_circuits() {
+        use crate::backend::Cleartext;
+        use crate::circuit::{bytes_to_wires, wires_to_bytes};
+        use crate::params::ParameterSet;
+        use crate::BoolFheAes;
+
+        let key = [
+            0x2b, 0x7e, 0x15, 0x16, 0x28, 0xae, 0xd2, 0xa6, 0xab, 0xf7, 0x15, 0x88, 0x09, 0xcf,
+            0x4f, 0x3c,
+        ];
+        let iv = [
+            0xf0, 0xf1, 0xf2, 0xf3, 0xf4, 0xf5, 0xf6, 0xf7, 0xf8, 0xf9, 0xfa, 0xfb, 0xfc, 0xfd,
+            0xfe, 0xff,
+        ];
+        let counters = (0..3u128)
+            .map(|i| (u128::from_be_bytes(iv) + i).to_be_bytes())
+            .collect();
+        let expected = encrypt_reference_aes128(counters, key);
+
+        for circuit in [AesCircuit::BoyarPeralta, AesCircuit::BoyarPeraltaDepth16] {
+            let fhe_aes = BoolFheAes::with_circuit(Cleartext, ParameterSet::Default, circuit);
+            fhe_aes.expand_key(bytes_to_wires(&key).try_into().unwrap());
+            let outputs = fhe_aes.aes_ctr_blocks(bytes_to_wires(&iv).try_into().unwrap(), 3);
+            for (output, expected) in outputs.iter().zip(&expected) {
+                assert_eq!(
+                    wires_to_bytes(output).as_slice(),
+                    expected.as_slice(),
+                    "{} circuit",
+                    circuit
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_generated_circuit_gate_counts() {
+        let stats = AesCircuit::BoyarPeralta.circuit().stats();
+        assert_eq!(stats.and_gates + stats.mand_ands, 200 * 32);
+        assert_eq!(stats.and_depth, 60);
+
+        let stats = AesCircuit::BoyarPeraltaDepth16.circuit().stats();
+        assert_eq!(stats.and_gates + stats.mand_ands, 200 * 34);
+        assert_eq!(stats.and_depth, 40);
+    }
+
+    #[test]
+    fn test_aes_circuit_names() {
+        for circuit in AesCircuit::ALL {
+            assert_eq!(circuit.name().parse::<AesCircuit>(), Ok(circuit));
+        }
+        assert!("bp113".parse::<AesCircuit>().is_err());
+    }
+}
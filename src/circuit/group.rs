@@ -0,0 +1,139 @@
+//! Regrouping of independent gates into MAND, MXOR and MINV batches
+//!
+//! ANDs at the same AND depth never depend on each other, so each AND depth becomes
+//! a single MAND. The XOR and INV gates between two MANDs are layered by their
+//! dependency level within that AND depth, and each layer becomes an MINV and an
+//! MXOR. Gates without a batch form join the layer of their level as single gates.
+//! `execute_all` evaluates every batch with `par_iter`, so third-party
+//! circuits get the same parallelism as the hand-grouped AES circuit.
+
+use super::{depth, Circuit, Gate};
+use std::collections::BTreeMap;
+
+/// Gates of one layer, grouped by operation
+#[derive(Default)]
+struct Layer {
+    ands: Vec<(u32, u32, u32)>,
+    xors: Vec<(u32, u32, u32)>,
+    invs: Vec<(u32, u32)>,
+    /// Gates without a batch form (OR, NAND, NOR, XNOR, MUX, EQW), emitted one by one
+    others: Vec<Gate>,
+}
+
+/// Regroups the gates of `circuit` into maximal parallel batches, in dependency
+/// order. A batch holding a single gate is emitted as that gate.
+pub(super) fn group_by_level(circuit: &Circuit) -> Vec<Gate> {
+    let depths = depth::and_depths(&circuit.gates, circuit.output_end);
+    // Number of XOR/INV gates on the longest path from the last AND depth, per wire
+    let mut sublevels = vec![0u32; circuit.output_end as usize];
+    let mut layers: BTreeMap<(u32, u32), Layer> = BTreeMap::new();
+
+    // Dependency level of a linear gate within the AND depth of its output
+    let mut sublevel = |inputs: &[u32], output: u32| {
+        let depth = depths[output as usize];
+        let level = inputs
+            .iter()
+            .filter(|wire| depths[**wire as usize] == depth)
+            .map(|wire| sublevels[*wire as usize])
+            .max()
+            .unwrap_or(0)
+            + 1;
+        sublevels[output as usize] = level;
+        (depth, level)
+    };
+
+    for gate in circuit.gates.iter().flat_map(Gate::elementary) {
+        match gate {
+            Gate::And { input1, input2, output } => {
+                let layer = layers.entry((depths[output as usize], 0)).or_default();
+                layer.ands.push((input1, input2, output));
+            }
+            Gate::Xor { input1, input2, output } => {
+                let key = sublevel(&[input1, input2], output);
+                layers.entry(key).or_default().xors.push((input1, input2, output));
+            }
+            Gate::Inv { input, output } => {
+                let key = sublevel(&[input], output);
+                layers.entry(key).or_default().invs.push((input, output));
+            }
+            Gate::Mand { .. } | Gate::Mxor { .. } | Gate::Minv { .. } => unreachable!(),
+            gate if gate.is_linear() => {
+                let (inputs, output) = gate.wires().remove(0);
+                layers.entry(sublevel(&inputs, output)).or_default().others.push(gate);
+            }
+            gate => {
+                let (_, output) = gate.wires().remove(0);
+                layers.entry((depths[output as usize], 0)).or_default().others.push(gate);
+            }
+        }
+    }
+
+    let mut gates = Vec::new();
+    for layer in layers.into_values() {
+        match layer.invs.as_slice() {
+            [] => {}
+            [(input, output)] => gates.push(Gate::Inv { input: *input, output: *output }),
+            _ => gates.push(Gate::Minv { gates: layer.invs }),
+        }
+        match layer.xors.as_slice() {
+            [] => {}
+            [(input1, input2, output)] => gates.push(Gate::Xor {
+                input1: *input1,
+                input2: *input2,
+                output: *output,
+            }),
+            _ => gates.push(Gate::Mxor { gates: layer.xors }),
+        }
+        match layer.ands.as_slice() {
+            [] => {}
+            [(input1, input2, output)] => gates.push(Gate::And {
+                input1: *input1,
+                input2: *input2,
+                output: *output,
+            }),
+            _ => gates.push(Gate::Mand { gates: layer.ands }),
+        }
+        gates.extend(layer.others);
+    }
+
+    gates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::circuit::{equivalent, CircuitParser, Pass};
+
+    #[test]
+    fn test_group_independent_gates() {
+        // Two ANDs on the inputs, then two XORs on their outputs
+        let circuit = CircuitParser::parse(
+            "4 8\n2 2 2\n1 2\n\n2 1 0 1 4 AND\n2 1 2 3 5 AND\n2 1 4 5 6 XOR\n2 1 4 0 7 XOR\n",
+        );
+        let grouped = Pass::Group.run(&circuit);
+
+        assert!(equivalent(&circuit, &grouped, 16));
+        assert!(matches!(
+            &grouped.gates[..],
+            [Gate::Mand { gates: ands }, Gate::Mxor { gates: xors }] if ands.len() == 2 && xors.len() == 2
+        ));
+    }
+
+    #[test]
+    fn test_group_aes() {
+        let circuit = Circuit::aes_128();
+        let grouped = Pass::Group.run(&circuit);
+        let (stats, grouped_stats) = (circuit.stats(), grouped.stats());
+
+        assert!(equivalent(&circuit, &grouped, 8));
+        // One MAND per AND depth, as in the hand-grouped circuit
+        assert_eq!(grouped_stats.mand_gates as u32, stats.and_depth);
+        assert_eq!(grouped_stats.mand_ands, stats.and_gates + stats.mand_ands);
+        assert_eq!(grouped_stats.block_bootstraps, stats.block_bootstraps);
+        assert!(grouped.gates.len() < circuit.gates.len());
+
+        // Written back in the extended format
+        let reparsed = CircuitParser::parse(&grouped.to_bristol());
+        assert_eq!(reparsed.gates, grouped.gates);
+    }
+}
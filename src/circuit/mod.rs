@@ -1,15 +1,35 @@
 //! Circuit representation and parsing for AES-128 FHE implementation
 
-use crate::utils::constants::{AES_128_KEY_SIZE_BITS, AES_BLOCK_SIZE_BITS};
+use crate::backend::BooleanBackend;
+use crate::utils::constants::AES_BLOCK_SIZE_BITS;
 use bit_vec::BitVec;
+use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
+use std::ops::Range;
 
+mod builder;
+pub(crate) mod depth;
+pub(crate) mod eval;
+mod export;
+mod fingerprint;
+mod generate;
+mod group;
+mod optimize;
 mod parser;
+mod stats;
+mod writer;
 
+pub use builder::CircuitBuilder;
+pub use eval::{bytes_to_wires, wires_to_bytes};
+pub use export::DotClustering;
+pub use generate::{AesCircuit, SboxCircuit};
+pub use optimize::{equivalent, Discarded, OptimizationReport, Pass, PassReport};
 pub use parser::CircuitParser;
+pub use stats::CircuitStats;
 
 /// Represents a single gate in the boolean circuit
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "UPPERCASE")]
 pub enum Gate {
     And {
         input1: u32,
@@ -28,13 +48,226 @@ pub enum Gate {
     Mand {
         gates: Vec<(u32, u32, u32)>,
     },
+    Mxor {
+        gates: Vec<(u32, u32, u32)>,
+    },
+    Minv {
+        gates: Vec<(u32, u32)>,
+    },
+    Or {
+        input1: u32,
+        input2: u32,
+        output: u32,
+    },
+    Nand {
+        input1: u32,
+        input2: u32,
+        output: u32,
+    },
+    Nor {
+        input1: u32,
+        input2: u32,
+        output: u32,
+    },
+    Xnor {
+        input1: u32,
+        input2: u32,
+        output: u32,
+    },
+    /// `output = if select { if_true } else { if_false }`
+    Mux {
+        select: u32,
+        if_true: u32,
+        if_false: u32,
+        output: u32,
+    },
+    /// Copies `input` to `output`
+    Eqw {
+        input: u32,
+        output: u32,
+    },
+}
+
+impl Gate {
+    /// Returns the gate opcode as written in the circuit file
+    pub fn name(&self) -> &'static str {
+        match self {
+            Gate::And { .. } => "AND",
+            Gate::Inv { .. } => "INV",
+            Gate::Xor { .. } => "XOR",
+            Gate::Mand { .. } => "MAND",
+            Gate::Mxor { .. } => "MXOR",
+            Gate::Minv { .. } => "MINV",
+            Gate::Or { .. } => "OR",
+            Gate::Nand { .. } => "NAND",
+            Gate::Nor { .. } => "NOR",
+            Gate::Xnor { .. } => "XNOR",
+            Gate::Mux { .. } => "MUX",
+            Gate::Eqw { .. } => "EQW",
+        }
+    }
+
+    /// Returns `true` for gates that are affine over GF(2) (XOR, XNOR, INV, EQW and
+    /// their batches), which add nothing to the multiplicative depth
+    pub fn is_linear(&self) -> bool {
+        matches!(
+            self,
+            Gate::Xor { .. }
+                | Gate::Xnor { .. }
+                | Gate::Inv { .. }
+                | Gate::Eqw { .. }
+                | Gate::Mxor { .. }
+                | Gate::Minv { .. }
+        )
+    }
+
+    /// Returns the number of bootstraps each elementary gate costs under the tfhe
+    /// boolean backend: none for INV and EQW, two for MUX and one otherwise
+    pub fn bootstraps(&self) -> usize {
+        match self {
+            Gate::Inv { .. } | Gate::Minv { .. } | Gate::Eqw { .. } => 0,
+            Gate::Mux { .. } => 2,
+            _ => 1,
+        }
+    }
+
+    /// Applies the gate operation to the `inputs` of one of its elementary gates,
+    /// given in the order returned by `wires`
+    pub fn apply<B: BooleanBackend>(&self, backend: &B, inputs: &[B::Ciphertext]) -> B::Ciphertext {
+        match self {
+            Gate::And { .. } | Gate::Mand { .. } => backend.and(&inputs[0], &inputs[1]),
+            Gate::Xor { .. } | Gate::Mxor { .. } => backend.xor(&inputs[0], &inputs[1]),
+            Gate::Inv { .. } | Gate::Minv { .. } => backend.not(&inputs[0]),
+            Gate::Or { .. } => backend.or(&inputs[0], &inputs[1]),
+            Gate::Nand { .. } => backend.nand(&inputs[0], &inputs[1]),
+            Gate::Nor { .. } => backend.nor(&inputs[0], &inputs[1]),
+            Gate::Xnor { .. } => backend.xnor(&inputs[0], &inputs[1]),
+            Gate::Mux { .. } => backend.mux(&inputs[0], &inputs[1], &inputs[2]),
+            Gate::Eqw { .. } => backend.clone_ciphertext(&inputs[0]),
+        }
+    }
+
+    /// Returns the `(inputs, output)` wires of each elementary gate; a batch yields
+    /// one entry per gate it groups
+    pub fn wires(&self) -> Vec<(Vec<u32>, u32)> {
+        match self {
+            Gate::And { input1, input2, output }
+            | Gate::Xor { input1, input2, output }
+            | Gate::Or { input1, input2, output }
+            | Gate::Nand { input1, input2, output }
+            | Gate::Nor { input1, input2, output }
+            | Gate::Xnor { input1, input2, output } => vec![(vec![*input1, *input2], *output)],
+            Gate::Inv { input, output } | Gate::Eqw { input, output } => {
+                vec![(vec![*input], *output)]
+            }
+            Gate::Mux { select, if_true, if_false, output } => {
+                vec![(vec![*select, *if_true, *if_false], *output)]
+            }
+            Gate::Mand { gates } | Gate::Mxor { gates } => gates
+                .iter()
+                .map(|(input1, input2, output)| (vec![*input1, *input2], *output))
+                .collect(),
+            Gate::Minv { gates } => gates
+                .iter()
+                .map(|(input, output)| (vec![*input], *output))
+                .collect(),
+        }
+    }
+
+    /// Splits MAND, MXOR and MINV batches into single gates
+    pub fn elementary(&self) -> Vec<Gate> {
+        match self {
+            Gate::Mand { gates } => gates
+                .iter()
+                .map(|(input1, input2, output)| Gate::And {
+                    input1: *input1,
+                    input2: *input2,
+                    output: *output,
+                })
+                .collect(),
+            Gate::Mxor { gates } => gates
+                .iter()
+                .map(|(input1, input2, output)| Gate::Xor {
+                    input1: *input1,
+                    input2: *input2,
+                    output: *output,
+                })
+                .collect(),
+            Gate::Minv { gates } => gates
+                .iter()
+                .map(|(input, output)| Gate::Inv { input: *input, output: *output })
+                .collect(),
+            gate => vec![gate.clone()],
+        }
+    }
+
+    /// Returns the gate with every input and output wire replaced by `map(wire)`
+    pub fn map_wires(&self, map: impl Fn(u32) -> u32) -> Gate {
+        let map3 = |(a, b, out): &(u32, u32, u32)| (map(*a), map(*b), map(*out));
+        match self {
+            Gate::And { input1, input2, output } => Gate::And {
+                input1: map(*input1),
+                input2: map(*input2),
+                output: map(*output),
+            },
+            Gate::Inv { input, output } => Gate::Inv {
+                input: map(*input),
+                output: map(*output),
+            },
+            Gate::Xor { input1, input2, output } => Gate::Xor {
+                input1: map(*input1),
+                input2: map(*input2),
+                output: map(*output),
+            },
+            Gate::Mand { gates } => Gate::Mand { gates: gates.iter().map(map3).collect() },
+            Gate::Mxor { gates } => Gate::Mxor { gates: gates.iter().map(map3).collect() },
+            Gate::Minv { gates } => Gate::Minv {
+                gates: gates.iter().map(|(a, out)| (map(*a), map(*out))).collect(),
+            },
+            Gate::Or { input1, input2, output } => Gate::Or {
+                input1: map(*input1),
+                input2: map(*input2),
+                output: map(*output),
+            },
+            Gate::Nand { input1, input2, output } => Gate::Nand {
+                input1: map(*input1),
+                input2: map(*input2),
+                output: map(*output),
+            },
+            Gate::Nor { input1, input2, output } => Gate::Nor {
+                input1: map(*input1),
+                input2: map(*input2),
+                output: map(*output),
+            },
+            Gate::Xnor { input1, input2, output } => Gate::Xnor {
+                input1: map(*input1),
+                input2: map(*input2),
+                output: map(*output),
+            },
+            Gate::Mux { select, if_true, if_false, output } => Gate::Mux {
+                select: map(*select),
+                if_true: map(*if_true),
+                if_false: map(*if_false),
+                output: map(*output),
+            },
+            Gate::Eqw { input, output } => Gate::Eqw {
+                input: map(*input),
+                output: map(*output),
+            },
+        }
+    }
 }
 
 /// Contains the parsed circuit structure
+#[derive(Debug, Clone)]
 pub struct Circuit {
     pub gates: Vec<Gate>,
     pub key_expand_reachable: HashSet<u32>,
     pub output_end: u32,
+    /// Bit width of each input, as declared in the circuit file
+    pub inputs: Vec<u32>,
+    /// Bit width of each output, as declared in the circuit file
+    pub outputs: Vec<u32>,
 }
 
 impl Circuit {
@@ -43,4 +276,46 @@ impl Circuit {
         let circuit_text = include_str!("./aes_128_extended.txt");
         CircuitParser::parse(circuit_text)
     }
+
+    /// Creates a circuit from gates in dependency order. Inputs occupy the first
+    /// wires and outputs the last wires below `output_end`, as in parsed circuits.
+    /// Gates depending only on `key_wires` are key expansion; pass an empty range
+    /// for circuits without an AES key.
+    pub fn from_gates(
+        gates: Vec<Gate>,
+        key_wires: Range<u32>,
+        inputs: Vec<u32>,
+        outputs: Vec<u32>,
+        output_end: u32,
+    ) -> Self {
+        let mut key_expand_reachable: HashSet<u32> = key_wires.collect();
+        for gate in &gates {
+            parser::update_reachability(gate, &mut key_expand_reachable);
+        }
+
+        Circuit {
+            gates,
+            key_expand_reachable,
+            output_end,
+            inputs,
+            outputs,
+        }
+    }
+
+    /// Leading input wires holding the key, from which key expansion is derived
+    pub fn key_wires(&self) -> Range<u32> {
+        let key_bits = (0..self.input_count()).take_while(|wire| self.key_expand_reachable.contains(wire)).count();
+        0..key_bits as u32
+    }
+
+    /// Total number of input wires
+    pub fn input_count(&self) -> u32 {
+        self.inputs.iter().sum()
+    }
+
+    /// Range of the output wires
+    pub fn output_wires(&self) -> Range<u32> {
+        let output_count: u32 = self.outputs.iter().sum();
+        self.output_end - output_count..self.output_end
+    }
 }
@@ -0,0 +1,822 @@
+//! Circuit optimization passes
+//!
+//! Every gate removed is a bootstrap saved on every evaluated block. Passes work on
+//! gates in dependency order and keep the wire layout `BoolFheAes` relies on:
+//! inputs on the first wires and outputs on the last wires below `output_end`.
+//! After each pass the circuit is checked against the original with the
+//! cleartext evaluator.
+
+use super::{group, Circuit, Gate};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
+
+/// Number of random input vectors used to check a pass preserved the circuit
+const EQUIVALENCE_SAMPLES: usize = 64;
+
+/// A single optimization pass
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pass {
+    /// Removes gates whose outputs never reach the circuit outputs
+    DeadGates,
+    /// Merges gates computing the same operation on the same inputs
+    CommonSubexpressions,
+    /// Replaces `INV(INV(a))` by `a`
+    DoubleInv,
+    /// Moves inverters through XOR gates, so they cancel or are only materialized
+    /// where an AND or a circuit output needs them
+    PushInv,
+    /// Fuses single-use inverters into the neighbouring gate (`INV(AND)` into NAND,
+    /// `AND(INV, INV)` into NOR, `XOR(INV, b)` into XNOR, ...) and the AND/XOR
+    /// form of a multiplexer into MUX
+    Fuse,
+    /// Regroups independent gates by dependency level into MAND, MXOR and MINV batches
+    Group,
+}
+
+impl Pass {
+    /// All passes, in the order the default pipeline runs them
+    pub const ALL: [Pass; 6] = [
+        Pass::DeadGates,
+        Pass::CommonSubexpressions,
+        Pass::DoubleInv,
+        Pass::PushInv,
+        Pass::Fuse,
+        Pass::Group,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Pass::DeadGates => "dead-gates",
+            Pass::CommonSubexpressions => "cse",
+            Pass::DoubleInv => "double-inv",
+            Pass::PushInv => "push-inv",
+            Pass::Fuse => "fuse",
+            Pass::Group => "group",
+        }
+    }
+
+    /// Runs the pass. Rewriting passes are followed by dead-gate elimination so
+    /// the gates they make redundant are removed.
+    pub fn run(&self, circuit: &Circuit) -> Circuit {
+        // The rewriting passes keep MAND groups but work on single XORs and inverters;
+        // fusion looks through every gate
+        let split;
+        let circuit = match self {
+            Pass::Group => circuit,
+            Pass::Fuse => {
+                split = Circuit {
+                    gates: circuit.gates.iter().flat_map(Gate::elementary).collect(),
+                    ..circuit.clone()
+                };
+                &split
+            }
+            _ => {
+                split = split_xor_inv_batches(circuit);
+                &split
+            }
+        };
+
+        let gates = match self {
+            Pass::DeadGates => circuit.gates.clone(),
+            Pass::CommonSubexpressions => eliminate_common_subexpressions(circuit),
+            Pass::DoubleInv => remove_double_inv(circuit),
+            Pass::PushInv => push_inv(circuit),
+            Pass::Fuse => fuse_gates(circuit),
+            Pass::Group => group::group_by_level(circuit),
+        };
+        let gates = eliminate_dead_gates(circuit, gates);
+        renumber(circuit, gates)
+    }
+}
+
+impl fmt::Display for Pass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Pass {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        Pass::ALL
+            .into_iter()
+            .find(|pass| pass.name() == s)
+            .ok_or_else(|| format!("Unknown pass '{}'", s))
+    }
+}
+
+/// Gate savings of one pass
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PassReport {
+    pub pass: Pass,
+    /// Elementary gates before and after the pass
+    pub gates_before: usize,
+    pub gates_after: usize,
+    /// Bootstrapped gates per block before and after the pass
+    pub bootstraps_before: usize,
+    pub bootstraps_after: usize,
+    /// Why the result was thrown away and the input kept, if it was
+    pub discarded: Option<Discarded>,
+    /// Whether the result agreed with the input circuit on random inputs
+    pub verified: bool,
+}
+
+impl PassReport {
+    /// Whether the result was kept
+    pub fn applied(&self) -> bool {
+        self.discarded.is_none()
+    }
+}
+
+/// Reason a pass result was discarded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Discarded {
+    /// The result disagreed with the input circuit
+    NotEquivalent,
+    /// The result had more elementary gates than the input circuit
+    AddsGates,
+}
+
+/// Reports of every pass in a pipeline run
+#[derive(Debug, Clone, Default)]
+pub struct OptimizationReport {
+    pub passes: Vec<PassReport>,
+}
+
+impl OptimizationReport {
+    /// Whether every kept pass was verified equivalent, so the optimized circuit
+    /// computes the same function as the input
+    pub fn verified(&self) -> bool {
+        self.passes.iter().filter(|pass| pass.applied()).all(|pass| pass.verified)
+    }
+}
+
+impl fmt::Display for OptimizationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for report in &self.passes {
+            writeln!(
+                f,
+                "{:<11} gates {:>6} -> {:>6} ({:+})  bootstraps/block {:>6} -> {:>6} ({:+})  {}",
+                report.pass.name(),
+                report.gates_before,
+                report.gates_after,
+                report.gates_after as i64 - report.gates_before as i64,
+                report.bootstraps_before,
+                report.bootstraps_after,
+                report.bootstraps_after as i64 - report.bootstraps_before as i64,
+                match report.discarded {
+                    None => "verified",
+                    Some(Discarded::NotEquivalent) => "discarded (NOT EQUIVALENT)",
+                    Some(Discarded::AddsGates) => "verified, discarded (adds gates)",
+                }
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Circuit {
+    /// Runs `passes` in order, verifying each result with the cleartext evaluator.
+    /// Results that are not equivalent or add gates are discarded, so the returned
+    /// circuit always computes the same function as `self`.
+    pub fn optimize(&self, passes: &[Pass]) -> (Circuit, OptimizationReport) {
+        self.optimize_with(passes, Pass::run)
+    }
+
+    /// Same as `optimize`, running each pass with `run`
+    fn optimize_with(&self, passes: &[Pass], run: impl Fn(&Pass, &Circuit) -> Circuit) -> (Circuit, OptimizationReport) {
+        let mut report = OptimizationReport::default();
+        let mut current = self.clone();
+
+        for pass in passes {
+            let mut optimized = run(pass, &current);
+            let verified = equivalent(&current, &optimized, EQUIVALENCE_SAMPLES);
+
+            // A pass can add inverters on some circuits; keep the input then
+            let discarded = if !verified {
+                Some(Discarded::NotEquivalent)
+            } else if elementary_gates(&optimized) > elementary_gates(&current) {
+                Some(Discarded::AddsGates)
+            } else {
+                None
+            };
+            if discarded.is_some() {
+                optimized = current.clone();
+            }
+
+            let (before, after) = (current.stats(), optimized.stats());
+            report.passes.push(PassReport {
+                pass: *pass,
+                gates_before: elementary_gates(&current),
+                gates_after: elementary_gates(&optimized),
+                bootstraps_before: before.block_bootstraps,
+                bootstraps_after: after.block_bootstraps,
+                discarded,
+                verified,
+            });
+            current = optimized;
+        }
+
+        (current, report)
+    }
+}
+
+/// Checks that two circuits with the same inputs and outputs agree on the output
+/// wires for `samples` pseudorandom input vectors
+pub fn equivalent(a: &Circuit, b: &Circuit, samples: usize) -> bool {
+    if a.inputs != b.inputs || a.outputs != b.outputs {
+        return false;
+    }
+
+    let mut state = 0x9e3779b97f4a7c15u64;
+    (0..samples).all(|_| {
+        let inputs: Vec<bool> = (0..a.input_count())
+            .map(|_| {
+                // xorshift64
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state & 1 == 1
+            })
+            .collect();
+
+        let values_a = a.evaluate(&inputs);
+        let values_b = b.evaluate(&inputs);
+        a.output_wires()
+            .zip(b.output_wires())
+            .all(|(wire_a, wire_b)| values_a[wire_a as usize] == values_b[wire_b as usize])
+    })
+}
+
+fn elementary_gates(circuit: &Circuit) -> usize {
+    circuit.gates.iter().map(|gate| gate.wires().len()).sum()
+}
+
+/// Keeps only the gates whose outputs are needed by the circuit outputs
+fn eliminate_dead_gates(circuit: &Circuit, gates: Vec<Gate>) -> Vec<Gate> {
+    let mut live: HashSet<u32> = circuit.output_wires().collect();
+    let mut kept = Vec::with_capacity(gates.len());
+
+    for gate in gates.into_iter().rev() {
+        let gate = match gate {
+            Gate::Mand { gates } => {
+                let gates: Vec<_> = gates
+                    .into_iter()
+                    .filter(|(_, _, output)| live.contains(output))
+                    .collect();
+                if gates.is_empty() {
+                    continue;
+                }
+                Gate::Mand { gates }
+            }
+            Gate::Mxor { gates } => {
+                let gates: Vec<_> = gates
+                    .into_iter()
+                    .filter(|(_, _, output)| live.contains(output))
+                    .collect();
+                if gates.is_empty() {
+                    continue;
+                }
+                Gate::Mxor { gates }
+            }
+            Gate::Minv { gates } => {
+                let gates: Vec<_> = gates
+                    .into_iter()
+                    .filter(|(_, output)| live.contains(output))
+                    .collect();
+                if gates.is_empty() {
+                    continue;
+                }
+                Gate::Minv { gates }
+            }
+            gate => {
+                if !gate.wires().iter().any(|(_, output)| live.contains(output)) {
+                    continue;
+                }
+                gate
+            }
+        };
+
+        for (inputs, _) in gate.wires() {
+            live.extend(inputs);
+        }
+        kept.push(gate);
+    }
+
+    kept.reverse();
+    kept
+}
+
+/// Follows `aliases` to the wire that carries the same value
+fn resolve(aliases: &HashMap<u32, u32>, wire: u32) -> u32 {
+    *aliases.get(&wire).unwrap_or(&wire)
+}
+
+/// Gates computing the same operation on the same inputs are merged. Circuit
+/// outputs are always computed by their own gate.
+fn eliminate_common_subexpressions(circuit: &Circuit) -> Vec<Gate> {
+    let outputs = circuit.output_wires();
+    let mut aliases: HashMap<u32, u32> = HashMap::new();
+    let mut seen: HashMap<(&'static str, Vec<u32>), u32> = HashMap::new();
+    let mut gates = Vec::with_capacity(circuit.gates.len());
+
+    // Returns the wire to use for `output`, or `None` if an existing wire already holds it
+    let mut lookup = |key: (&'static str, Vec<u32>), output: u32, aliases: &mut HashMap<u32, u32>| {
+        match seen.get(&key) {
+            Some(existing) if !outputs.contains(&output) => {
+                aliases.insert(output, *existing);
+                false
+            }
+            _ => {
+                seen.entry(key).or_insert(output);
+                true
+            }
+        }
+    };
+
+    for gate in &circuit.gates {
+        match gate {
+            Gate::Mand { gates: ands } => {
+                let mut kept = Vec::with_capacity(ands.len());
+                for (input1, input2, output) in ands {
+                    let (a, b) = (resolve(&aliases, *input1), resolve(&aliases, *input2));
+                    if lookup(("AND", vec![a.min(b), a.max(b)]), *output, &mut aliases) {
+                        kept.push((a, b, *output));
+                    }
+                }
+                if !kept.is_empty() {
+                    gates.push(Gate::Mand { gates: kept });
+                }
+            }
+            Gate::Mxor { .. } | Gate::Minv { .. } => unreachable!("XOR and INV batches are split first"),
+            Gate::Eqw { input, output } if !outputs.contains(output) => {
+                aliases.insert(*output, resolve(&aliases, *input));
+            }
+            gate => {
+                let gate = gate.map_wires(|wire| resolve(&aliases, wire));
+                let (mut inputs, output) = gate.wires().remove(0);
+                // Every two-input gate is commutative
+                if inputs.len() == 2 {
+                    inputs.sort_unstable();
+                }
+                if lookup((gate.name(), inputs), output, &mut aliases) {
+                    gates.push(gate);
+                }
+            }
+        }
+    }
+
+    gates
+}
+
+/// Replaces `INV(INV(a))` by `a` wherever the result is not a circuit output
+fn remove_double_inv(circuit: &Circuit) -> Vec<Gate> {
+    let outputs = circuit.output_wires();
+    let mut aliases: HashMap<u32, u32> = HashMap::new();
+    let mut inverted_from: HashMap<u32, u32> = HashMap::new();
+    let mut gates = Vec::with_capacity(circuit.gates.len());
+
+    for gate in &circuit.gates {
+        match gate {
+            Gate::Inv { input, output } => {
+                let a = resolve(&aliases, *input);
+                match inverted_from.get(&a) {
+                    Some(original) if !outputs.contains(output) => {
+                        aliases.insert(*output, *original);
+                    }
+                    _ => {
+                        inverted_from.insert(*output, a);
+                        gates.push(Gate::Inv { input: a, output: *output });
+                    }
+                }
+            }
+            gate => gates.push(gate.map_wires(|wire| resolve(&aliases, wire))),
+        }
+    }
+
+    gates
+}
+
+/// Tracks every wire as `base XOR parity`, using `NOT a XOR b = NOT (a XOR b)`.
+/// XNOR is an XOR with flipped parity and EQW copies the parity. Inverters are only
+/// emitted where a non-linear gate or a circuit output needs the inverted value,
+/// and each inverted wire is materialized once.
+fn push_inv(circuit: &Circuit) -> Vec<Gate> {
+    let outputs = circuit.output_wires();
+    let mut next_wire = circuit.output_end;
+    let mut fresh = || {
+        next_wire += 1;
+        next_wire - 1
+    };
+
+    let mut parity: HashMap<u32, (u32, bool)> = HashMap::new();
+    let mut inverted: HashMap<u32, u32> = HashMap::new();
+    let mut gates = Vec::with_capacity(circuit.gates.len());
+
+    // Returns a wire holding the value of `wire`, emitting an inverter if needed
+    let mut materialize = |wire: u32,
+                           parity: &HashMap<u32, (u32, bool)>,
+                           gates: &mut Vec<Gate>,
+                           fresh: &mut dyn FnMut() -> u32| {
+        match parity.get(&wire) {
+            Some((base, true)) => *inverted.entry(*base).or_insert_with(|| {
+                let output = fresh();
+                gates.push(Gate::Inv { input: *base, output });
+                output
+            }),
+            Some((base, false)) => *base,
+            None => wire,
+        }
+    };
+
+    for gate in &circuit.gates {
+        match gate {
+            Gate::Xor { input1, input2, output } => {
+                let (a, pa) = *parity.get(input1).unwrap_or(&(*input1, false));
+                let (b, pb) = *parity.get(input2).unwrap_or(&(*input2, false));
+                if outputs.contains(output) && pa ^ pb {
+                    let raw = fresh();
+                    gates.push(Gate::Xor { input1: a, input2: b, output: raw });
+                    gates.push(Gate::Inv { input: raw, output: *output });
+                } else {
+                    gates.push(Gate::Xor { input1: a, input2: b, output: *output });
+                    parity.insert(*output, (*output, pa ^ pb));
+                }
+            }
+            Gate::Inv { input, output } => {
+                if outputs.contains(output) {
+                    let a = materialize(*input, &parity, &mut gates, &mut fresh);
+                    gates.push(Gate::Inv { input: a, output: *output });
+                } else {
+                    let (a, pa) = *parity.get(input).unwrap_or(&(*input, false));
+                    parity.insert(*output, (a, !pa));
+                }
+            }
+            Gate::Xnor { input1, input2, output } => {
+                let (a, pa) = *parity.get(input1).unwrap_or(&(*input1, false));
+                let (b, pb) = *parity.get(input2).unwrap_or(&(*input2, false));
+                let inverted = !(pa ^ pb);
+                if outputs.contains(output) && inverted {
+                    gates.push(Gate::Xnor { input1: a, input2: b, output: *output });
+                } else {
+                    gates.push(Gate::Xor { input1: a, input2: b, output: *output });
+                    parity.insert(*output, (*output, inverted));
+                }
+            }
+            Gate::Eqw { input, output } => {
+                if outputs.contains(output) {
+                    let a = materialize(*input, &parity, &mut gates, &mut fresh);
+                    gates.push(Gate::Eqw { input: a, output: *output });
+                } else {
+                    let copy = *parity.get(input).unwrap_or(&(*input, false));
+                    parity.insert(*output, copy);
+                }
+            }
+            Gate::Mxor { .. } | Gate::Minv { .. } => unreachable!("XOR and INV batches are split first"),
+            gate => {
+                let mut materialized = HashMap::new();
+                for input in gate.wires().into_iter().flat_map(|(inputs, _)| inputs) {
+                    let a = materialize(input, &parity, &mut gates, &mut fresh);
+                    materialized.insert(input, a);
+                }
+                gates.push(gate.map_wires(|wire| *materialized.get(&wire).unwrap_or(&wire)));
+            }
+        }
+    }
+
+    gates
+}
+
+/// Returns `gate` with its operation negated, for the gates that have a negated form
+fn negated(gate: &Gate) -> Option<Gate> {
+    Some(match *gate {
+        Gate::And { input1, input2, output } => Gate::Nand { input1, input2, output },
+        Gate::Nand { input1, input2, output } => Gate::And { input1, input2, output },
+        Gate::Or { input1, input2, output } => Gate::Nor { input1, input2, output },
+        Gate::Nor { input1, input2, output } => Gate::Or { input1, input2, output },
+        Gate::Xor { input1, input2, output } => Gate::Xnor { input1, input2, output },
+        Gate::Xnor { input1, input2, output } => Gate::Xor { input1, input2, output },
+        _ => return None,
+    })
+}
+
+/// Peephole fusion over elementary gates. Inverters cost no bootstrap under the
+/// boolean backend, so the inverter fusions save gates and latency; the MUX fusion
+/// also saves a bootstrap. Only single-use wires that are not circuit outputs are
+/// fused away.
+fn fuse_gates(circuit: &Circuit) -> Vec<Gate> {
+    let outputs = circuit.output_wires();
+    let mut uses: HashMap<u32, usize> = HashMap::new();
+    for (inputs, _) in circuit.gates.iter().flat_map(Gate::wires) {
+        for input in inputs {
+            *uses.entry(input).or_default() += 1;
+        }
+    }
+
+    // Emitted gates, with fused ones taken out, and the position defining each wire
+    let mut gates: Vec<Option<Gate>> = Vec::with_capacity(circuit.gates.len());
+    let mut defined_at: HashMap<u32, usize> = HashMap::new();
+
+    for gate in &circuit.gates {
+        // Position of the gate defining `wire`, if only this gate reads it
+        let fusable = |wire: u32, gates: &[Option<Gate>]| {
+            let position = *defined_at.get(&wire)?;
+            (uses[&wire] == 1 && !outputs.contains(&wire) && gates[position].is_some())
+                .then_some(position)
+        };
+        // Input of the inverter defining `wire`, if it can be fused away
+        let inverted = |wire: u32, gates: &[Option<Gate>]| match gates[fusable(wire, gates)?] {
+            Some(Gate::Inv { input, .. }) => Some(input),
+            _ => None,
+        };
+
+        let fused = match *gate {
+            Gate::Inv { input, output } => fusable(input, &gates).and_then(|position| {
+                let fused = negated(gates[position].as_ref()?)?;
+                gates[position] = None;
+                Some(fused.map_wires(|wire| if wire == input { output } else { wire }))
+            }),
+            Gate::Xor { input1, input2, output } | Gate::Xnor { input1, input2, output } => {
+                match (inverted(input1, &gates), inverted(input2, &gates)) {
+                    (None, None) if matches!(gate, Gate::Xor { .. }) => {
+                        mux_pattern(input1, input2, output, &mut gates, fusable)
+                    }
+                    (None, None) => None,
+                    (a, b) => {
+                        let flip = a.is_some() != b.is_some();
+                        for (wire, source) in [(input1, a), (input2, b)] {
+                            if source.is_some() {
+                                gates[defined_at[&wire]] = None;
+                            }
+                        }
+                        let gate = gate.map_wires(|wire| match wire {
+                            w if w == input1 => a.unwrap_or(input1),
+                            w if w == input2 => b.unwrap_or(input2),
+                            w => w,
+                        });
+                        if flip { negated(&gate) } else { Some(gate) }
+                    }
+                }
+            }
+            Gate::And { input1, input2, output }
+            | Gate::Or { input1, input2, output }
+            | Gate::Nand { input1, input2, output }
+            | Gate::Nor { input1, input2, output } => {
+                // De Morgan: AND(NOT a, NOT b) = NOR(a, b), and so on
+                match (inverted(input1, &gates), inverted(input2, &gates)) {
+                    (Some(a), Some(b)) => {
+                        gates[defined_at[&input1]] = None;
+                        gates[defined_at[&input2]] = None;
+                        let input1 = a;
+                        let input2 = b;
+                        Some(match gate {
+                            Gate::And { .. } => Gate::Nor { input1, input2, output },
+                            Gate::Or { .. } => Gate::Nand { input1, input2, output },
+                            Gate::Nand { .. } => Gate::Or { input1, input2, output },
+                            _ => Gate::And { input1, input2, output },
+                        })
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+
+        let gate = fused.unwrap_or_else(|| gate.clone());
+        for (_, output) in gate.wires() {
+            defined_at.insert(output, gates.len());
+        }
+        gates.push(Some(gate));
+    }
+
+    gates.into_iter().flatten().collect()
+}
+
+/// Matches `XOR(f, AND(s, XOR(t, f)))`, the AND/XOR form of `if s { t } else { f }`,
+/// with the inner gates fusable, and returns the equivalent MUX
+fn mux_pattern(
+    input1: u32,
+    input2: u32,
+    output: u32,
+    gates: &mut [Option<Gate>],
+    fusable: impl Fn(u32, &[Option<Gate>]) -> Option<usize>,
+) -> Option<Gate> {
+    for (if_false, masked) in [(input1, input2), (input2, input1)] {
+        let Some(and_position) = fusable(masked, gates) else { continue };
+        let Some(Gate::And { input1: a, input2: b, .. }) = gates[and_position] else { continue };
+        for (select, diff) in [(a, b), (b, a)] {
+            let Some(xor_position) = fusable(diff, gates) else { continue };
+            let Some(Gate::Xor { input1: x, input2: y, .. }) = gates[xor_position] else { continue };
+            let if_true = match (x == if_false, y == if_false) {
+                (true, false) => y,
+                (false, true) => x,
+                _ => continue,
+            };
+            gates[and_position] = None;
+            gates[xor_position] = None;
+            return Some(Gate::Mux { select, if_true, if_false, output });
+        }
+    }
+    None
+}
+
+/// Replaces MXOR and MINV batches by single gates
+fn split_xor_inv_batches(circuit: &Circuit) -> Circuit {
+    let gates = circuit
+        .gates
+        .iter()
+        .flat_map(|gate| match gate {
+            Gate::Mxor { .. } | Gate::Minv { .. } => gate.elementary(),
+            gate => vec![gate.clone()],
+        })
+        .collect();
+
+    Circuit { gates, ..circuit.clone() }
+}
+
+/// Renumbers wires compactly: inputs keep their wires, internal wires are numbered
+/// in definition order and outputs move to the last wires
+fn renumber(circuit: &Circuit, gates: Vec<Gate>) -> Circuit {
+    let input_count = circuit.input_count();
+    let old_outputs = circuit.output_wires();
+    let output_count = old_outputs.len() as u32;
+
+    let internal: Vec<u32> = gates
+        .iter()
+        .flat_map(|gate| gate.wires())
+        .map(|(_, output)| output)
+        .filter(|output| !old_outputs.contains(output))
+        .collect();
+    let output_end = input_count + internal.len() as u32 + output_count;
+
+    let mut mapping: HashMap<u32, u32> = (0..input_count).map(|wire| (wire, wire)).collect();
+    for (i, wire) in internal.iter().enumerate() {
+        mapping.insert(*wire, input_count + i as u32);
+    }
+    for (i, wire) in old_outputs.enumerate() {
+        mapping.insert(wire, output_end - output_count + i as u32);
+    }
+
+    let gates = gates.iter().map(|gate| gate.map_wires(|wire| mapping[&wire])).collect();
+
+    Circuit::from_gates(gates, circuit.key_wires(), circuit.inputs.clone(), circuit.outputs.clone(), output_end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::circuit::CircuitParser;
+
+    fn gate_count(text: &str, pass: Pass) -> (usize, Circuit) {
+        let circuit = CircuitParser::parse(text);
+        let optimized = pass.run(&circuit);
+        assert!(equivalent(&circuit, &optimized, 16));
+        (elementary_gates(&optimized), optimized)
+    }
+
+    #[test]
+    fn test_dead_gates() {
+        // Wire 3 never reaches output wire 4
+        let (gates, _) = gate_count("2 5\n1 2\n1 1\n\n2 1 0 1 3 AND\n2 1 0 1 4 XOR\n", Pass::DeadGates);
+        assert_eq!(gates, 1);
+    }
+
+    #[test]
+    fn test_common_subexpressions() {
+        let text = "3 5\n1 2\n1 1\n\n2 1 0 1 2 AND\n2 1 1 0 3 AND\n2 1 2 3 4 XOR\n";
+        let (gates, _) = gate_count(text, Pass::CommonSubexpressions);
+        assert_eq!(gates, 2);
+    }
+
+    #[test]
+    fn test_double_inv() {
+        let text = "3 5\n1 2\n1 1\n\n1 1 0 2 INV\n1 1 2 3 INV\n2 1 3 1 4 AND\n";
+        let (gates, _) = gate_count(text, Pass::DoubleInv);
+        assert_eq!(gates, 1);
+    }
+
+    #[test]
+    fn test_push_inv() {
+        // XOR(NOT a, NOT b) needs no inverter
+        let text = "3 5\n1 2\n1 1\n\n1 1 0 2 INV\n1 1 1 3 INV\n2 1 2 3 4 XOR\n";
+        let (gates, optimized) = gate_count(text, Pass::PushInv);
+        assert_eq!(gates, 1);
+        assert!(matches!(optimized.gates[0], Gate::Xor { .. }));
+    }
+
+    #[test]
+    fn test_push_inv_inverted_output() {
+        // XOR(NOT a, b) on an output keeps one inverter after the XOR
+        let text = "2 4\n1 2\n1 1\n\n1 1 0 2 INV\n2 1 2 1 3 XOR\n";
+        let (gates, _) = gate_count(text, Pass::PushInv);
+        assert_eq!(gates, 2);
+    }
+
+    #[test]
+    fn test_fuse_inverters() {
+        // INV(AND) becomes NAND
+        let text = "2 4\n1 2\n1 1\n\n2 1 0 1 2 AND\n1 1 2 3 INV\n";
+        let (gates, optimized) = gate_count(text, Pass::Fuse);
+        assert_eq!(gates, 1);
+        assert!(matches!(optimized.gates[0], Gate::Nand { .. }));
+
+        // AND(NOT a, NOT b) becomes NOR
+        let text = "3 5\n1 2\n1 1\n\n1 1 0 2 INV\n1 1 1 3 INV\n2 1 2 3 4 AND\n";
+        let (gates, optimized) = gate_count(text, Pass::Fuse);
+        assert_eq!(gates, 1);
+        assert!(matches!(optimized.gates[0], Gate::Nor { .. }));
+
+        // XOR(NOT a, b) becomes XNOR
+        let text = "2 4\n1 2\n1 1\n\n1 1 0 2 INV\n2 1 2 1 3 XOR\n";
+        let (gates, optimized) = gate_count(text, Pass::Fuse);
+        assert_eq!(gates, 1);
+        assert!(matches!(optimized.gates[0], Gate::Xnor { .. }));
+    }
+
+    #[test]
+    fn test_fuse_keeps_shared_wires() {
+        // The AND output is read twice, so the inverter stays
+        let text = "3 5\n1 2\n1 2\n\n2 1 0 1 2 AND\n1 1 2 3 INV\n2 1 2 0 4 XOR\n";
+        let (gates, _) = gate_count(text, Pass::Fuse);
+        assert_eq!(gates, 3);
+    }
+
+    #[test]
+    fn test_fuse_mux() {
+        // f ^ (s & (t ^ f)) with s, t, f on wires 0, 1, 2
+        let text = "3 6\n1 3\n1 1\n\n2 1 1 2 3 XOR\n2 1 0 3 4 AND\n2 1 2 4 5 XOR\n";
+        let circuit = CircuitParser::parse(text);
+        let (gates, optimized) = gate_count(text, Pass::Fuse);
+        assert_eq!(gates, 1);
+        assert_eq!(optimized.gates[0], Gate::Mux { select: 0, if_true: 1, if_false: 2, output: 3 });
+        // Two bootstraps instead of three
+        let bootstraps = |circuit: &Circuit| circuit.gates.iter().map(Gate::bootstraps).sum::<usize>();
+        assert_eq!(bootstraps(&optimized) + 1, bootstraps(&circuit));
+    }
+
+    #[test]
+    fn test_fuse_aes() {
+        let circuit = Circuit::aes_128();
+        let (optimized, report) = circuit.optimize(&[Pass::PushInv, Pass::Fuse]);
+        let (stats, fused) = (circuit.stats(), optimized.stats());
+
+        assert!(report.verified());
+        assert!(fused.inv_gates + fused.minv_invs < stats.inv_gates + stats.minv_invs);
+        assert!(fused.block_bootstraps <= stats.block_bootstraps);
+        assert_eq!(fused.and_depth, stats.and_depth);
+    }
+
+    #[test]
+    fn test_non_equivalent_pass_is_discarded() {
+        let circuit = CircuitParser::parse("2 4\n1 2\n1 1\n\n2 1 0 1 2 XOR\n1 1 2 3 INV\n");
+        // A broken pass turning the XOR into an AND, then a correct one
+        let (optimized, report) = circuit.optimize_with(&[Pass::CommonSubexpressions, Pass::DeadGates], |pass, circuit| {
+            let mut result = pass.run(circuit);
+            if *pass == Pass::CommonSubexpressions {
+                result.gates[0] = Gate::And { input1: 0, input2: 1, output: 2 };
+            }
+            result
+        });
+
+        assert_eq!(report.passes[0].discarded, Some(Discarded::NotEquivalent));
+        assert!(!report.passes[0].verified && report.passes[1].applied() && report.passes[1].verified);
+        assert!(report.verified());
+        assert!(equivalent(&circuit, &optimized, 16));
+        assert!(report.to_string().contains("NOT EQUIVALENT"));
+    }
+
+    #[test]
+    fn test_optimize_aes() {
+        let circuit = Circuit::aes_128();
+        let (optimized, report) = circuit.optimize(&Pass::ALL);
+
+        assert!(report.verified());
+        assert!(report.passes.iter().all(|pass| pass.gates_after <= pass.gates_before));
+        assert_eq!(optimized.inputs, circuit.inputs);
+        assert!(optimized.stats().block_bootstraps <= circuit.stats().block_bootstraps);
+
+        let key: [u8; 16] = core::array::from_fn(|i| i as u8);
+        let block = [0x3au8; 16];
+        assert_eq!(optimized.evaluate_aes(&key, &block), circuit.evaluate_aes(&key, &block));
+    }
+
+    #[test]
+    fn test_optimized_circuit_runs_in_bool_fhe_aes() {
+        use crate::backend::Cleartext;
+        use crate::circuit::{bytes_to_wires, wires_to_bytes};
+        use crate::params::ParameterSet;
+        use crate::BoolFheAes;
+
+        let (optimized, _) = Circuit::aes_128().optimize(&Pass::ALL);
+        let key: [u8; 16] = core::array::from_fn(|i| i as u8);
+        let block = [0x3au8; 16];
+        let expected = optimized.evaluate_aes(&key, &block);
+
+        let fhe_aes = BoolFheAes::from_circuit(Cleartext, ParameterSet::Default, optimized);
+        fhe_aes.expand_key(bytes_to_wires(&key).try_into().unwrap());
+        let output = fhe_aes.execute(bytes_to_wires(&block).try_into().unwrap());
+        assert_eq!(wires_to_bytes(&output), expected);
+    }
+}
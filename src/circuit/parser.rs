@@ -17,9 +17,8 @@ impl CircuitParser {
 
         let mut gates = Vec::with_capacity(gate_count);
 
-        // Skip input/output declarations
-        lines.next(); // INPUTS
-        lines.next(); // OUTPUTS
+        let inputs = parse_io(lines.next().expect("Missing input declaration"));
+        let outputs = parse_io(lines.next().expect("Missing output declaration"));
 
         for line in lines.filter(|l| !l.trim().is_empty()) {
             let gate = parse_line(line, &key_expand_reachable);
@@ -31,6 +30,8 @@ impl CircuitParser {
             gates,
             key_expand_reachable,
             output_end,
+            inputs,
+            outputs,
         }
     }
 }
@@ -43,6 +44,14 @@ fn parse_header(header: &str) -> (usize, u32) {
     )
 }
 
+/// Parses an input or output declaration: a count followed by the bit width of each
+fn parse_io(line: &str) -> Vec<u32> {
+    line.split_whitespace()
+        .skip(1)
+        .map(|width| width.parse().expect("Invalid input/output width"))
+        .collect()
+}
+
 fn parse_line(line: &str, reachable: &HashSet<u32>) -> Gate {
     let parts: Vec<&str> = line.split_whitespace().collect();
     let op = parts.last().unwrap();
@@ -51,7 +60,30 @@ fn parse_line(line: &str, reachable: &HashSet<u32>) -> Gate {
         "AND" => parse_and_gate(&parts),
         "INV" => parse_inv_gate(&parts),
         "XOR" => parse_xor_gate(&parts),
-        "MAND" => parse_mand_gate(&parts),
+        "MAND" => Gate::Mand { gates: parse_batch(&parts) },
+        "MXOR" => Gate::Mxor { gates: parse_batch(&parts) },
+        "MINV" => parse_minv_gate(&parts),
+        "OR" => {
+            let (input1, input2, output) = parse_binary(&parts);
+            Gate::Or { input1, input2, output }
+        }
+        "NAND" => {
+            let (input1, input2, output) = parse_binary(&parts);
+            Gate::Nand { input1, input2, output }
+        }
+        "NOR" => {
+            let (input1, input2, output) = parse_binary(&parts);
+            Gate::Nor { input1, input2, output }
+        }
+        "XNOR" => {
+            let (input1, input2, output) = parse_binary(&parts);
+            Gate::Xnor { input1, input2, output }
+        }
+        "MUX" => parse_mux_gate(&parts),
+        "EQW" => Gate::Eqw {
+            input: parts[2].parse().unwrap(),
+            output: parts[3].parse().unwrap(),
+        },
         _ => panic!("Unknown gate operation: {}", op),
     }
 }
@@ -79,7 +111,23 @@ fn parse_xor_gate(parts: &[&str]) -> Gate {
     }
 }
 
-fn parse_mand_gate(parts: &[&str]) -> Gate {
+fn parse_binary(parts: &[&str]) -> (u32, u32, u32) {
+    (parts[2].parse().unwrap(), parts[3].parse().unwrap(), parts[4].parse().unwrap())
+}
+
+/// Parses a Bristol Fashion MUX line, `3 1 select if_true if_false output MUX`
+fn parse_mux_gate(parts: &[&str]) -> Gate {
+    Gate::Mux {
+        select: parts[2].parse().unwrap(),
+        if_true: parts[3].parse().unwrap(),
+        if_false: parts[4].parse().unwrap(),
+        output: parts[5].parse().unwrap(),
+    }
+}
+
+/// Parses the gates of a MAND or MXOR line: all first inputs, then all second
+/// inputs, then all outputs
+fn parse_batch(parts: &[&str]) -> Vec<(u32, u32, u32)> {
     let gate_count: usize = parts[1].parse().unwrap();
     let mut gates = Vec::with_capacity(gate_count);
 
@@ -90,28 +138,24 @@ fn parse_mand_gate(parts: &[&str]) -> Gate {
         gates.push((input1, input2, output));
     }
 
-    Gate::Mand { gates }
+    gates
 }
 
-fn update_reachability(gate: &Gate, reachable: &mut HashSet<u32>) {
-    match gate {
-        Gate::And { input1, input2, output } |
-        Gate::Xor { input1, input2, output } => {
-            if reachable.contains(input1) && reachable.contains(input2) {
-                reachable.insert(*output);
-            }
-        }
-        Gate::Inv { input, output } => {
-            if reachable.contains(input) {
-                reachable.insert(*output);
-            }
-        }
-        Gate::Mand { gates } => {
-            for (in1, in2, out) in gates {
-                if reachable.contains(in1) && reachable.contains(in2) {
-                    reachable.insert(*out);
-                }
-            }
+/// Parses a MINV line: all inputs, then all outputs
+fn parse_minv_gate(parts: &[&str]) -> Gate {
+    let gate_count: usize = parts[1].parse().unwrap();
+    let gates = (0..gate_count)
+        .map(|i| (parts[2 + i].parse().unwrap(), parts[2 + gate_count + i].parse().unwrap()))
+        .collect();
+
+    Gate::Minv { gates }
+}
+
+/// Marks the outputs of `gate` whose inputs are all key-reachable
+pub(super) fn update_reachability(gate: &Gate, reachable: &mut HashSet<u32>) {
+    for (inputs, output) in gate.wires() {
+        if inputs.iter().all(|wire| reachable.contains(wire)) {
+            reachable.insert(output);
         }
     }
 }
@@ -126,6 +170,12 @@ mod tests {
         assert_eq!(parse_header(header), (36663, 36919));
     }
 
+    #[test]
+    fn test_parse_io() {
+        assert_eq!(parse_io("2 128 128 "), vec![128, 128]);
+        assert_eq!(parse_io("1 128"), vec![128]);
+    }
+
     #[test]
     fn test_parse_mand_gate() {
         let line = "4 2 0 2 1 3 4 5 MAND";
@@ -137,4 +187,42 @@ mod tests {
             panic!("Expected MAND gate");
         }
     }
+
+    #[test]
+    fn test_parse_xor_inv_batches() {
+        let gate = parse_line("4 2 0 1 2 3 4 5 MXOR", &HashSet::new());
+        assert_eq!(gate, Gate::Mxor { gates: vec![(0, 2, 4), (1, 3, 5)] });
+
+        let gate = parse_line("2 2 0 1 4 5 MINV", &HashSet::new());
+        assert_eq!(gate, Gate::Minv { gates: vec![(0, 4), (1, 5)] });
+    }
+
+    #[test]
+    fn test_parse_extended_gate_set() {
+        let parse = |line| parse_line(line, &HashSet::new());
+        assert_eq!(parse("2 1 0 1 2 OR"), Gate::Or { input1: 0, input2: 1, output: 2 });
+        assert_eq!(parse("2 1 0 1 2 NAND"), Gate::Nand { input1: 0, input2: 1, output: 2 });
+        assert_eq!(parse("2 1 0 1 2 NOR"), Gate::Nor { input1: 0, input2: 1, output: 2 });
+        assert_eq!(parse("2 1 0 1 2 XNOR"), Gate::Xnor { input1: 0, input2: 1, output: 2 });
+        assert_eq!(
+            parse("3 1 0 1 2 3 MUX"),
+            Gate::Mux { select: 0, if_true: 1, if_false: 2, output: 3 }
+        );
+        assert_eq!(parse("1 1 0 1 EQW"), Gate::Eqw { input: 0, output: 1 });
+    }
+
+    #[test]
+    fn test_evaluate_extended_gate_set() {
+        // Every gate on the inputs 0 and 1, with a MUX selecting on input 2
+        let circuit = CircuitParser::parse(
+            "7 10\n2 2 1\n1 7\n\n2 1 0 1 3 OR\n2 1 0 1 4 NAND\n2 1 0 1 5 NOR\n\
+             2 1 0 1 6 XNOR\n3 1 2 0 1 7 MUX\n1 1 0 8 EQW\n2 1 0 1 9 AND\n",
+        );
+        for i in 0..8 {
+            let (a, b, s) = (i & 1 == 1, i & 2 == 2, i & 4 == 4);
+            let outputs = circuit.evaluate_outputs(&[a, b, s]);
+            let mux = if s { a } else { b };
+            assert_eq!(outputs, [a | b, !(a & b), !(a | b), !(a ^ b), mux, a, a & b]);
+        }
+    }
 }
@@ -0,0 +1,191 @@
+//! Circuit statistics and cost model
+
+use super::{depth, Circuit, Gate};
+use serde::Serialize;
+use std::fmt;
+
+/// Gate counts, depths and estimated cost of a circuit
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CircuitStats {
+    /// Standalone AND gates
+    pub and_gates: usize,
+    /// XOR gates
+    pub xor_gates: usize,
+    /// Inverters
+    pub inv_gates: usize,
+    /// MAND groups
+    pub mand_gates: usize,
+    /// AND gates grouped inside MAND gates
+    pub mand_ands: usize,
+    /// MXOR groups
+    pub mxor_gates: usize,
+    /// XOR gates grouped inside MXOR gates
+    pub mxor_xors: usize,
+    /// MINV groups
+    pub minv_gates: usize,
+    /// Inverters grouped inside MINV gates
+    pub minv_invs: usize,
+    /// OR gates
+    pub or_gates: usize,
+    /// NAND gates
+    pub nand_gates: usize,
+    /// NOR gates
+    pub nor_gates: usize,
+    /// XNOR gates
+    pub xnor_gates: usize,
+    /// MUX gates
+    pub mux_gates: usize,
+    /// EQW (wire copy) gates
+    pub eqw_gates: usize,
+    /// Multiplicative (AND) depth
+    pub and_depth: u32,
+    /// Depth counting every gate
+    pub total_depth: u32,
+    /// Number of elementary gates on each level, starting at level 1
+    pub level_widths: Vec<usize>,
+    /// Elementary gates computed once during key expansion
+    pub key_reachable_gates: usize,
+    /// Bootstraps per block under the boolean backend: one per binary gate that is
+    /// not key-reachable and two per MUX (INV and EQW need no bootstrap)
+    pub block_bootstraps: usize,
+}
+
+impl Circuit {
+    /// Computes gate counts, depths and the per-block bootstrap estimate
+    pub fn stats(&self) -> CircuitStats {
+        let mut stats = CircuitStats {
+            and_gates: 0,
+            xor_gates: 0,
+            inv_gates: 0,
+            mand_gates: 0,
+            mand_ands: 0,
+            mxor_gates: 0,
+            mxor_xors: 0,
+            minv_gates: 0,
+            minv_invs: 0,
+            or_gates: 0,
+            nand_gates: 0,
+            nor_gates: 0,
+            xnor_gates: 0,
+            mux_gates: 0,
+            eqw_gates: 0,
+            and_depth: 0,
+            total_depth: 0,
+            level_widths: Vec::new(),
+            key_reachable_gates: 0,
+            block_bootstraps: 0,
+        };
+
+        let levels = depth::wire_levels(&self.gates, self.output_end);
+        for gate in &self.gates {
+            match gate {
+                Gate::And { .. } => stats.and_gates += 1,
+                Gate::Xor { .. } => stats.xor_gates += 1,
+                Gate::Inv { .. } => stats.inv_gates += 1,
+                Gate::Mand { gates } => {
+                    stats.mand_gates += 1;
+                    stats.mand_ands += gates.len();
+                }
+                Gate::Mxor { gates } => {
+                    stats.mxor_gates += 1;
+                    stats.mxor_xors += gates.len();
+                }
+                Gate::Minv { gates } => {
+                    stats.minv_gates += 1;
+                    stats.minv_invs += gates.len();
+                }
+                Gate::Or { .. } => stats.or_gates += 1,
+                Gate::Nand { .. } => stats.nand_gates += 1,
+                Gate::Nor { .. } => stats.nor_gates += 1,
+                Gate::Xnor { .. } => stats.xnor_gates += 1,
+                Gate::Mux { .. } => stats.mux_gates += 1,
+                Gate::Eqw { .. } => stats.eqw_gates += 1,
+            }
+
+            for (_, output) in gate.wires() {
+                let level = levels[output as usize];
+                if stats.level_widths.len() < level as usize {
+                    stats.level_widths.resize(level as usize, 0);
+                }
+                stats.level_widths[level as usize - 1] += 1;
+
+                if self.key_expand_reachable.contains(&output) {
+                    stats.key_reachable_gates += 1;
+                } else {
+                    stats.block_bootstraps += gate.bootstraps();
+                }
+            }
+        }
+
+        stats.total_depth = stats.level_widths.len() as u32;
+        stats.and_depth = depth::and_depths(&self.gates, self.output_end)
+            .into_iter()
+            .max()
+            .unwrap_or(0);
+        stats
+    }
+}
+
+impl fmt::Display for CircuitStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "AND gates:            {}", self.and_gates)?;
+        writeln!(f, "XOR gates:            {}", self.xor_gates)?;
+        writeln!(f, "INV gates:            {}", self.inv_gates)?;
+        writeln!(f, "MAND gates:           {} ({} ANDs)", self.mand_gates, self.mand_ands)?;
+        if self.mxor_gates + self.minv_gates > 0 {
+            writeln!(f, "MXOR gates:           {} ({} XORs)", self.mxor_gates, self.mxor_xors)?;
+            writeln!(f, "MINV gates:           {} ({} INVs)", self.minv_gates, self.minv_invs)?;
+        }
+        for (name, count) in [
+            ("OR", self.or_gates),
+            ("NAND", self.nand_gates),
+            ("NOR", self.nor_gates),
+            ("XNOR", self.xnor_gates),
+            ("MUX", self.mux_gates),
+            ("EQW", self.eqw_gates),
+        ] {
+            if count > 0 {
+                writeln!(f, "{:<22}{}", format!("{} gates:", name), count)?;
+            }
+        }
+        writeln!(f, "AND depth:            {}", self.and_depth)?;
+        writeln!(f, "Total depth:          {}", self.total_depth)?;
+        let widths: Vec<String> = self.level_widths.iter().map(usize::to_string).collect();
+        writeln!(f, "Level widths:         [{}]", widths.join(", "))?;
+        writeln!(f, "Key-reachable gates:  {}", self.key_reachable_gates)?;
+        write!(f, "Bootstraps per block: {}", self.block_bootstraps)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{Cleartext, Tracer};
+    use crate::BoolFheAes;
+
+    #[test]
+    fn test_aes_stats() {
+        let circuit = Circuit::aes_128();
+        let stats = circuit.stats();
+
+        assert_eq!(stats.and_gates, 0);
+        assert_eq!(stats.mand_gates, 60);
+        assert_eq!(stats.and_depth, 60);
+        assert_eq!(stats.level_widths.len() as u32, stats.total_depth);
+        assert_eq!(
+            stats.level_widths.iter().sum::<usize>(),
+            stats.and_gates + stats.xor_gates + stats.inv_gates + stats.mand_ands
+        );
+    }
+
+    #[test]
+    fn test_block_bootstraps_match_tracer() {
+        let stats = Circuit::aes_128().stats();
+
+        let fhe_aes = BoolFheAes::new(Tracer::new(Cleartext));
+        fhe_aes.expand_key([false; 128]);
+        fhe_aes.backend().reset();
+        fhe_aes.execute([false; 128]);
+        assert_eq!(stats.block_bootstraps, fhe_aes.backend().counts().bootstraps());
+    }
+}
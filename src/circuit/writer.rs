@@ -0,0 +1,105 @@
+//! Writer for the extended Bristol circuit format
+
+use super::{Circuit, Gate};
+use std::fmt::Write;
+
+impl Circuit {
+    /// Serializes the circuit back to extended Bristol text, as read by `CircuitParser`
+    pub fn to_bristol(&self) -> String {
+        let mut text = String::new();
+
+        writeln!(text, "{} {}", self.gates.len(), self.output_end).unwrap();
+        writeln!(text, "{}", format_io(&self.inputs)).unwrap();
+        writeln!(text, "{}", format_io(&self.outputs)).unwrap();
+        writeln!(text).unwrap();
+
+        for gate in &self.gates {
+            writeln!(text, "{}", format_gate(gate)).unwrap();
+        }
+        text
+    }
+}
+
+fn format_io(widths: &[u32]) -> String {
+    let mut line = widths.len().to_string();
+    for width in widths {
+        write!(line, " {}", width).unwrap();
+    }
+    line
+}
+
+/// Formats a gate line: input count, output count, inputs, outputs, opcode
+fn format_gate(gate: &Gate) -> String {
+    match gate {
+        Gate::And { input1, input2, output }
+        | Gate::Xor { input1, input2, output }
+        | Gate::Or { input1, input2, output }
+        | Gate::Nand { input1, input2, output }
+        | Gate::Nor { input1, input2, output }
+        | Gate::Xnor { input1, input2, output } => {
+            format!("2 1 {} {} {} {}", input1, input2, output, gate.name())
+        }
+        Gate::Inv { input, output } | Gate::Eqw { input, output } => {
+            format!("1 1 {} {} {}", input, output, gate.name())
+        }
+        Gate::Mux { select, if_true, if_false, output } => {
+            format!("3 1 {} {} {} {} MUX", select, if_true, if_false, output)
+        }
+        Gate::Mand { gates } | Gate::Mxor { gates } => {
+            let mut line = format!("{} {}", 2 * gates.len(), gates.len());
+            for (input1, _, _) in gates {
+                write!(line, " {}", input1).unwrap();
+            }
+            for (_, input2, _) in gates {
+                write!(line, " {}", input2).unwrap();
+            }
+            for (_, _, output) in gates {
+                write!(line, " {}", output).unwrap();
+            }
+            write!(line, " {}", gate.name()).unwrap();
+            line
+        }
+        Gate::Minv { gates } => {
+            let mut line = format!("{} {}", gates.len(), gates.len());
+            for (input, _) in gates {
+                write!(line, " {}", input).unwrap();
+            }
+            for (_, output) in gates {
+                write!(line, " {}", output).unwrap();
+            }
+            line.push_str(" MINV");
+            line
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::circuit::CircuitParser;
+
+    #[test]
+    fn test_bristol_round_trip() {
+        let circuit = Circuit::aes_128();
+        let reparsed = CircuitParser::parse(&circuit.to_bristol());
+
+        assert_eq!(reparsed.gates, circuit.gates);
+        assert_eq!(reparsed.output_end, circuit.output_end);
+        assert_eq!(reparsed.inputs, vec![128, 128]);
+        assert_eq!(reparsed.outputs, vec![128]);
+        assert_eq!(reparsed.key_expand_reachable, circuit.key_expand_reachable);
+    }
+
+    #[test]
+    fn test_format_mand_gate() {
+        let gate = Gate::Mand { gates: vec![(0, 2, 4), (1, 3, 5)] };
+        assert_eq!(format_gate(&gate), "4 2 0 1 2 3 4 5 MAND");
+    }
+
+    #[test]
+    fn test_format_mux_and_eqw() {
+        let gate = Gate::Mux { select: 0, if_true: 1, if_false: 2, output: 3 };
+        assert_eq!(format_gate(&gate), "3 1 0 1 2 3 MUX");
+        assert_eq!(format_gate(&Gate::Eqw { input: 0, output: 1 }), "1 1 0 1 EQW");
+    }
+}
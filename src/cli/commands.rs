@@ -1,11 +1,20 @@
-use clap::{Parser, Subcommand, Args};
+use clap::{Parser, Subcommand, Args, ValueEnum};
 use anyhow::{Context, Result};
+use indicatif::{ProgressBar, ProgressStyle};
 use log::info;
+use signal_hook::consts::SIGINT;
+use std::path::PathBuf;
+use std::sync::Arc;
 use std::time::Instant;
 use tfhe::boolean::prelude::*;
 use crate::{
     BoolFheAes,
+    fhe_aes::{CancellationToken, Progress, SchedulerConfig},
+    circuit::{AesCircuit, Circuit, CircuitParser, DotClustering, Pass},
     encrypt_reference_aes128,
+    params::ParameterSet,
+    client::Client,
+    server::{Endpoint, Server, DEFAULT_MAX_SESSIONS},
     utils::constants::{AES_128_KEY_SIZE, AES_BLOCK_SIZE}
 };
 
@@ -22,6 +31,26 @@ pub struct EncryptArgs {
     
     #[arg(short, long)]
     slow: bool,
+
+    /// Parameter set name or path to a .toml/.json parameter file
+    #[arg(short, long, default_value = "default")]
+    params: ParameterSet,
+
+    /// Write the encrypted output blocks to this file
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// AES-128 circuit to evaluate: scale-mamba, bp or bp-depth16
+    #[arg(long, default_value = "scale-mamba")]
+    circuit: AesCircuit,
+
+    /// Profile the evaluation and write the timings as JSON to this file
+    #[arg(long)]
+    profile: Option<PathBuf>,
+
+    /// Write the profile as folded stacks, for flamegraph.pl or inferno, to this file
+    #[arg(long)]
+    profile_folded: Option<PathBuf>,
 }
 
 #[derive(Args, Debug)]
@@ -36,30 +65,255 @@ pub struct VerifyArgs {
     samples: usize,
 }
 
+#[derive(Args, Debug)]
+pub struct NoiseArgs {
+    #[arg(short, long, default_value = "000102030405060708090a0b0c0d0e0f")]
+    key: String,
+
+    #[arg(short, long)]
+    samples: usize,
+
+    /// Parameter set name or path to a .toml/.json parameter file
+    #[arg(short, long, default_value = "default")]
+    params: ParameterSet,
+
+    /// AES-128 circuit to evaluate: scale-mamba, bp or bp-depth16
+    #[arg(long, default_value = "scale-mamba")]
+    circuit: AesCircuit,
+}
+
+#[derive(Args, Debug)]
+pub struct ServeArgs {
+    /// Address to listen on: host:port or unix:path
+    #[arg(short, long, default_value = "127.0.0.1:7878")]
+    listen: Endpoint,
+
+    /// Parameter set name or path to a .toml/.json parameter file
+    #[arg(short, long, default_value = "default")]
+    params: ParameterSet,
+
+    /// AES-128 circuit to evaluate: scale-mamba, bp or bp-depth16
+    #[arg(long, default_value = "scale-mamba")]
+    circuit: AesCircuit,
+
+    /// Most blocks, across sessions, evaluated together in one batch
+    #[arg(long, default_value_t = SchedulerConfig::default().max_batch_blocks)]
+    max_batch_blocks: usize,
+
+    /// Most blocks a connection may have queued, across its sessions
+    #[arg(long, default_value_t = SchedulerConfig::default().max_queued_blocks_per_tenant)]
+    max_queued_blocks: usize,
+
+    /// Most sessions open at once across all connections
+    #[arg(long, default_value_t = DEFAULT_MAX_SESSIONS)]
+    max_sessions: usize,
+}
+
+#[derive(Args, Debug)]
+pub struct ClientArgs {
+    /// Server address: host:port or unix:path
+    #[arg(long, default_value = "127.0.0.1:7878")]
+    connect: Endpoint,
+
+    /// Parameter set the server runs with
+    #[arg(short, long, default_value = "default")]
+    params: ParameterSet,
+
+    /// 128-bit AES key (hex), uploaded encrypted
+    #[arg(short, long)]
+    key: String,
+
+    #[command(subcommand)]
+    command: ClientCommands,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ClientCommands {
+    /// Request CTR keystream blocks and check them against the reference AES
+    Keystream {
+        /// 128-bit initial counter (hex), uploaded encrypted
+        #[arg(short, long)]
+        iv: String,
+
+        #[arg(short, long, default_value_t = 1)]
+        count: usize,
+    },
+
+    /// Transcipher an AES-CTR ciphertext and print the decrypted plaintext (hex)
+    Transcipher {
+        /// 128-bit initial counter (hex), sent in the clear
+        #[arg(long)]
+        counter: String,
+
+        /// AES-CTR ciphertext (hex)
+        #[arg(long)]
+        ciphertext: String,
+    },
+}
+
+#[derive(Args, Debug)]
+pub struct CircuitArgs {
+    #[command(subcommand)]
+    command: CircuitCommands,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum CircuitCommands {
+    /// Print gate counts, depths and cost estimates
+    Stats(CircuitStatsArgs),
+
+    /// Export a circuit as Graphviz DOT, JSON or extended Bristol text
+    Export(CircuitExportArgs),
+
+    /// Optimize a circuit and write it in extended Bristol format
+    Optimize(CircuitOptimizeArgs),
+
+    /// Compare gate counts and costs of the available AES-128 circuits
+    Compare(CircuitCompareArgs),
+
+    /// Print the fingerprint of a circuit
+    Fingerprint(CircuitFingerprintArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct CircuitStatsArgs {
+    /// Circuit file in extended Bristol format (defaults to the embedded AES-128 circuit)
+    #[arg(short, long)]
+    file: Option<PathBuf>,
+
+    /// Print the statistics as JSON
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct CircuitExportArgs {
+    /// Circuit file in extended Bristol format (defaults to the embedded AES-128 circuit)
+    #[arg(short, long)]
+    file: Option<PathBuf>,
+
+    #[arg(long, value_enum, default_value = "dot")]
+    format: ExportFormat,
+
+    /// Cluster DOT nodes by AES round or dependency level
+    #[arg(long, value_enum, default_value = "none")]
+    cluster: DotClustering,
+
+    /// Write to this file instead of stdout
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+pub struct CircuitOptimizeArgs {
+    /// Circuit file in extended Bristol format (defaults to the embedded AES-128 circuit)
+    #[arg(short, long)]
+    file: Option<PathBuf>,
+
+    /// Comma-separated passes to run: dead-gates, cse, double-inv, push-inv, fuse, group
+    #[arg(
+        long,
+        value_delimiter = ',',
+        default_value = "dead-gates,cse,double-inv,push-inv,fuse,group"
+    )]
+    passes: Vec<Pass>,
+
+    /// Write the optimized circuit to this file
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+pub struct CircuitCompareArgs {
+    /// Print the statistics as JSON
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct CircuitFingerprintArgs {
+    /// Circuit file in extended Bristol format
+    #[arg(short, long, conflicts_with = "circuit")]
+    file: Option<PathBuf>,
+
+    /// AES-128 circuit to fingerprint: scale-mamba, bp or bp-depth16
+    #[arg(long, default_value = "scale-mamba")]
+    circuit: AesCircuit,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum ExportFormat {
+    Dot,
+    Json,
+    Bristol,
+}
+
 pub fn handle_encrypt(args: EncryptArgs) -> Result<()> {
     let (key_bytes, iv_bytes) = parse_inputs(&args.key, &args.iv)
         .context("Failed to parse inputs")?;
 
-    info!("Starting FHE-AES encryption with {} blocks", args.count);
+    info!(
+        "Starting FHE-AES encryption with {} blocks using parameter set {} and the {} circuit",
+        args.count, args.params, args.circuit
+    );
     
-    let (client_key, server_key) = tfhe::boolean::gen_keys();
-    let fhe_aes = BoolFheAes::new(server_key);
+    let cancellation = cancel_on_ctrl_c()?;
+    let progress_bar = encryption_progress_bar();
+    let (client_key, server_key) = args.params.gen_keys();
+    let mut fhe_aes = BoolFheAes::with_circuit(server_key, args.params, args.circuit);
+    if args.profile.is_some() || args.profile_folded.is_some() {
+        fhe_aes = fhe_aes.with_profiler();
+    }
+    let fhe_aes = fhe_aes
+        .with_cancellation(cancellation)
+        .with_progress({
+            let progress_bar = progress_bar.clone();
+            move |progress| update_progress_bar(&progress_bar, progress)
+        });
     
     let fhe_key = BoolFheAes::encrypt_key(&client_key, &key_bytes);
     let fhe_iv = BoolFheAes::encrypt_iv(&client_key, &iv_bytes);
 
     // Key expansion
     let start_time = Instant::now();
-    fhe_aes.expand_key(fhe_key);
+    fhe_aes.try_expand_key(fhe_key).context("Key expansion interrupted")?;
     let key_expansion_time = start_time.elapsed();
     info!("Key expansion time: {:?}", key_expansion_time);
 
     // Encryption
     let encrypt_start = Instant::now();
-    let outputs = fhe_aes.aes_ctr_blocks(fhe_iv, args.count);
+    let outputs = fhe_aes.try_aes_ctr_blocks(fhe_iv, args.count);
+    if outputs.is_err() {
+        progress_bar.abandon();
+    } else {
+        progress_bar.finish();
+    }
+    let outputs = outputs.context("Encryption interrupted")?;
     let encrypt_time = encrypt_start.elapsed();
     info!("Encryption time for {} blocks: {:?}", args.count, encrypt_time);
 
+    if let Some(profile) = fhe_aes.profile() {
+        info!(
+            "Thread utilization: {:.1}%, spinning: {:?}",
+            100.0 * profile.utilization,
+            std::time::Duration::from_nanos(profile.spin_nanos)
+        );
+        if let Some(path) = &args.profile {
+            std::fs::write(path, profile.to_json())
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+        }
+        if let Some(path) = &args.profile_folded {
+            std::fs::write(path, profile.to_folded())
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+        }
+    }
+
+    if let Some(path) = &args.output {
+        std::fs::write(path, fhe_aes.export_outputs(&outputs)?)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        info!("Wrote encrypted outputs to {}", path.display());
+    }
+
     // Verification
     let decrypted_outputs: Vec<[u8; AES_BLOCK_SIZE]> = outputs
         .iter()
@@ -85,6 +339,29 @@ pub fn handle_encrypt(args: EncryptArgs) -> Result<()> {
     Ok(())
 }
 
+/// Returns a token cancelled by the first Ctrl-C; a second one exits immediately
+fn cancel_on_ctrl_c() -> Result<CancellationToken> {
+    let cancellation = CancellationToken::new();
+    signal_hook::flag::register_conditional_shutdown(SIGINT, 1, cancellation.flag())
+        .and_then(|_| signal_hook::flag::register(SIGINT, cancellation.flag()))
+        .context("Failed to install the Ctrl-C handler")?;
+    Ok(cancellation)
+}
+
+fn encryption_progress_bar() -> ProgressBar {
+    let style = ProgressStyle::with_template(
+        "[{elapsed_precise}] {bar:40} {pos}/{len} gates, {msg} (eta {eta})",
+    )
+    .expect("Invalid progress bar template");
+    ProgressBar::new(0).with_style(style)
+}
+
+fn update_progress_bar(progress_bar: &ProgressBar, progress: Progress) {
+    progress_bar.set_length(progress.gates_total as u64);
+    progress_bar.set_position(progress.gates_done as u64);
+    progress_bar.set_message(format!("{}/{} blocks", progress.blocks_done, progress.blocks_total));
+}
+
 pub fn handle_benchmark(args: BenchmarkArgs) -> Result<()> {
     info!("Starting benchmarking with {} iterations", args.iterations);
     // Benchmarking implementation
@@ -97,16 +374,183 @@ pub fn handle_verify(args: VerifyArgs) -> Result<()> {
     Ok(())
 }
 
-fn parse_inputs(key: &str, iv: &str) -> Result<([u8; AES_128_KEY_SIZE], [u8; AES_BLOCK_SIZE])> {
-    let key_bytes = hex::decode(key)
+pub fn handle_noise(args: NoiseArgs) -> Result<()> {
+    let key_bytes: [u8; AES_128_KEY_SIZE] = hex::decode(&args.key)
         .context("Failed to decode key")?
         .try_into()
         .map_err(|_| anyhow::anyhow!("Invalid key length"))?;
 
-    let iv_bytes = hex::decode(iv)
-        .context("Failed to decode IV")?
+    info!("Measuring noise over {} blocks using parameter set {}", args.samples, args.params);
+
+    let (client_key, server_key) = args.params.gen_keys();
+    let fhe_aes = BoolFheAes::with_circuit(server_key, args.params, args.circuit);
+
+    let start_time = Instant::now();
+    let report = fhe_aes.measure_noise(&client_key, &key_bytes, args.samples);
+    info!("Measurement time: {:?}", start_time.elapsed());
+    info!("{}", report);
+
+    Ok(())
+}
+
+pub fn handle_serve(args: ServeArgs) -> Result<()> {
+    info!(
+        "Serving the {} circuit for parameter set {}",
+        args.circuit, args.params
+    );
+    let config = SchedulerConfig {
+        max_batch_blocks: args.max_batch_blocks,
+        max_queued_blocks_per_tenant: args.max_queued_blocks,
+    };
+    let server = Server::<ServerKey>::with_scheduler_config(args.params, args.circuit, config)
+        .with_max_sessions(args.max_sessions);
+    let server = Arc::new(server);
+    server.serve(&args.listen)
+}
+
+pub fn handle_client(args: ClientArgs) -> Result<()> {
+    let key_bytes = parse_key(&args.key)?;
+
+    let start_time = Instant::now();
+    let mut client = Client::<ServerKey>::connect(&args.connect, args.params)?;
+    client.upload_key(&key_bytes)?;
+    info!(
+        "Opened session {} on {} (circuit {}) in {:?}",
+        client.session(),
+        args.connect,
+        client.circuit_fingerprint(),
+        start_time.elapsed()
+    );
+
+    match args.command {
+        ClientCommands::Keystream { iv, count } => {
+            let iv_bytes = parse_iv(&iv)?;
+            let start_time = Instant::now();
+            let blocks = client.keystream(&iv_bytes, count)?;
+            info!("Received {} keystream blocks in {:?}", count, start_time.elapsed());
+
+            let counter = u128::from_be_bytes(iv_bytes);
+            let counters = (0..count).map(|i| counter.wrapping_add(i as u128).to_be_bytes()).collect();
+            let expected = encrypt_reference_aes128(counters, key_bytes);
+            for (block, expected) in blocks.iter().zip(&expected) {
+                println!("{}", hex::encode(block));
+                anyhow::ensure!(block.as_slice() == expected.as_slice(), "Keystream block does not match the reference AES");
+            }
+            info!("Successfully verified all {} blocks", count);
+        }
+        ClientCommands::Transcipher { counter, ciphertext } => {
+            let counter = parse_iv(&counter)?;
+            let ciphertext = hex::decode(ciphertext).context("Failed to decode ciphertext")?;
+            let start_time = Instant::now();
+            let plaintext = client.transcipher(&counter, &ciphertext)?;
+            info!("Transciphered {} bytes in {:?}", ciphertext.len(), start_time.elapsed());
+            println!("{}", hex::encode(plaintext));
+        }
+    }
+    Ok(())
+}
+
+pub fn handle_circuit(args: CircuitArgs) -> Result<()> {
+    match args.command {
+        CircuitCommands::Stats(args) => {
+            let circuit = load_circuit(args.file.as_ref())?;
+            let stats = circuit.stats();
+            if args.json {
+                println!("{}", serde_json::to_string_pretty(&stats)?);
+            } else {
+                println!("{}", stats);
+            }
+        }
+        CircuitCommands::Export(args) => {
+            let circuit = load_circuit(args.file.as_ref())?;
+            let text = match args.format {
+                ExportFormat::Dot => circuit.to_dot(args.cluster),
+                ExportFormat::Json => circuit.to_json(),
+                ExportFormat::Bristol => circuit.to_bristol(),
+            };
+            match &args.output {
+                Some(path) => std::fs::write(path, text)
+                    .with_context(|| format!("Failed to write {}", path.display()))?,
+                None => print!("{}", text),
+            }
+        }
+        CircuitCommands::Optimize(args) => {
+            let circuit = load_circuit(args.file.as_ref())?;
+            let (optimized, report) = circuit.optimize(&args.passes);
+            print!("{}", report);
+            anyhow::ensure!(report.verified(), "Optimized circuit is not equivalent to the input");
+
+            if let Some(path) = &args.output {
+                std::fs::write(path, optimized.to_bristol())
+                    .with_context(|| format!("Failed to write {}", path.display()))?;
+            }
+        }
+        CircuitCommands::Compare(args) => {
+            let stats: Vec<_> = AesCircuit::ALL
+                .iter()
+                .map(|circuit| (circuit.name(), circuit.circuit().stats()))
+                .collect();
+            if args.json {
+                let stats: std::collections::BTreeMap<_, _> = stats.into_iter().collect();
+                println!("{}", serde_json::to_string_pretty(&stats)?);
+                return Ok(());
+            }
+
+            println!(
+                "{:<12} {:>6} {:>6} {:>6} {:>9} {:>11} {:>13} {:>10}",
+                "circuit", "AND", "XOR", "INV", "AND depth", "total depth", "key-reachable", "bootstraps"
+            );
+            for (name, stats) in stats {
+                println!(
+                    "{:<12} {:>6} {:>6} {:>6} {:>9} {:>11} {:>13} {:>10}",
+                    name,
+                    stats.and_gates + stats.mand_ands,
+                    stats.xor_gates + stats.mxor_xors + stats.xnor_gates,
+                    stats.inv_gates + stats.minv_invs,
+                    stats.and_depth,
+                    stats.total_depth,
+                    stats.key_reachable_gates,
+                    stats.block_bootstraps
+                );
+            }
+        }
+        CircuitCommands::Fingerprint(args) => {
+            let circuit = match &args.file {
+                Some(path) => load_circuit(Some(path))?,
+                None => args.circuit.circuit(),
+            };
+            println!("{}", circuit.fingerprint());
+        }
+    }
+    Ok(())
+}
+
+/// Loads a circuit file, or the embedded AES-128 circuit if no file is given
+fn load_circuit(file: Option<&PathBuf>) -> Result<Circuit> {
+    match file {
+        Some(path) => {
+            let text = std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read circuit {}", path.display()))?;
+            Ok(CircuitParser::parse(&text))
+        }
+        None => Ok(Circuit::aes_128()),
+    }
+}
+
+fn parse_inputs(key: &str, iv: &str) -> Result<([u8; AES_128_KEY_SIZE], [u8; AES_BLOCK_SIZE])> {
+    Ok((parse_key(key)?, parse_iv(iv)?))
+}
+
+fn parse_key(key: &str) -> Result<[u8; AES_128_KEY_SIZE]> {
+    hex::decode(key)
+        .context("Failed to decode key")?
         .try_into()
-        .map_err(|_| anyhow::anyhow!("Invalid IV length"))?;
+        .map_err(|_| anyhow::anyhow!("Invalid key length"))
+}
 
-    Ok((key_bytes, iv_bytes))
+fn parse_iv(iv: &str) -> Result<[u8; AES_BLOCK_SIZE]> {
+    hex::decode(iv)
+        .context("Failed to decode IV")?
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("Invalid IV length"))
 }
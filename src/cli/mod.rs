@@ -2,7 +2,7 @@
 
 mod commands;
 use clap::{Parser, Subcommand, Args};
-use anyhow::Result;
+use anyhow::{Context, Result};
 #[derive(Parser)]
 #[command(name = "fhe-aes")]
 #[command(about = "FHE AES Implementation", version, long_about = None)]
@@ -21,15 +21,32 @@ pub enum Commands {
     
     /// Verify against reference implementation
     Verify(commands::VerifyArgs),
+
+    /// Measure bootstrap failures per gate type
+    Noise(commands::NoiseArgs),
+
+    /// Inspect boolean circuits
+    Circuit(commands::CircuitArgs),
+
+    /// Serve FHE-AES evaluations over TCP or a Unix socket
+    Serve(commands::ServeArgs),
+
+    /// Submit jobs to a running `serve` process
+    Client(commands::ClientArgs),
 }
 
 pub fn run() -> Result<(), anyhow::Error> {
     let cli = Cli::parse();
     env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+    crate::fhe_aes::self_test().context("Startup self-test failed")?;
 
     match cli.command {
         Commands::Encrypt(args) => commands::handle_encrypt(args),
         Commands::Benchmark(args) => commands::handle_benchmark(args),
         Commands::Verify(args) => commands::handle_verify(args),
+        Commands::Noise(args) => commands::handle_noise(args),
+        Commands::Circuit(args) => commands::handle_circuit(args),
+        Commands::Serve(args) => commands::handle_serve(args),
+        Commands::Client(args) => commands::handle_client(args),
     }
 }
@@ -0,0 +1,196 @@
+//! Client for the evaluation server started by `fhe-aes serve`
+//!
+//! The client generates and keeps the client key: only the server key and
+//! encrypted inputs leave the process, and results are decrypted locally.
+
+use crate::backend::{BooleanBackend, ClientBackend};
+use crate::circuit::{bytes_to_wires, wires_to_bytes};
+use crate::fhe_aes::SchedulerMetrics;
+use crate::params::ParameterSet;
+use crate::serialization::Artifact;
+use crate::server::{self, read_frame, write_frame, Endpoint, Request, Response};
+use crate::utils::constants::{AES_128_KEY_SIZE, AES_128_OUTPUT_BITSIZE, AES_BLOCK_SIZE};
+use anyhow::{bail, Context, Result};
+use serde::{de::DeserializeOwned, Serialize};
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use tfhe::boolean::prelude::*;
+
+trait Stream: Read + Write + Send {}
+
+impl<T: Read + Write + Send> Stream for T {}
+
+type Block<B> = [<B as BooleanBackend>::Ciphertext; AES_128_OUTPUT_BITSIZE];
+
+/// Connection to an evaluation server holding one session. The server must run
+/// the same backend `B`, by default the tfhe boolean `ServerKey`.
+pub struct Client<B: ClientBackend = ServerKey> {
+    stream: Box<dyn Stream>,
+    parameter_set: ParameterSet,
+    client_key: B::ClientKey,
+    session: u64,
+    circuit_fingerprint: String,
+}
+
+impl<B> Client<B>
+where
+    B: ClientBackend + Serialize,
+    B::Ciphertext: Serialize + DeserializeOwned,
+{
+    /// Connects to `endpoint`, generates keys for `parameter_set` and registers the server key
+    pub fn connect(endpoint: &Endpoint, parameter_set: ParameterSet) -> Result<Self> {
+        let (client_key, server_key) = B::gen_keys(&parameter_set);
+        match endpoint {
+            Endpoint::Tcp(address) => {
+                let stream = TcpStream::connect(address)
+                    .with_context(|| format!("Failed to connect to {}", address))?;
+                Self::new(stream, parameter_set, client_key, &server_key)
+            }
+            #[cfg(unix)]
+            Endpoint::Unix(path) => {
+                let stream = std::os::unix::net::UnixStream::connect(path)
+                    .with_context(|| format!("Failed to connect to {}", path.display()))?;
+                Self::new(stream, parameter_set, client_key, &server_key)
+            }
+            #[cfg(not(unix))]
+            Endpoint::Unix(_) => bail!("Unix sockets are not supported on this platform"),
+        }
+    }
+
+    /// Registers `server_key` over an open `stream` and opens a session
+    pub fn new(
+        stream: impl Read + Write + Send + 'static,
+        parameter_set: ParameterSet,
+        client_key: B::ClientKey,
+        server_key: &B,
+    ) -> Result<Self> {
+        let mut stream: Box<dyn Stream> = Box::new(stream);
+        let request = Request::<&B, B::Ciphertext>::RegisterServerKey(Artifact::new(&parameter_set, server_key));
+        write_frame(&mut stream, &request)?;
+        let Response::Registered { session, circuit_fingerprint } = receive::<B::Ciphertext>(&mut stream)? else {
+            bail!("Unexpected response to the server key upload");
+        };
+
+        Ok(Self { stream, parameter_set, client_key, session, circuit_fingerprint })
+    }
+
+    pub fn session(&self) -> u64 {
+        self.session
+    }
+
+    /// Fingerprint of the circuit the server evaluates, see `Circuit::fingerprint`
+    pub fn circuit_fingerprint(&self) -> &str {
+        &self.circuit_fingerprint
+    }
+
+    pub fn client_key(&self) -> &B::ClientKey {
+        &self.client_key
+    }
+
+    /// Encrypts `key` and has the server expand it for the following jobs
+    pub fn upload_key(&mut self, key: &[u8; AES_128_KEY_SIZE]) -> Result<()> {
+        let key = self.encrypt(key);
+        self.send(&Request::ExpandKey { session: self.session, key: Artifact::new(&self.parameter_set, key) })?;
+        let Response::KeyExpanded = receive::<B::Ciphertext>(&mut self.stream)? else {
+            bail!("Unexpected response to the key upload");
+        };
+        Ok(())
+    }
+
+    /// Requests `count` CTR keystream blocks from the encrypted `iv`
+    pub fn encrypted_keystream(
+        &mut self,
+        iv: &[u8; AES_BLOCK_SIZE],
+        count: usize,
+    ) -> Result<Vec<Block<B>>> {
+        let iv = self.encrypt(iv);
+        self.send(&Request::Keystream {
+            session: self.session,
+            iv: Artifact::new(&self.parameter_set, iv),
+            count: count as u64,
+        })?;
+        self.receive_blocks(count)
+    }
+
+    /// Requests and decrypts `count` CTR keystream blocks
+    pub fn keystream(&mut self, iv: &[u8; AES_BLOCK_SIZE], count: usize) -> Result<Vec<[u8; AES_BLOCK_SIZE]>> {
+        let blocks = self.encrypted_keystream(iv, count)?;
+        Ok(blocks.iter().map(|block| self.decrypt(block)).collect())
+    }
+
+    /// Has the server transcipher the AES-CTR `ciphertext` started at `counter` into
+    /// FHE-encrypted plaintext blocks; the last block is zero-padded
+    pub fn encrypted_transcipher(
+        &mut self,
+        counter: &[u8; AES_BLOCK_SIZE],
+        ciphertext: &[u8],
+    ) -> Result<Vec<Block<B>>> {
+        self.send(&Request::Transcipher { session: self.session, counter: *counter, ciphertext: ciphertext.to_vec() })?;
+        self.receive_blocks(ciphertext.len().div_ceil(AES_BLOCK_SIZE))
+    }
+
+    /// Transciphers `ciphertext` and decrypts the result back to the plaintext
+    pub fn transcipher(&mut self, counter: &[u8; AES_BLOCK_SIZE], ciphertext: &[u8]) -> Result<Vec<u8>> {
+        let blocks = self.encrypted_transcipher(counter, ciphertext)?;
+        let mut plaintext: Vec<u8> = blocks
+            .iter()
+            .flat_map(|block| self.decrypt(block))
+            .collect();
+        plaintext.truncate(ciphertext.len());
+        Ok(plaintext)
+    }
+
+    /// Queue depth and latency of the server's job scheduler
+    pub fn metrics(&mut self) -> Result<SchedulerMetrics> {
+        self.send(&Request::Metrics)?;
+        let Response::Metrics(metrics) = receive::<B::Ciphertext>(&mut self.stream)? else {
+            bail!("Unexpected response to the metrics request");
+        };
+        Ok(metrics)
+    }
+
+    fn send(&mut self, request: &Request<B, B::Ciphertext>) -> Result<()> {
+        write_frame(&mut self.stream, request)
+    }
+
+    /// Encrypts `bytes` in the wire layout of `BoolFheAes::encrypt_key` and `encrypt_iv`
+    fn encrypt(&self, bytes: &[u8]) -> Vec<B::Ciphertext> {
+        bytes_to_wires(bytes).into_iter().map(|bit| B::encrypt(&self.client_key, bit)).collect()
+    }
+
+    /// Decrypts an output block, as `BoolFheAes::decrypt_output`
+    fn decrypt(&self, block: &Block<B>) -> [u8; AES_BLOCK_SIZE] {
+        let bits: Vec<bool> = block.iter().map(|bit| B::decrypt(&self.client_key, bit)).collect();
+        wires_to_bytes(&bits)
+    }
+
+    /// Collects the `count` blocks streamed for a request, in index order
+    fn receive_blocks(&mut self, count: usize) -> Result<Vec<Block<B>>> {
+        let mut blocks: Vec<Option<Block<B>>> = (0..count).map(|_| None).collect();
+        loop {
+            match receive::<B::Ciphertext>(&mut self.stream)? {
+                Response::Block { index, block } => {
+                    let slot = blocks.get_mut(index as usize).context("Block index out of range")?;
+                    *slot = Some(server::block(block)?);
+                }
+                Response::Done => break,
+                _ => bail!("Unexpected response while receiving blocks"),
+            }
+        }
+
+        blocks
+            .into_iter()
+            .enumerate()
+            .map(|(i, block)| block.with_context(|| format!("Server did not send block {}", i)))
+            .collect()
+    }
+}
+
+/// Reads the next response, turning `Response::Error` into an error
+fn receive<C: DeserializeOwned>(stream: &mut impl Read) -> Result<Response<C>> {
+    match read_frame(stream)? {
+        None => bail!("Server closed the connection"),
+        Some(Response::Error(message)) => bail!("Server error: {}", message),
+        Some(response) => Ok(response),
+    }
+}
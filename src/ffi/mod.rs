@@ -0,0 +1,470 @@
+//! C ABI built with the `ffi` feature; the header is generated into `include/fhe_aes.h`
+//!
+//! Evaluators and client keys are opaque handles created and destroyed through
+//! this module. Every function returns an `FheAesStatus` instead of panicking,
+//! and the message of the last failure on the calling thread is available from
+//! `fhe_aes_last_error`. Keys, inputs and outputs cross the boundary as the same
+//! serialized `Artifact`s the CLI and the server use.
+
+use crate::circuit::AesCircuit;
+use crate::fhe_aes::CancellationToken;
+use crate::params::ParameterSet;
+use crate::serialization::Artifact;
+use crate::utils::constants::{AES_128_KEY_SIZE, AES_128_OUTPUT_BITSIZE, AES_BLOCK_SIZE};
+use crate::BoolFheAes;
+use std::cell::RefCell;
+use std::ffi::{c_char, CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use tfhe::boolean::prelude::*;
+
+/// Result of every `fhe_aes_*` function
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FheAesStatus {
+    Ok = 0,
+    /// A required pointer argument was null
+    NullPointer = 1,
+    /// A name or length argument was not accepted
+    InvalidArgument = 2,
+    /// Bytes could not be deserialized, or belong to another parameter set
+    InvalidArtifact = 3,
+    /// Blocks were requested before `fhe_aes_expand_key`
+    KeyNotExpanded = 4,
+    /// The evaluation was cancelled with `fhe_aes_cancel`
+    Cancelled = 5,
+    /// The library panicked; the handle involved should be freed and not used again
+    Panic = 6,
+}
+
+/// AES-128 evaluator holding a server key and, once expanded, an AES key
+pub struct FheAes {
+    fhe_aes: BoolFheAes,
+    cancellation: CancellationToken,
+    key_expanded: bool,
+}
+
+/// Client key, used to encrypt inputs and decrypt outputs
+pub struct FheAesClientKey {
+    parameter_set: ParameterSet,
+    client_key: ClientKey,
+}
+
+/// Bytes allocated by the library, released with `fhe_aes_buffer_free`
+#[repr(C)]
+pub struct FheAesBuffer {
+    pub data: *mut u8,
+    pub len: usize,
+}
+
+struct Error {
+    status: FheAesStatus,
+    message: String,
+}
+
+impl Error {
+    fn new(status: FheAesStatus, message: impl Into<String>) -> Self {
+        Self { status, message: message.into() }
+    }
+}
+
+trait WithStatus<T> {
+    fn status(self, status: FheAesStatus) -> Result<T, Error>;
+}
+
+impl<T> WithStatus<T> for anyhow::Result<T> {
+    fn status(self, status: FheAesStatus) -> Result<T, Error> {
+        self.map_err(|error| Error::new(status, format!("{:#}", error)))
+    }
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<CString> = RefCell::new(CString::default());
+}
+
+fn set_last_error(message: &str) {
+    let message = CString::new(message.replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = message);
+}
+
+/// Runs `f`, turning errors and panics into a status and recording their message
+fn guard(f: impl FnOnce() -> Result<(), Error>) -> FheAesStatus {
+    let error = match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => {
+            set_last_error("");
+            return FheAesStatus::Ok;
+        }
+        Ok(Err(error)) => error,
+        Err(payload) => {
+            let message = payload
+                .downcast_ref::<&str>()
+                .map(|message| message.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "Unknown panic".to_string());
+            Error::new(FheAesStatus::Panic, message)
+        }
+    };
+    set_last_error(&error.message);
+    error.status
+}
+
+unsafe fn reference<'a, T>(pointer: *const T, name: &str) -> Result<&'a T, Error> {
+    pointer.as_ref().ok_or_else(|| Error::new(FheAesStatus::NullPointer, format!("`{}` is null", name)))
+}
+
+unsafe fn bytes<'a>(data: *const u8, len: usize, name: &str) -> Result<&'a [u8], Error> {
+    Ok(std::slice::from_raw_parts(reference(data, name)?, len))
+}
+
+unsafe fn array<'a, const N: usize>(data: *const u8, name: &str) -> Result<&'a [u8; N], Error> {
+    Ok(&*(reference(data, name)? as *const u8).cast::<[u8; N]>())
+}
+
+/// Parses an optional name with `FromStr`, `None` for a null pointer
+unsafe fn name<T: std::str::FromStr>(name: *const c_char, argument: &str) -> Result<Option<T>, Error>
+where
+    T::Err: std::fmt::Display,
+{
+    if name.is_null() {
+        return Ok(None);
+    }
+    let invalid = |message: String| Error::new(FheAesStatus::InvalidArgument, message);
+    let name = CStr::from_ptr(name)
+        .to_str()
+        .map_err(|_| invalid(format!("`{}` is not valid UTF-8", argument)))?;
+    name.parse().map(Some).map_err(|error: T::Err| invalid(error.to_string()))
+}
+
+unsafe fn write<T>(out: *mut T, value: T, name: &str) -> Result<(), Error> {
+    if out.is_null() {
+        return Err(Error::new(FheAesStatus::NullPointer, format!("`{}` is null", name)));
+    }
+    out.write(value);
+    Ok(())
+}
+
+fn buffer(bytes: Vec<u8>) -> FheAesBuffer {
+    let bytes = Box::into_raw(bytes.into_boxed_slice());
+    FheAesBuffer { data: bytes.cast(), len: bytes.len() }
+}
+
+/// Deserializes the `N` ciphertexts of an encrypted key or block
+fn ciphertexts<const N: usize>(bytes: &[u8], parameter_set: &ParameterSet) -> Result<[Ciphertext; N], Error> {
+    let artifact = Artifact::<Vec<Ciphertext>>::from_bytes(bytes).status(FheAesStatus::InvalidArtifact)?;
+    let ciphertexts = artifact.into_payload(parameter_set).status(FheAesStatus::InvalidArtifact)?;
+    let len = ciphertexts.len();
+    ciphertexts
+        .try_into()
+        .map_err(|_| Error::new(FheAesStatus::InvalidArtifact, format!("Expected {} ciphertexts, got {}", N, len)))
+}
+
+/// Message of the last failed call on this thread, empty after a successful one.
+/// The string is owned by the library and valid until the next call on this thread.
+#[no_mangle]
+pub extern "C" fn fhe_aes_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ptr())
+}
+
+/// Creates an evaluator from a server key serialized as an `Artifact<ServerKey>`.
+/// `parameter_set` and `circuit` are names as accepted by the CLI; null selects
+/// `default` and `scale-mamba`.
+///
+/// # Safety
+/// `parameter_set` and `circuit` must be null or NUL-terminated strings,
+/// `server_key` must point to `server_key_len` readable bytes and `out` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn fhe_aes_new(
+    parameter_set: *const c_char,
+    circuit: *const c_char,
+    server_key: *const u8,
+    server_key_len: usize,
+    out: *mut *mut FheAes,
+) -> FheAesStatus {
+    guard(|| {
+        if out.is_null() {
+            return Err(Error::new(FheAesStatus::NullPointer, "`out` is null"));
+        }
+        let parameter_set = name(parameter_set, "parameter_set")?.unwrap_or(ParameterSet::Default);
+        let circuit = name::<AesCircuit>(circuit, "circuit")?.unwrap_or_default();
+        let server_key = bytes(server_key, server_key_len, "server_key")?;
+        let server_key = Artifact::<ServerKey>::from_bytes(server_key)
+            .and_then(|artifact| artifact.into_payload(&parameter_set))
+            .status(FheAesStatus::InvalidArtifact)?;
+
+        let cancellation = CancellationToken::new();
+        let fhe_aes = BoolFheAes::with_circuit(server_key, parameter_set, circuit)
+            .with_cancellation(cancellation.clone());
+        let handle = Box::new(FheAes { fhe_aes, cancellation, key_expanded: false });
+        write(out, Box::into_raw(handle), "out")
+    })
+}
+
+/// Destroys an evaluator; null is ignored
+///
+/// # Safety
+/// `fhe_aes` must be null or a handle from `fhe_aes_new` that is not used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn fhe_aes_free(fhe_aes: *mut FheAes) {
+    if !fhe_aes.is_null() {
+        drop(Box::from_raw(fhe_aes));
+    }
+}
+
+/// Expands the 128 encrypted AES key bits produced by `fhe_aes_encrypt_key`,
+/// replacing any previously expanded key
+///
+/// # Safety
+/// `fhe_aes` must be a live handle not used concurrently, and `key` must point to
+/// `key_len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn fhe_aes_expand_key(fhe_aes: *mut FheAes, key: *const u8, key_len: usize) -> FheAesStatus {
+    guard(|| {
+        let fhe_aes = fhe_aes
+            .as_mut()
+            .ok_or_else(|| Error::new(FheAesStatus::NullPointer, "`fhe_aes` is null"))?;
+        let key = ciphertexts(bytes(key, key_len, "key")?, fhe_aes.fhe_aes.parameter_set())?;
+
+        fhe_aes.key_expanded = false;
+        fhe_aes
+            .fhe_aes
+            .try_expand_key(key)
+            .map_err(|error| Error::new(FheAesStatus::Cancelled, error.to_string()))?;
+        fhe_aes.key_expanded = true;
+        Ok(())
+    })
+}
+
+/// Computes `count` CTR blocks from the encrypted counter produced by
+/// `fhe_aes_encrypt_iv` and stores them in `out`, serialized like `export_outputs`
+///
+/// # Safety
+/// `fhe_aes` must be a live handle, `iv` must point to `iv_len` readable bytes and
+/// `out` must be writable. Concurrent calls on the same handle are allowed.
+#[no_mangle]
+pub unsafe extern "C" fn fhe_aes_ctr_blocks(
+    fhe_aes: *const FheAes,
+    iv: *const u8,
+    iv_len: usize,
+    count: usize,
+    out: *mut FheAesBuffer,
+) -> FheAesStatus {
+    guard(|| {
+        let fhe_aes = reference(fhe_aes, "fhe_aes")?;
+        if !fhe_aes.key_expanded {
+            return Err(Error::new(FheAesStatus::KeyNotExpanded, "No AES key was expanded"));
+        }
+        let iv = ciphertexts(bytes(iv, iv_len, "iv")?, fhe_aes.fhe_aes.parameter_set())?;
+
+        let outputs = fhe_aes
+            .fhe_aes
+            .try_aes_ctr_blocks(iv, count)
+            .map_err(|error| Error::new(FheAesStatus::Cancelled, error.to_string()))?;
+        let outputs = fhe_aes.fhe_aes.export_outputs(&outputs).status(FheAesStatus::InvalidArtifact)?;
+        write(out, buffer(outputs), "out")
+    })
+}
+
+/// Makes running and future evaluations on `fhe_aes` fail with `Cancelled`.
+/// Safe to call from another thread while an evaluation is running.
+///
+/// # Safety
+/// `fhe_aes` must be null or a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn fhe_aes_cancel(fhe_aes: *const FheAes) {
+    if let Some(fhe_aes) = fhe_aes.as_ref() {
+        fhe_aes.cancellation.cancel();
+    }
+}
+
+/// Releases a buffer filled by the library; an empty buffer is ignored
+///
+/// # Safety
+/// `buffer` must come from the library and not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn fhe_aes_buffer_free(buffer: FheAesBuffer) {
+    if !buffer.data.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(buffer.data, buffer.len)));
+    }
+}
+
+/// Generates a client key for `parameter_set` (null selects `default`) and stores
+/// the matching server key in `server_key`, serialized for `fhe_aes_new`
+///
+/// # Safety
+/// `parameter_set` must be null or a NUL-terminated string; `out` and
+/// `server_key` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn fhe_aes_client_key_generate(
+    parameter_set: *const c_char,
+    out: *mut *mut FheAesClientKey,
+    server_key: *mut FheAesBuffer,
+) -> FheAesStatus {
+    guard(|| {
+        let parameter_set = name(parameter_set, "parameter_set")?.unwrap_or(ParameterSet::Default);
+        if out.is_null() || server_key.is_null() {
+            return Err(Error::new(FheAesStatus::NullPointer, "`out` or `server_key` is null"));
+        }
+
+        let (client_key, key) = parameter_set.gen_keys();
+        let key = Artifact::new(&parameter_set, key).to_bytes().status(FheAesStatus::InvalidArtifact)?;
+        write(server_key, buffer(key), "server_key")?;
+        write(out, Box::into_raw(Box::new(FheAesClientKey { parameter_set, client_key })), "out")
+    })
+}
+
+/// Destroys a client key; null is ignored
+///
+/// # Safety
+/// `client_key` must be null or a handle from `fhe_aes_client_key_generate` that
+/// is not used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn fhe_aes_client_key_free(client_key: *mut FheAesClientKey) {
+    if !client_key.is_null() {
+        drop(Box::from_raw(client_key));
+    }
+}
+
+/// Encrypts the 16-byte AES `key` for `fhe_aes_expand_key`
+///
+/// # Safety
+/// `client_key` must be a live handle, `key` must point to 16 readable bytes and
+/// `out` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn fhe_aes_encrypt_key(
+    client_key: *const FheAesClientKey,
+    key: *const u8,
+    out: *mut FheAesBuffer,
+) -> FheAesStatus {
+    guard(|| {
+        let client_key = reference(client_key, "client_key")?;
+        let key = BoolFheAes::encrypt_key(&client_key.client_key, array::<AES_128_KEY_SIZE>(key, "key")?);
+        let key = Artifact::new(&client_key.parameter_set, key.to_vec())
+            .to_bytes()
+            .status(FheAesStatus::InvalidArtifact)?;
+        write(out, buffer(key), "out")
+    })
+}
+
+/// Encrypts the 16-byte initial counter block for `fhe_aes_ctr_blocks`
+///
+/// # Safety
+/// `client_key` must be a live handle, `iv` must point to 16 readable bytes and
+/// `out` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn fhe_aes_encrypt_iv(
+    client_key: *const FheAesClientKey,
+    iv: *const u8,
+    out: *mut FheAesBuffer,
+) -> FheAesStatus {
+    guard(|| {
+        let client_key = reference(client_key, "client_key")?;
+        let iv = BoolFheAes::encrypt_iv(&client_key.client_key, array::<AES_BLOCK_SIZE>(iv, "iv")?);
+        let iv = Artifact::new(&client_key.parameter_set, iv.to_vec())
+            .to_bytes()
+            .status(FheAesStatus::InvalidArtifact)?;
+        write(out, buffer(iv), "out")
+    })
+}
+
+/// Decrypts blocks serialized by `fhe_aes_ctr_blocks` into 16 bytes per block
+///
+/// # Safety
+/// `client_key` must be a live handle, `outputs` must point to `outputs_len`
+/// readable bytes and `out` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn fhe_aes_decrypt_outputs(
+    client_key: *const FheAesClientKey,
+    outputs: *const u8,
+    outputs_len: usize,
+    out: *mut FheAesBuffer,
+) -> FheAesStatus {
+    guard(|| {
+        let client_key = reference(client_key, "client_key")?;
+        let blocks: Vec<Vec<Ciphertext>> = Artifact::from_bytes(bytes(outputs, outputs_len, "outputs")?)
+            .and_then(|artifact| artifact.into_payload(&client_key.parameter_set))
+            .status(FheAesStatus::InvalidArtifact)?;
+
+        let mut plaintext = Vec::with_capacity(blocks.len() * AES_BLOCK_SIZE);
+        for block in blocks {
+            let block: [Ciphertext; AES_128_OUTPUT_BITSIZE] = block
+                .try_into()
+                .map_err(|_| Error::new(FheAesStatus::InvalidArtifact, "Invalid output block length"))?;
+            plaintext.extend(BoolFheAes::decrypt_output(&client_key.client_key, &block));
+        }
+        write(out, buffer(plaintext), "out")
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encrypt_reference_aes128;
+
+    const KEY: [u8; 16] = [0x2b, 0x7e, 0x15, 0x16, 0x28, 0xae, 0xd2, 0xa6, 0xab, 0xf7, 0x15, 0x88, 0x09, 0xcf, 0x4f, 0x3c];
+    const IV: [u8; 16] = [0xf0, 0xf1, 0xf2, 0xf3, 0xf4, 0xf5, 0xf6, 0xf7, 0xf8, 0xf9, 0xfa, 0xfb, 0xfc, 0xfd, 0xfe, 0xff];
+
+    fn empty() -> FheAesBuffer {
+        FheAesBuffer { data: ptr::null_mut(), len: 0 }
+    }
+
+    fn last_error() -> String {
+        unsafe { CStr::from_ptr(fhe_aes_last_error()) }.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn test_ctr_round_trip() {
+        unsafe {
+            let (mut client_key, mut server_key) = (ptr::null_mut(), empty());
+            assert_eq!(fhe_aes_client_key_generate(ptr::null(), &mut client_key, &mut server_key), FheAesStatus::Ok);
+            let mut fhe_aes = ptr::null_mut();
+            let circuit = c"bp".as_ptr();
+            assert_eq!(
+                fhe_aes_new(ptr::null(), circuit, server_key.data, server_key.len, &mut fhe_aes),
+                FheAesStatus::Ok
+            );
+            fhe_aes_buffer_free(server_key);
+
+            let (mut key, mut iv, mut outputs, mut plaintext) = (empty(), empty(), empty(), empty());
+            assert_eq!(fhe_aes_encrypt_key(client_key, KEY.as_ptr(), &mut key), FheAesStatus::Ok);
+            assert_eq!(fhe_aes_encrypt_iv(client_key, IV.as_ptr(), &mut iv), FheAesStatus::Ok);
+            assert_eq!(fhe_aes_ctr_blocks(fhe_aes, iv.data, iv.len, 2, &mut outputs), FheAesStatus::KeyNotExpanded);
+            assert_eq!(last_error(), "No AES key was expanded");
+
+            assert_eq!(fhe_aes_expand_key(fhe_aes, key.data, key.len), FheAesStatus::Ok);
+            assert_eq!(fhe_aes_ctr_blocks(fhe_aes, iv.data, iv.len, 2, &mut outputs), FheAesStatus::Ok);
+            assert_eq!(last_error(), "");
+            assert_eq!(fhe_aes_decrypt_outputs(client_key, outputs.data, outputs.len, &mut plaintext), FheAesStatus::Ok);
+
+            let counter = u128::from_be_bytes(IV);
+            let expected = encrypt_reference_aes128(vec![IV, (counter + 1).to_be_bytes()], KEY);
+            let expected: Vec<u8> = expected.iter().flat_map(|block| block.to_vec()).collect();
+            assert_eq!(std::slice::from_raw_parts(plaintext.data, plaintext.len), expected);
+
+            fhe_aes_cancel(fhe_aes);
+            assert_eq!(fhe_aes_ctr_blocks(fhe_aes, iv.data, iv.len, 1, &mut empty()), FheAesStatus::Cancelled);
+
+            for buffer in [key, iv, outputs, plaintext] {
+                fhe_aes_buffer_free(buffer);
+            }
+            fhe_aes_free(fhe_aes);
+            fhe_aes_client_key_free(client_key);
+        }
+    }
+
+    #[test]
+    fn test_invalid_arguments() {
+        unsafe {
+            let mut fhe_aes = ptr::null_mut();
+            let bytes = [1, 2, 3];
+            let status = fhe_aes_new(c"nope".as_ptr(), ptr::null(), bytes.as_ptr(), bytes.len(), &mut fhe_aes);
+            assert_eq!(status, FheAesStatus::InvalidArgument);
+            assert!(last_error().contains("Unknown parameter set"));
+
+            let status = fhe_aes_new(ptr::null(), ptr::null(), bytes.as_ptr(), bytes.len(), &mut fhe_aes);
+            assert_eq!(status, FheAesStatus::InvalidArtifact);
+            assert_eq!(fhe_aes_new(ptr::null(), ptr::null(), ptr::null(), 0, &mut fhe_aes), FheAesStatus::NullPointer);
+            assert_eq!(fhe_aes_expand_key(ptr::null_mut(), bytes.as_ptr(), 3), FheAesStatus::NullPointer);
+            assert!(fhe_aes.is_null());
+        }
+    }
+}
@@ -5,16 +5,55 @@ use crate::utils::constants::{AES_BLOCK_SIZE_BITS, AES_128_OUTPUT_BITSIZE};
 use bit_vec::BitVec;
 use dashmap::DashMap;
 use rayon::prelude::*;
-use tfhe::boolean::prelude::*;
 
-impl BoolFheAes {
+impl<B: BooleanBackend> BoolFheAes<B> {
     /// FHE-computes a single AES-128 block for the encrypted AES-128 `block` using the expanded key.
     /// It then extracts and returns the FHE-encrypted output block.
     /// NOTE: assumes the key was already expanded using `expand_key`
+    ///
+    /// # Panics
+    /// If the cancellation token is set; use `try_execute` to handle it.
     pub fn execute(
         &self,
-        block: [Ciphertext; AES_BLOCK_SIZE_BITS],
-    ) -> [Ciphertext; AES_128_OUTPUT_BITSIZE] {
+        block: [B::Ciphertext; AES_BLOCK_SIZE_BITS],
+    ) -> [B::Ciphertext; AES_128_OUTPUT_BITSIZE] {
+        self.try_execute(block).expect("Block evaluation was cancelled")
+    }
+
+    /// Same as `execute`, but returns `Err(Cancelled)` if the cancellation token is set
+    pub fn try_execute(
+        &self,
+        block: [B::Ciphertext; AES_BLOCK_SIZE_BITS],
+    ) -> Result<[B::Ciphertext; AES_128_OUTPUT_BITSIZE], Cancelled> {
+        self.execute_block(block, &self.start_progress(1))
+    }
+
+    /// Evaluates one block and extracts its output, counting it as done in `progress`
+    pub(crate) fn execute_block(
+        &self,
+        block: [B::Ciphertext; AES_BLOCK_SIZE_BITS],
+        progress: &ProgressTracker,
+    ) -> Result<[B::Ciphertext; AES_128_OUTPUT_BITSIZE], Cancelled> {
+        let values = self.execute_wires(block, Some(progress))?;
+
+        // Extract output block
+        let output_start = self.output_end - AES_128_OUTPUT_BITSIZE as u32;
+        let output = std::array::from_fn(|i| {
+            let value = values.get(&(output_start + i as u32))
+                .expect("Missing output wire value");
+            self.backend.clone_ciphertext(&value)
+        });
+        progress.block_done();
+        Ok(output)
+    }
+
+    /// Evaluates the circuit on `block` and returns every wire computed for it.
+    /// Wires produced during key expansion are not included.
+    pub(crate) fn execute_wires(
+        &self,
+        block: [B::Ciphertext; AES_BLOCK_SIZE_BITS],
+        progress: Option<&ProgressTracker>,
+    ) -> Result<DashMap<u32, B::Ciphertext>, Cancelled> {
         let values = DashMap::with_capacity(self.output_end as usize);
         
         // Insert input block into values map
@@ -24,76 +63,108 @@ impl BoolFheAes {
         });
 
         // Execute all relevant gates
-        self.execute_all(&values, false);
-
-        // Extract output block
-        let mut output = std::array::from_fn(|_| Ciphertext::Trivial(false));
-        let output_start = self.output_end - AES_128_OUTPUT_BITSIZE as u32;
-        
-        for i in output_start..self.output_end {
-            let output_idx = (i - output_start) as usize;
-            output[output_idx] = values.get(&i)
-                .expect("Missing output wire value")
-                .clone();
-        }
+        self.execute_all(&values, false, progress)?;
 
-        output
+        Ok(values)
     }
 
     /// Generates multiple CTR mode blocks in parallel
+    ///
+    /// # Panics
+    /// If the cancellation token is set; use `try_aes_ctr_blocks` to handle it.
     pub fn aes_ctr_blocks(
         &self,
-        iv: [Ciphertext; AES_BLOCK_SIZE_BITS],
+        iv: [B::Ciphertext; AES_BLOCK_SIZE_BITS],
+        count: usize,
+    ) -> Vec<[B::Ciphertext; AES_128_OUTPUT_BITSIZE]> {
+        self.try_aes_ctr_blocks(iv, count).expect("Block evaluation was cancelled")
+    }
+
+    /// Same as `aes_ctr_blocks`, but returns `Err(Cancelled)` if the cancellation
+    /// token is set. Blocks computed before cancellation are dropped.
+    pub fn try_aes_ctr_blocks(
+        &self,
+        iv: [B::Ciphertext; AES_BLOCK_SIZE_BITS],
         count: usize,
-    ) -> Vec<[Ciphertext; AES_128_OUTPUT_BITSIZE]> {
+    ) -> Result<Vec<[B::Ciphertext; AES_128_OUTPUT_BITSIZE]>, Cancelled> {
+        let progress = self.start_progress(count);
         match count {
-            0 => vec![],
-            1 => vec![self.execute(iv)],
-            _ => self.generate_parallel_blocks(iv, count)
+            0 => Ok(vec![]),
+            1 => Ok(vec![self.execute_block(iv, &progress)?]),
+            _ => self.generate_parallel_blocks(iv, count, &progress)
+        }
+    }
+
+    /// Same as `try_aes_ctr_blocks`, but hands every block to `on_block` with its
+    /// index as soon as it is computed, in no particular order
+    pub fn try_aes_ctr_for_each(
+        &self,
+        iv: [B::Ciphertext; AES_BLOCK_SIZE_BITS],
+        count: usize,
+        on_block: impl Fn(usize, [B::Ciphertext; AES_128_OUTPUT_BITSIZE]) + Send + Sync,
+    ) -> Result<(), Cancelled> {
+        let progress = self.start_progress(count);
+        if count == 0 {
+            return Ok(());
         }
+
+        let (head, tail) = rayon::join(
+            || self.execute_block(iv.clone(), &progress).map(|output| on_block(0, output)),
+            || {
+                self.get_blocks(&iv, count - 1)
+                    .into_par_iter()
+                    .enumerate()
+                    .try_for_each(|(i, block)| {
+                        self.execute_block(block, &progress).map(|output| on_block(i + 1, output))
+                    })
+            },
+        );
+        head.and(tail)
     }
 
     /// Parallel block generation using work stealing
     fn generate_parallel_blocks(
         &self,
-        iv: [Ciphertext; AES_BLOCK_SIZE_BITS],
+        iv: [B::Ciphertext; AES_BLOCK_SIZE_BITS],
         count: usize,
-    ) -> Vec<[Ciphertext; AES_128_OUTPUT_BITSIZE]> {
+        progress: &ProgressTracker,
+    ) -> Result<Vec<[B::Ciphertext; AES_128_OUTPUT_BITSIZE]>, Cancelled> {
         let (head, tail) = rayon::join(
-            || self.execute(iv.clone()),
-            || self.process_tail_blocks(&iv, count)
+            || self.execute_block(iv.clone(), progress),
+            || self.process_tail_blocks(&iv, count, progress)
         );
 
         let mut results = Vec::with_capacity(count);
-        results.push(head);
-        results.extend(tail);
-        results
+        results.push(head?);
+        results.extend(tail?);
+        Ok(results)
     }
 
     /// Process remaining blocks in parallel
     fn process_tail_blocks(
         &self,
-        iv: &[Ciphertext; AES_BLOCK_SIZE_BITS],
+        iv: &[B::Ciphertext; AES_BLOCK_SIZE_BITS],
         count: usize,
-    ) -> Vec<[Ciphertext; AES_128_OUTPUT_BITSIZE]> {
+        progress: &ProgressTracker,
+    ) -> Result<Vec<[B::Ciphertext; AES_128_OUTPUT_BITSIZE]>, Cancelled> {
         self.get_blocks(iv, count - 1)
             .into_par_iter()
-            .map(|block| self.execute(block))
+            .map(|block| self.execute_block(block, progress))
             .collect()
     }
 
     /// Homomorphically generate incremented blocks for CTR mode
-    fn get_blocks(
+    pub(crate) fn get_blocks(
         &self,
-        iv: &[Ciphertext; AES_BLOCK_SIZE_BITS],
+        iv: &[B::Ciphertext; AES_BLOCK_SIZE_BITS],
         count: usize,
-    ) -> Vec<[Ciphertext; AES_BLOCK_SIZE_BITS]> {
-        let xor_cache: DashMap<(usize, bool), Ciphertext> = DashMap::with_capacity(128);
-        let and_cache: DashMap<(usize, bool), Ciphertext> = DashMap::with_capacity(128);
+    ) -> Vec<[B::Ciphertext; AES_BLOCK_SIZE_BITS]> {
+        let xor_cache: DashMap<(usize, bool), B::Ciphertext> = DashMap::with_capacity(128);
+        let and_cache: DashMap<(usize, bool), B::Ciphertext> = DashMap::with_capacity(128);
 
         (1..=count).into_par_iter().map(|i| {
             let counter_bits = BitVec::from_bytes(&(i as u128).to_be_bytes());
-            let mut carry = Ciphertext::Trivial(false);
+            let mut carry = self.backend.trivial(false);
             let mut block = iv.clone();
 
             for bit_pos in 0..AES_BLOCK_SIZE_BITS {
@@ -123,21 +194,21 @@ impl BoolFheAes {
     #[inline]
     fn get_or_compute_operations(
         &self,
-        block: &mut [Ciphertext; AES_BLOCK_SIZE_BITS],
+        block: &mut [B::Ciphertext; AES_BLOCK_SIZE_BITS],
         bit_pos: usize,
         bit: bool,
-        xor_cache: &DashMap<(usize, bool), Ciphertext>,
-        and_cache: &DashMap<(usize, bool), Ciphertext>,
-    ) -> (Ciphertext, Ciphertext) {
+        xor_cache: &DashMap<(usize, bool), B::Ciphertext>,
+        and_cache: &DashMap<(usize, bool), B::Ciphertext>,
+    ) -> (B::Ciphertext, B::Ciphertext) {
         if let (Some(xor), Some(and)) = (xor_cache.get(&(bit_pos, bit)), and_cache.get(&(bit_pos, bit))) {
             (xor.clone(), and.clone())
         } else {
             let a = &block[bit_pos];
-            let b = Ciphertext::Trivial(bit);
+            let b = self.backend.trivial(bit);
             
             let (xor, and) = rayon::join(
-                || self.server_key.xor(a, &b),
-                || self.server_key.and(a, &b)
+                || self.backend.xor(a, &b),
+                || self.backend.and(a, &b)
             );
             
             xor_cache.insert((bit_pos, bit), xor.clone());
@@ -151,13 +222,23 @@ impl BoolFheAes {
     #[inline]
     fn bit_full_adder(
         &self,
-        a_xor_b: Ciphertext,
-        a_and_b: Ciphertext,
-        cin: Ciphertext,
-    ) -> (Ciphertext, Ciphertext) {
+        a_xor_b: B::Ciphertext,
+        a_and_b: B::Ciphertext,
+        cin: B::Ciphertext,
+    ) -> (B::Ciphertext, B::Ciphertext) {
         rayon::join(
-            || self.server_key.xor(&a_xor_b, &cin),
-            || self.server_key.or(&a_and_b, &self.server_key.and(&cin, &a_xor_b))
+            || self.backend.xor(&a_xor_b, &cin),
+            || self.backend.or(&a_and_b, &self.backend.and(&cin, &a_xor_b))
         )
     }
 }
+
+/// Concatenates output `blocks` into the first `len` bytes of the stream layout, 8
+/// wires per byte with the least significant bit first. Output wires hold each block
+/// as a 128-bit integer, least significant bit first, so its first byte is on the
+/// last 8 wires.
+pub(crate) fn blocks_to_stream_bytes<C: Clone>(blocks: &[[C; AES_128_OUTPUT_BITSIZE]], len: usize) -> Vec<C> {
+    let mut bytes: Vec<C> = blocks.iter().flat_map(|block| block.chunks(8).rev().flatten().cloned()).collect();
+    bytes.truncate(len * 8);
+    bytes
+}
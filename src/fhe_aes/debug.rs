@@ -0,0 +1,188 @@
+//! Debug evaluator that decrypts and checks every wire against a cleartext run
+//!
+//! This is a test facility: it holds the `ClientKey`, so it must never be used on
+//! the server side of a real deployment.
+
+use super::*;
+use crate::circuit::{bytes_to_wires, depth, eval};
+use std::fmt;
+
+/// The first gate whose decrypted FHE output disagrees with the cleartext evaluation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GateMismatch {
+    /// Position of the gate in the circuit
+    pub gate_index: usize,
+    /// Gate opcode, as returned by `Gate::name`
+    pub gate_type: &'static str,
+    /// Input wires of the failing (sub-)gate
+    pub inputs: Vec<u32>,
+    /// Output wire holding the wrong value
+    pub output: u32,
+    /// AES round the gate belongs to
+    pub round: u32,
+    /// Value computed by the cleartext evaluator
+    pub expected: bool,
+}
+
+impl fmt::Display for GateMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "gate #{} ({}) in round {}: inputs {:?} -> wire {} decrypted to {}, expected {}",
+            self.gate_index,
+            self.gate_type,
+            self.round,
+            self.inputs,
+            self.output,
+            !self.expected,
+            self.expected
+        )
+    }
+}
+
+impl std::error::Error for GateMismatch {}
+
+/// Runs `BoolFheAes` and the cleartext evaluator side by side and reports the
+/// first diverging gate
+pub struct DebugEvaluator<'a> {
+    fhe_aes: &'a BoolFheAes,
+    client_key: &'a ClientKey,
+    key: [u8; AES_128_KEY_SIZE],
+    rounds: Vec<u32>,
+}
+
+impl<'a> DebugEvaluator<'a> {
+    pub fn new(fhe_aes: &'a BoolFheAes, client_key: &'a ClientKey) -> Self {
+        Self {
+            fhe_aes,
+            client_key,
+            key: [0; AES_128_KEY_SIZE],
+            rounds: depth::gate_rounds(&fhe_aes.instructions, fhe_aes.output_end),
+        }
+    }
+
+    /// Encrypts and expands `key`, then checks every key-expansion wire
+    pub fn expand_key(&mut self, key: &[u8; AES_128_KEY_SIZE]) -> Result<(), GateMismatch> {
+        self.key = *key;
+        self.fhe_aes
+            .expand_key(BoolFheAes::encrypt_key(self.client_key, key));
+
+        let expected = self.expected_wires(&[0; AES_BLOCK_SIZE]);
+        self.check(&[&self.fhe_aes.expanded_key_outputs], &expected)
+    }
+
+    /// Encrypts and executes `block` with the expanded key, then checks every wire.
+    /// NOTE: assumes the key was already expanded using `DebugEvaluator::expand_key`
+    pub fn execute(
+        &self,
+        block: &[u8; AES_BLOCK_SIZE],
+    ) -> Result<[Ciphertext; AES_128_OUTPUT_BITSIZE], GateMismatch> {
+        let values = self
+            .fhe_aes
+            .execute_wires(BoolFheAes::encrypt_iv(self.client_key, block), None)
+            .expect("Block evaluation was cancelled");
+
+        let expected = self.expected_wires(block);
+        self.check(&[&self.fhe_aes.expanded_key_outputs, &values], &expected)?;
+
+        let output_start = self.fhe_aes.output_end - AES_128_OUTPUT_BITSIZE as u32;
+        Ok(std::array::from_fn(|i| {
+            values
+                .get(&(output_start + i as u32))
+                .expect("Missing output wire value")
+                .clone()
+        }))
+    }
+
+    fn expected_wires(&self, block: &[u8; AES_BLOCK_SIZE]) -> Vec<bool> {
+        let mut inputs = bytes_to_wires(&self.key);
+        inputs.extend(bytes_to_wires(block));
+        eval::evaluate_gates(&self.fhe_aes.instructions, self.fhe_aes.output_end, &inputs)
+    }
+
+    /// Walks the gates in circuit order and returns the first output wire computed
+    /// in any of `values` that decrypts to the wrong value
+    fn check(
+        &self,
+        values: &[&DashMap<u32, Ciphertext>],
+        expected: &[bool],
+    ) -> Result<(), GateMismatch> {
+        for (gate_index, gate) in self.fhe_aes.instructions.iter().enumerate() {
+            for (inputs, output) in gate.wires() {
+                let Some(ct) = values.iter().find_map(|map| map.get(&output)) else {
+                    continue;
+                };
+                let expected = expected[output as usize];
+                if self.client_key.decrypt(&ct) != expected {
+                    return Err(GateMismatch {
+                        gate_index,
+                        gate_type: gate.name(),
+                        inputs,
+                        output,
+                        round: self.rounds[gate_index],
+                        expected,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: [u8; 16] = [
+        0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e,
+        0x0f,
+    ];
+    const PLAINTEXT: [u8; 16] = [
+        0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xaa, 0xbb, 0xcc, 0xdd, 0xee,
+        0xff,
+    ];
+
+    #[test]
+    fn test_debug_evaluator_passes() {
+        let (client_key, server_key) = gen_keys();
+        let fhe_aes = BoolFheAes::new(server_key);
+        let mut debug = DebugEvaluator::new(&fhe_aes, &client_key);
+
+        debug.expand_key(&KEY).unwrap();
+        let output = debug.execute(&PLAINTEXT).unwrap();
+        let expected = encrypt_reference_aes128(vec![PLAINTEXT], KEY);
+        assert_eq!(
+            BoolFheAes::decrypt_output(&client_key, &output).as_slice(),
+            expected[0].as_slice()
+        );
+    }
+
+    #[test]
+    fn test_debug_evaluator_reports_first_bad_gate() {
+        let (client_key, server_key) = gen_keys();
+        let fhe_aes = BoolFheAes::new(server_key);
+        let mut debug = DebugEvaluator::new(&fhe_aes, &client_key);
+        debug.expand_key(&KEY).unwrap();
+
+        // Corrupt the output of the last key-expansion gate
+        let (gate_index, output) = fhe_aes
+            .instructions
+            .iter()
+            .enumerate()
+            .filter_map(|(i, gate)| match gate {
+                Gate::Xor { output, .. } if fhe_aes.key_expand_reachable.contains(output) => {
+                    Some((i, *output))
+                }
+                _ => None,
+            })
+            .last()
+            .unwrap();
+        let flipped = fhe_aes.backend.not(&fhe_aes.expanded_key_outputs.get(&output).unwrap());
+        fhe_aes.expanded_key_outputs.insert(output, flipped);
+
+        let mismatch = debug.execute(&PLAINTEXT).unwrap_err();
+        assert_eq!(mismatch.gate_index, gate_index);
+        assert_eq!(mismatch.output, output);
+        assert_eq!(mismatch.gate_type, "XOR");
+    }
+}
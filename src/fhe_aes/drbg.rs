@@ -0,0 +1,346 @@
+//! NIST SP 800-90A CTR_DRBG with AES-128 and no derivation function
+//!
+//! The DRBG key lives in the expanded key of a `BoolFheAes` and `V` is an encrypted
+//! counter block, so neither is ever decrypted on the server. Counters come from
+//! `get_blocks` and are evaluated in parallel with `execute_block`. Entropy inputs
+//! are encrypted; personalization strings and additional inputs are public.
+
+use super::*;
+use crate::circuit::bytes_to_wires;
+use anyhow::{ensure, Context, Result};
+
+/// Seed length in bytes, the key followed by `V`
+pub const CTR_DRBG_SEED_SIZE: usize = AES_128_KEY_SIZE + AES_BLOCK_SIZE;
+
+/// Largest request, 2^19 bits
+pub const CTR_DRBG_MAX_REQUEST_SIZE: usize = 1 << 16;
+
+/// Generate requests allowed before a reseed is required
+pub const CTR_DRBG_RESEED_INTERVAL: u64 = 1 << 48;
+
+/// Key and `V` of a DRBG state
+type State<C> = ([C; AES_128_KEY_SIZE_BITS], [C; AES_BLOCK_SIZE_BITS]);
+
+/// CTR_DRBG producing encrypted pseudorandom bytes from an encrypted seed
+pub struct FheCtrDrbg<B: BooleanBackend = ServerKey> {
+    fhe_aes: BoolFheAes<B>,
+    v: Option<[B::Ciphertext; AES_BLOCK_SIZE_BITS]>,
+    reseed_counter: u64,
+}
+
+impl FheCtrDrbg {
+    /// Encrypts entropy input for `instantiate` and `reseed`
+    pub fn encrypt_entropy(client_key: &ClientKey, entropy: &[u8; CTR_DRBG_SEED_SIZE]) -> Vec<Ciphertext> {
+        bytes_to_wires(entropy).into_iter().map(|bit| client_key.encrypt(bit)).collect()
+    }
+}
+
+impl<B: BooleanBackend> FheCtrDrbg<B> {
+    /// Evaluates AES through `fhe_aes`; its expanded key is replaced on every update
+    pub fn new(fhe_aes: BoolFheAes<B>) -> Self {
+        Self { fhe_aes, v: None, reseed_counter: 0 }
+    }
+
+    /// Seeds the DRBG with 32 bytes of encrypted `entropy`, as returned by
+    /// `encrypt_entropy`, and a `personalization` string of at most 32 bytes
+    pub fn instantiate(&mut self, entropy: &[B::Ciphertext], personalization: &[u8]) -> Result<()> {
+        let (key, v) = split_seed(entropy)?;
+        let personalization = pad_seed(personalization, "personalization string")?;
+
+        // Key and V start at zero, so the keystream of the first update is public
+        let counters = (1..=2u128).map(u128::to_be_bytes).collect();
+        let keystream = encrypt_reference_aes128(counters, [0; AES_128_KEY_SIZE]);
+        let public: Vec<u8> = keystream.iter().flatten().zip(personalization).map(|(k, p)| k ^ p).collect();
+
+        let key = self.fhe_aes.xor_cleartext(&key, &public[..AES_128_KEY_SIZE]);
+        let v = self.fhe_aes.xor_cleartext(&v, &public[AES_128_KEY_SIZE..]);
+        self.set_state(key, v)?;
+        self.reseed_counter = 1;
+        Ok(())
+    }
+
+    /// Mixes fresh encrypted `entropy` and public `additional_input` into the state
+    pub fn reseed(&mut self, entropy: &[B::Ciphertext], additional_input: &[u8]) -> Result<()> {
+        self.update(Some(entropy), additional_input)?;
+        self.reseed_counter = 1;
+        Ok(())
+    }
+
+    /// Generates `len` encrypted pseudorandom bytes, 8 ciphertexts each with the least
+    /// significant bit first as in `stream::decrypt_bytes`. `additional_input` is
+    /// public, at most 32 bytes, and may be empty.
+    pub fn generate_bytes(&mut self, len: usize, additional_input: &[u8]) -> Result<Vec<B::Ciphertext>> {
+        ensure!(self.v.is_some(), "The DRBG was not instantiated");
+        ensure!(self.reseed_counter <= CTR_DRBG_RESEED_INTERVAL, "The DRBG must be reseeded");
+        ensure!(
+            len <= CTR_DRBG_MAX_REQUEST_SIZE,
+            "Requests are at most {} bytes, got {}",
+            CTR_DRBG_MAX_REQUEST_SIZE,
+            len
+        );
+        let v = self.v.clone().context("The DRBG was not instantiated")?;
+
+        // The key mixing in the additional input is only kept if the whole request
+        // succeeds; V is not replaced before then
+        let (v, previous_key) = if additional_input.is_empty() {
+            (v, None)
+        } else {
+            let (key, v) = self.next_state(&v, None, additional_input)?;
+            (v, Some(self.replace_key(key)?))
+        };
+        let result = self.generate_from(&v, len, additional_input);
+        if let (Err(_), Some(previous_key)) = (&result, previous_key) {
+            self.fhe_aes.expanded_key_outputs = previous_key;
+        }
+        result
+    }
+
+    /// Requests served since the last reseed, plus one
+    pub fn reseed_counter(&self) -> u64 {
+        self.reseed_counter
+    }
+
+    /// Generates `len` bytes from `v` under the current key, then updates the state
+    fn generate_from(
+        &mut self,
+        v: &[B::Ciphertext; AES_BLOCK_SIZE_BITS],
+        len: usize,
+        additional_input: &[u8],
+    ) -> Result<Vec<B::Ciphertext>> {
+        let counters = self.fhe_aes.get_blocks(v, len.div_ceil(AES_BLOCK_SIZE));
+        let last = counters.last().unwrap_or(v).clone();
+        let blocks = self.execute_blocks(counters)?;
+        let bytes = blocks_to_stream_bytes(&blocks, len);
+
+        let (key, v) = self.next_state(&last, None, additional_input)?;
+        self.set_state(key, v)?;
+        self.reseed_counter += 1;
+        Ok(bytes)
+    }
+
+    /// `CTR_DRBG_Update`: encrypts `V + 1` and `V + 2` and XORs in the provided data,
+    /// the encrypted `entropy` if any and the public `data`, to get the new key and `V`
+    fn update(&mut self, entropy: Option<&[B::Ciphertext]>, data: &[u8]) -> Result<()> {
+        let v = self.v.clone().context("The DRBG was not instantiated")?;
+        let (key, v) = self.next_state(&v, entropy, data)?;
+        self.set_state(key, v)
+    }
+
+    /// Computes the key and `V` that `update` would set from `v`, leaving the state as is
+    fn next_state(
+        &self,
+        v: &[B::Ciphertext; AES_BLOCK_SIZE_BITS],
+        entropy: Option<&[B::Ciphertext]>,
+        data: &[u8],
+    ) -> Result<State<B::Ciphertext>> {
+        let data = pad_seed(data, "additional input")?;
+        let counters = self.fhe_aes.get_blocks(v, 2);
+        let [mut key, mut v]: [_; 2] = match self.execute_blocks(counters)?.try_into() {
+            Ok(blocks) => blocks,
+            Err(_) => unreachable!("Two counter blocks were evaluated"),
+        };
+
+        if let Some(entropy) = entropy {
+            let (entropy_key, entropy_v) = split_seed(entropy)?;
+            key = self.xor(&key, &entropy_key);
+            v = self.xor(&v, &entropy_v);
+        }
+        let key = self.fhe_aes.xor_cleartext(&key, &data[..AES_128_KEY_SIZE]);
+        let v = self.fhe_aes.xor_cleartext(&v, &data[AES_128_KEY_SIZE..]);
+        Ok((key, v))
+    }
+
+    /// Expands `key` and sets `V`; a cancelled expansion leaves both as they were
+    fn set_state(
+        &mut self,
+        key: [B::Ciphertext; AES_128_KEY_SIZE_BITS],
+        v: [B::Ciphertext; AES_BLOCK_SIZE_BITS],
+    ) -> Result<()> {
+        self.replace_key(key)?;
+        self.v = Some(v);
+        Ok(())
+    }
+
+    /// Expands `key` in place of the current expanded key and returns the latter,
+    /// or puts it back if the expansion is cancelled
+    fn replace_key(
+        &mut self,
+        key: [B::Ciphertext; AES_128_KEY_SIZE_BITS],
+    ) -> Result<DashMap<u32, B::Ciphertext>, Cancelled> {
+        let previous = std::mem::take(&mut self.fhe_aes.expanded_key_outputs);
+        if let Err(cancelled) = self.fhe_aes.try_expand_key(key) {
+            self.fhe_aes.expanded_key_outputs = previous;
+            return Err(cancelled);
+        }
+        Ok(previous)
+    }
+
+    fn execute_blocks(
+        &self,
+        counters: Vec<[B::Ciphertext; AES_BLOCK_SIZE_BITS]>,
+    ) -> Result<Vec<[B::Ciphertext; AES_128_OUTPUT_BITSIZE]>, Cancelled> {
+        let progress = self.fhe_aes.start_progress(counters.len());
+        counters.into_par_iter().map(|counter| self.fhe_aes.execute_block(counter, &progress)).collect()
+    }
+
+    fn xor(
+        &self,
+        a: &[B::Ciphertext; AES_BLOCK_SIZE_BITS],
+        b: &[B::Ciphertext; AES_BLOCK_SIZE_BITS],
+    ) -> [B::Ciphertext; AES_BLOCK_SIZE_BITS] {
+        std::array::from_fn(|wire| self.fhe_aes.backend.xor(&a[wire], &b[wire]))
+    }
+}
+
+/// Splits an encrypted seed in the `bytes_to_wires` layout into the key, its first
+/// 16 bytes, and `V`
+fn split_seed<T: Clone>(seed: &[T]) -> Result<([T; AES_128_KEY_SIZE_BITS], [T; AES_BLOCK_SIZE_BITS])> {
+    ensure!(
+        seed.len() == CTR_DRBG_SEED_SIZE * 8,
+        "Entropy inputs are {} bytes, got {} bits",
+        CTR_DRBG_SEED_SIZE,
+        seed.len()
+    );
+    let (v, key) = seed.split_at(AES_BLOCK_SIZE_BITS);
+    Ok((std::array::from_fn(|i| key[i].clone()), std::array::from_fn(|i| v[i].clone())))
+}
+
+/// Right-pads public provided data with zeros to the seed length
+fn pad_seed(data: &[u8], name: &str) -> Result<[u8; CTR_DRBG_SEED_SIZE]> {
+    ensure!(
+        data.len() <= CTR_DRBG_SEED_SIZE,
+        "The {} is at most {} bytes, got {}",
+        name,
+        CTR_DRBG_SEED_SIZE,
+        data.len()
+    );
+    let mut padded = [0; CTR_DRBG_SEED_SIZE];
+    padded[..data.len()].copy_from_slice(data);
+    Ok(padded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::Cleartext;
+    use crate::stream::bits_to_bytes;
+
+    fn entropy(hex: &str) -> Vec<bool> {
+        bytes_to_wires(&hex::decode(hex).unwrap())
+    }
+
+    /// CTR_DRBG in cleartext, directly from SP 800-90A
+    struct Reference {
+        key: [u8; 16],
+        v: u128,
+    }
+
+    impl Reference {
+        fn update(&mut self, data: &[u8]) {
+            let counters = (1..=2).map(|i| self.v.wrapping_add(i).to_be_bytes()).collect();
+            let keystream = encrypt_reference_aes128(counters, self.key);
+            let temp: Vec<u8> =
+                keystream.iter().flatten().zip(pad_seed(data, "data").unwrap()).map(|(t, d)| t ^ d).collect();
+            self.key = temp[..16].try_into().unwrap();
+            self.v = u128::from_be_bytes(temp[16..].try_into().unwrap());
+        }
+
+        fn generate(&mut self, len: usize, additional_input: &[u8]) -> Vec<u8> {
+            if !additional_input.is_empty() {
+                self.update(additional_input);
+            }
+            let count = len.div_ceil(16) as u128;
+            let counters = (1..=count).map(|i| self.v.wrapping_add(i).to_be_bytes()).collect();
+            let mut bytes: Vec<u8> = encrypt_reference_aes128(counters, self.key).iter().flatten().copied().collect();
+            bytes.truncate(len);
+            self.v = self.v.wrapping_add(count);
+            self.update(additional_input);
+            bytes
+        }
+    }
+
+    #[test]
+    fn test_cavp_vector() {
+        // CAVP CTR_DRBG, AES-128 no df, no prediction resistance, COUNT = 0
+        let mut drbg = FheCtrDrbg::new(BoolFheAes::new(Cleartext));
+        let seed = "ed1e7f21ef66ea5d8e2a85b9337245445b71d6393a4eecb0e63c193d0f72f9a9";
+        drbg.instantiate(&entropy(seed), &[]).unwrap();
+        let reseed = "303fb519f0a4e17d6df0b6426aa0ecb2a36079bd48be47ad2a8dbfe48da3efad";
+        drbg.reseed(&entropy(reseed), &[]).unwrap();
+        drbg.generate_bytes(64, &[]).unwrap();
+        let returned = drbg.generate_bytes(64, &[]).unwrap();
+        assert_eq!(hex::encode(bits_to_bytes(&returned)), concat!(
+            "f80111d08e874672f32f42997133a5210f7a9375e22cea70587f9cfafebe0f6a",
+            "6aa2eb68e7dd9164536d53fa020fcab20f54caddfab7d6d91e5ffec1dfd8deaa"
+        ));
+        assert_eq!(drbg.reseed_counter(), 3);
+    }
+
+    #[test]
+    fn test_additional_input_matches_reference() {
+        let seed: Vec<u8> = (0..32).collect();
+        let personalization = b"encrypted-ml pipeline";
+        let mut drbg = FheCtrDrbg::new(BoolFheAes::new(Cleartext));
+        drbg.instantiate(&bytes_to_wires(&seed), personalization).unwrap();
+
+        let mut reference = Reference { key: [0; 16], v: 0 };
+        let padded = pad_seed(personalization, "data").unwrap();
+        let material: Vec<u8> = seed.iter().zip(padded).map(|(s, p)| s ^ p).collect();
+        reference.update(&material);
+
+        for (len, additional_input) in [(5, &b"step 1"[..]), (40, &[]), (16, &[0xff; 32])] {
+            let bytes = drbg.generate_bytes(len, additional_input).unwrap();
+            assert_eq!(bits_to_bytes(&bytes), reference.generate(len, additional_input), "{} bytes", len);
+        }
+    }
+
+    #[test]
+    fn test_drbg_errors() {
+        let mut drbg = FheCtrDrbg::new(BoolFheAes::new(Cleartext));
+        assert!(drbg.generate_bytes(16, &[]).is_err());
+        assert!(drbg.reseed(&[false; 256], &[]).is_err());
+        assert!(drbg.instantiate(&[false; 128], &[]).is_err());
+        assert!(drbg.instantiate(&[false; 256], &[0; 33]).is_err());
+
+        drbg.instantiate(&[false; 256], &[]).unwrap();
+        assert!(drbg.generate_bytes(CTR_DRBG_MAX_REQUEST_SIZE + 1, &[]).is_err());
+        assert!(drbg.generate_bytes(16, &[0; 33]).is_err());
+        assert!(drbg.generate_bytes(0, &[]).unwrap().is_empty());
+
+        // A cancelled request leaves V where it was
+        let token = CancellationToken::new();
+        let mut drbg = FheCtrDrbg::new(BoolFheAes::new(Cleartext).with_cancellation(token.clone()));
+        drbg.instantiate(&[false; 256], &[]).unwrap();
+        let v = drbg.v;
+        token.cancel();
+        assert!(drbg.generate_bytes(64, &[]).is_err());
+        assert_eq!(drbg.v, v);
+    }
+
+    #[test]
+    fn test_cancelled_request_keeps_state() {
+        // Cancelled once the 4 output blocks start, after the additional input is mixed in
+        let token = CancellationToken::new();
+        let cancel = token.clone();
+        let fhe_aes = BoolFheAes::new(Cleartext)
+            .with_cancellation(token)
+            .with_progress(move |progress| {
+                if progress.blocks_total == 4 {
+                    cancel.cancel();
+                }
+            });
+        let mut drbg = FheCtrDrbg::new(fhe_aes);
+        let seed: Vec<u8> = (0..32).collect();
+        drbg.instantiate(&bytes_to_wires(&seed), &[]).unwrap();
+
+        let state = |drbg: &FheCtrDrbg<Cleartext>| {
+            let mut key: Vec<_> =
+                drbg.fhe_aes.expanded_key_outputs.iter().map(|entry| (*entry.key(), *entry.value())).collect();
+            key.sort();
+            (key, drbg.v, drbg.reseed_counter)
+        };
+        let before = state(&drbg);
+        assert!(drbg.generate_bytes(64, b"additional input").is_err());
+        assert_eq!(state(&drbg), before);
+    }
+}
@@ -50,13 +50,24 @@ impl BoolFheAes {
         }
         bits.to_bytes().try_into().unwrap()
     }
+}
 
+impl<B: BooleanBackend> BoolFheAes<B> {
     /// Expands the encrypted key
-    pub fn expand_key(&self, key: [Ciphertext; AES_128_KEY_SIZE_BITS]) {
+    ///
+    /// # Panics
+    /// If the cancellation token is set; use `try_expand_key` to handle it.
+    pub fn expand_key(&self, key: [B::Ciphertext; AES_128_KEY_SIZE_BITS]) {
+        self.try_expand_key(key).expect("Key expansion was cancelled");
+    }
+
+    /// Expands the encrypted key, stopping early if the cancellation token is set.
+    /// A cancelled expansion leaves a partial key that must be expanded again.
+    pub fn try_expand_key(&self, key: [B::Ciphertext; AES_128_KEY_SIZE_BITS]) -> Result<(), Cancelled> {
         self.expanded_key_outputs.clear();
         for (i, ct) in key.iter().enumerate() {
-            self.expanded_key_outputs.insert(i as u32, ct.clone());
+            self.expanded_key_outputs.insert(i as u32, self.backend.clone_ciphertext(ct));
         }
-        self.execute_all(&self.expanded_key_outputs, true);
+        self.execute_all(&self.expanded_key_outputs, true, None)
     }
 }
@@ -0,0 +1,248 @@
+//! RFC 3394 AES Key Wrap under an encrypted key-encryption key (KEK)
+//!
+//! Keys and wrapped keys are encrypted wires in the `bytes_to_wires` layout: the
+//! whole byte string as a big-endian integer, least significant bit first. An
+//! unwrapped 16-byte key is therefore ready for `BoolFheAes::expand_key`.
+
+use super::*;
+use crate::circuit::{bytes_to_wires, Pass, SboxCircuit};
+use anyhow::{ensure, Result};
+
+/// Bits in a 64-bit semiblock of RFC 3394
+const SEMIBLOCK_BITS: usize = 64;
+
+/// Initial value `A6A6A6A6A6A6A6A6`, checked on unwrap as the integrity check value
+const KEY_WRAP_IV: [u8; 8] = [0xa6; 8];
+
+/// Wraps and unwraps keys with AES-128 under an encrypted KEK. The KEK is
+/// expanded into an encryption evaluator for `wrap` and `derive_key` and into a
+/// decryption evaluator, generated with `Circuit::generate_aes_128_decrypt`, for
+/// `unwrap`.
+pub struct FheKeyWrap<B: BooleanBackend = ServerKey> {
+    encrypt: BoolFheAes<B>,
+    decrypt: BoolFheAes<B>,
+}
+
+impl<B: BooleanBackend + Clone> FheKeyWrap<B> {
+    pub fn new(backend: B, parameter_set: ParameterSet) -> Self {
+        let decrypt = Pass::Group.run(&Circuit::generate_aes_128_decrypt(SboxCircuit::BoyarPeralta));
+        Self {
+            encrypt: BoolFheAes::with_circuit(backend.clone(), parameter_set, AesCircuit::default()),
+            decrypt: BoolFheAes::from_circuit(backend, parameter_set, decrypt),
+        }
+    }
+}
+
+impl<B: BooleanBackend> FheKeyWrap<B> {
+    /// Expands the KEK, encrypted as by `BoolFheAes::encrypt_key`, for both directions
+    pub fn set_kek(&self, kek: [B::Ciphertext; AES_128_KEY_SIZE_BITS]) {
+        self.encrypt.expand_key(kek.clone());
+        self.decrypt.expand_key(kek);
+    }
+
+    pub fn backend(&self) -> &B {
+        &self.encrypt.backend
+    }
+
+    /// Wraps the encrypted `key`, a multiple of 8 bytes and at least 16, into an
+    /// encrypted wrapped key 8 bytes longer
+    pub fn wrap(&self, key: &[B::Ciphertext]) -> Result<Vec<B::Ciphertext>> {
+        self.check_kek()?;
+        ensure!(
+            key.len() % SEMIBLOCK_BITS == 0 && key.len() >= 2 * SEMIBLOCK_BITS,
+            "Keys to wrap are a multiple of 8 bytes and at least 16, got {} bits",
+            key.len()
+        );
+        let mut r = semiblocks(key);
+        let n = r.len();
+        let iv = bytes_to_wires(&KEY_WRAP_IV);
+        let mut a: Vec<B::Ciphertext> = iv.into_iter().map(|bit| self.backend().trivial(bit)).collect();
+
+        let progress = self.encrypt.start_progress(6 * n);
+        for j in 0..6 {
+            for (i, r_i) in r.iter_mut().enumerate() {
+                let output = self.encrypt.execute_block(block(&a, r_i), &progress)?;
+                *r_i = output[..SEMIBLOCK_BITS].to_vec();
+                a = self.xor_counter(&output[SEMIBLOCK_BITS..], n * j + i + 1);
+            }
+        }
+
+        r.insert(0, a);
+        Ok(from_semiblocks(r))
+    }
+
+    /// Unwraps the encrypted `wrapped` key, returning the encrypted key and the
+    /// integrity check as an encrypted boolean, true when the wrapped key is valid.
+    /// Nothing is decrypted, so a wrong KEK or a tampered wrapped key only shows in
+    /// the decrypted check.
+    pub fn unwrap(&self, wrapped: &[B::Ciphertext]) -> Result<(Vec<B::Ciphertext>, B::Ciphertext)> {
+        self.check_kek()?;
+        ensure!(
+            wrapped.len() % SEMIBLOCK_BITS == 0 && wrapped.len() >= 3 * SEMIBLOCK_BITS,
+            "Wrapped keys are a multiple of 8 bytes and at least 24, got {} bits",
+            wrapped.len()
+        );
+        let mut r = semiblocks(wrapped);
+        let mut a = r.remove(0);
+        let n = r.len();
+
+        let progress = self.decrypt.start_progress(6 * n);
+        for j in (0..6).rev() {
+            for i in (0..n).rev() {
+                let a_t = self.xor_counter(&a, n * j + i + 1);
+                let output = self.decrypt.execute_block(block(&a_t, &r[i]), &progress)?;
+                a = output[SEMIBLOCK_BITS..].to_vec();
+                r[i] = output[..SEMIBLOCK_BITS].to_vec();
+            }
+        }
+
+        Ok((from_semiblocks(r), self.equals(&a, &bytes_to_wires(&KEY_WRAP_IV))))
+    }
+
+    /// Same as `unwrap` for a public wrapped key
+    pub fn unwrap_public(&self, wrapped: &[u8]) -> Result<(Vec<B::Ciphertext>, B::Ciphertext)> {
+        let wires: Vec<B::Ciphertext> =
+            bytes_to_wires(wrapped).into_iter().map(|bit| self.backend().trivial(bit)).collect();
+        self.unwrap(&wires)
+    }
+
+    /// Derives a 128-bit key as the KEK encryption of the public `context` block,
+    /// ready for `BoolFheAes::expand_key`. Distinct contexts give independent keys.
+    pub fn derive_key(&self, context: &[u8; AES_BLOCK_SIZE]) -> Result<[B::Ciphertext; AES_128_KEY_SIZE_BITS]> {
+        self.check_kek()?;
+        let progress = self.encrypt.start_progress(1);
+        Ok(self.encrypt.execute_block(self.encrypt.trivial_counter(context, 0), &progress)?)
+    }
+
+    fn check_kek(&self) -> Result<()> {
+        ensure!(!self.encrypt.expanded_key_outputs.is_empty(), "No KEK was set");
+        Ok(())
+    }
+
+    /// XORs the public step counter `t`, as a big-endian 64-bit integer, into `a`
+    fn xor_counter(&self, a: &[B::Ciphertext], t: usize) -> Vec<B::Ciphertext> {
+        let counter: Vec<bool> = (0..a.len()).map(|bit| t >> bit & 1 == 1).collect();
+        self.backend().xor_public(a, &counter)
+    }
+
+    /// Encrypted boolean, true when the encrypted `wires` equal the public `bits`
+    fn equals(&self, wires: &[B::Ciphertext], bits: &[bool]) -> B::Ciphertext {
+        let mut equal: Vec<B::Ciphertext> = wires
+            .iter()
+            .zip(bits)
+            .map(|(wire, bit)| match bit {
+                true => self.backend().clone_ciphertext(wire),
+                false => self.backend().not(wire),
+            })
+            .collect();
+        // Balanced AND tree, depth 6 for a semiblock
+        while equal.len() > 1 {
+            equal = equal
+                .par_chunks(2)
+                .map(|pair| match pair {
+                    [a, b] => self.backend().and(a, b),
+                    [a] => self.backend().clone_ciphertext(a),
+                    _ => unreachable!(),
+                })
+                .collect();
+        }
+        equal.pop().expect("No wires to compare")
+    }
+}
+
+/// Splits wires in the `bytes_to_wires` layout into semiblocks, first semiblock
+/// first; each semiblock is itself in the `bytes_to_wires` layout
+fn semiblocks<T: Clone>(wires: &[T]) -> Vec<Vec<T>> {
+    wires.chunks(SEMIBLOCK_BITS).rev().map(<[T]>::to_vec).collect()
+}
+
+/// Inverse of `semiblocks`
+fn from_semiblocks<T>(semiblocks: Vec<Vec<T>>) -> Vec<T> {
+    semiblocks.into_iter().rev().flatten().collect()
+}
+
+/// The AES block `msb | lsb`, with `msb` on the high 64 wires
+fn block<T: Clone>(msb: &[T], lsb: &[T]) -> [T; AES_BLOCK_SIZE_BITS] {
+    std::array::from_fn(|wire| match wire < SEMIBLOCK_BITS {
+        true => lsb[wire].clone(),
+        false => msb[wire - SEMIBLOCK_BITS].clone(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::Cleartext;
+    use crate::circuit::wires_to_bytes;
+    use bit_vec::BitVec;
+
+    // RFC 3394 section 4.1, 128 bits of key data with a 128-bit KEK
+    const KEK: &str = "000102030405060708090A0B0C0D0E0F";
+    const KEY: &str = "00112233445566778899AABBCCDDEEFF";
+    const WRAPPED: &str = "1FA68B0A8112B447AEF34BD8FB5A7B829D3E862371D2CFE5";
+
+    fn key_wrap() -> FheKeyWrap<Cleartext> {
+        let key_wrap = FheKeyWrap::new(Cleartext, ParameterSet::Default);
+        key_wrap.set_kek(bytes_to_wires(&hex::decode(KEK).unwrap()).try_into().unwrap());
+        key_wrap
+    }
+
+    fn to_bytes(wires: &[bool]) -> Vec<u8> {
+        wires.iter().rev().copied().collect::<BitVec>().to_bytes()
+    }
+
+    #[test]
+    fn test_rfc3394_vector() {
+        let key_wrap = key_wrap();
+        let key = hex::decode(KEY).unwrap();
+        let wrapped = key_wrap.wrap(&bytes_to_wires(&key)).unwrap();
+        assert_eq!(to_bytes(&wrapped), hex::decode(WRAPPED).unwrap());
+
+        let (unwrapped, valid) = key_wrap.unwrap_public(&hex::decode(WRAPPED).unwrap()).unwrap();
+        assert!(valid);
+        assert_eq!(to_bytes(&unwrapped), key);
+
+        // The unwrapped key feeds straight into key expansion
+        let fhe_aes = BoolFheAes::new(Cleartext);
+        fhe_aes.expand_key(unwrapped.try_into().unwrap());
+        let block = [0x42; AES_BLOCK_SIZE];
+        let output = fhe_aes.execute(bytes_to_wires(&block).try_into().unwrap());
+        let expected = encrypt_reference_aes128(vec![block], key.try_into().unwrap());
+        assert_eq!(wires_to_bytes(&output).as_slice(), expected[0].as_slice());
+    }
+
+    #[test]
+    fn test_unwrap_detects_tampering() {
+        let key_wrap = key_wrap();
+        let key: Vec<u8> = (0..24).collect();
+        let wrapped = to_bytes(&key_wrap.wrap(&bytes_to_wires(&key)).unwrap());
+        assert_eq!(wrapped.len(), 32);
+        let (unwrapped, valid) = key_wrap.unwrap_public(&wrapped).unwrap();
+        assert!(valid);
+        assert_eq!(to_bytes(&unwrapped), key);
+
+        let mut tampered = wrapped.clone();
+        tampered[20] ^= 1;
+        assert!(!key_wrap.unwrap_public(&tampered).unwrap().1);
+    }
+
+    #[test]
+    fn test_key_wrap_errors() {
+        let key_wrap = FheKeyWrap::new(Cleartext, ParameterSet::Default);
+        assert!(key_wrap.wrap(&[false; 128]).is_err());
+
+        let key_wrap = self::key_wrap();
+        assert!(key_wrap.wrap(&[false; 64]).is_err());
+        assert!(key_wrap.wrap(&[false; 136]).is_err());
+        assert!(key_wrap.unwrap_public(&[0; 16]).is_err());
+    }
+
+    #[test]
+    fn test_derive_key() {
+        let key_wrap = key_wrap();
+        let context = [7; AES_BLOCK_SIZE];
+        let derived = key_wrap.derive_key(&context).unwrap();
+        let expected = encrypt_reference_aes128(vec![context], hex::decode(KEK).unwrap().try_into().unwrap());
+        assert_eq!(wires_to_bytes(&derived).as_slice(), expected[0].as_slice());
+    }
+}
@@ -2,28 +2,64 @@
 
 mod key;
 mod blocks;
-
-use crate::circuit::Gate;
-use dashmap::DashMap;
+#[cfg(any(test, feature = "debug-evaluator"))]
+mod debug;
+mod drbg;
+mod key_wrap;
+mod noise;
+mod profile;
+mod progress;
+mod scheduler;
+mod self_test;
+mod serialize;
+mod transcipher;
+
+use crate::backend::BooleanBackend;
+use crate::circuit::{AesCircuit, Circuit, Gate};
+use crate::params::ParameterSet;
+use dashmap::{DashMap, DashSet};
 use rayon::prelude::*;
 use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
 use tfhe::boolean::prelude::*;
 use aes::cipher::{generic_array::GenericArray, BlockEncrypt, KeyInit, consts::U16};
 use aes::Aes128;
 use crate::utils::constants::*;
+use profile::Profiler;
+use progress::{ProgressCallback, ProgressTracker};
+use std::time::Instant;
 
 
 
 pub use blocks::*;
+#[cfg(any(test, feature = "debug-evaluator"))]
+pub use debug::{DebugEvaluator, GateMismatch};
+pub use drbg::{FheCtrDrbg, CTR_DRBG_MAX_REQUEST_SIZE, CTR_DRBG_RESEED_INTERVAL, CTR_DRBG_SEED_SIZE};
 pub use key::*;
+pub use key_wrap::FheKeyWrap;
+pub use noise::{bootstrap_failure_log2, GateFailureStats, NoiseReport};
+pub use profile::{GateTiming, Profile, ThreadTiming};
+pub use progress::{CancellationToken, Cancelled, Progress};
+pub use scheduler::{JobHandle, Scheduler, SchedulerConfig, SchedulerMetrics};
+pub use self_test::self_test;
 
 /// Main FHE-AES structure
-pub struct BoolFheAes {
+///
+/// Gates are evaluated through the [`BooleanBackend`] `B`, which defaults to the
+/// tfhe boolean `ServerKey`.
+pub struct BoolFheAes<B: BooleanBackend = ServerKey> {
     pub(crate) instructions: Vec<Gate>,
     pub(crate) output_end: u32,
-    pub(crate) server_key: ServerKey,
+    pub(crate) backend: B,
     pub(crate) key_expand_reachable: HashSet<u32>,
-    pub(crate) expanded_key_outputs: DashMap<u32, Ciphertext>,
+    pub(crate) expanded_key_outputs: DashMap<u32, B::Ciphertext>,
+    pub(crate) parameter_set: ParameterSet,
+    pub(crate) circuit_fingerprint: String,
+    pub(crate) cancellation: CancellationToken,
+    pub(crate) progress_callback: Option<ProgressCallback>,
+    /// Tracker of the last call started, returned by `progress`
+    pub(crate) last_progress: Mutex<Arc<ProgressTracker>>,
+    pub(crate) profiler: Option<Profiler>,
 }
 
 
@@ -43,9 +79,15 @@ pub fn encrypt_reference_aes128(
 }
 
 
-impl BoolFheAes {
-    /// Creates a new BoolFheAes instance with loaded circuit
-    pub fn new(server_key: ServerKey) -> Self {
+impl<B: BooleanBackend> BoolFheAes<B> {
+    /// Creates a new BoolFheAes instance with loaded circuit, evaluated on `backend`
+    /// generated with the default parameter set
+    pub fn new(backend: B) -> Self {
+        Self::with_parameter_set(backend, ParameterSet::Default)
+    }
+
+    /// Creates a new BoolFheAes instance for a `backend` generated with `parameter_set`
+    pub fn with_parameter_set(backend: B, parameter_set: ParameterSet) -> Self {
         let mut circuit = include_str!("./aes_128_extended.txt").lines();
         let header = circuit.next().expect("Invalid circuit format");
         let (gate_count, output_end) = parse_header(header);
@@ -66,18 +108,124 @@ impl BoolFheAes {
             instructions.push(gate);
         }
 
+        // Fingerprint of this copy of the circuit, checked by `self_test`
+        let circuit_fingerprint = Circuit::from_gates(
+            instructions.clone(),
+            0..AES_128_KEY_SIZE_BITS as u32,
+            vec![AES_128_KEY_SIZE_BITS as u32, AES_BLOCK_SIZE_BITS as u32],
+            vec![AES_128_OUTPUT_BITSIZE as u32],
+            output_end,
+        )
+        .fingerprint();
+
         Self {
             instructions,
             output_end,
-            server_key,
+            backend,
             key_expand_reachable,
             expanded_key_outputs: DashMap::new(),
+            parameter_set,
+            circuit_fingerprint,
+            cancellation: CancellationToken::new(),
+            progress_callback: None,
+            last_progress: Mutex::default(),
+            profiler: None,
+        }
+    }
+
+    /// Creates a new BoolFheAes instance evaluating `circuit` instead of the embedded one.
+    /// The circuit must use the AES-128 wire layout: key on wires 0..128, block on
+    /// wires 128..256 and the output block on the last 128 wires.
+    pub fn from_circuit(backend: B, parameter_set: ParameterSet, circuit: Circuit) -> Self {
+        let circuit_fingerprint = circuit.fingerprint();
+        Self {
+            instructions: circuit.gates,
+            output_end: circuit.output_end,
+            backend,
+            key_expand_reachable: circuit.key_expand_reachable,
+            expanded_key_outputs: DashMap::new(),
+            parameter_set,
+            circuit_fingerprint,
+            cancellation: CancellationToken::new(),
+            progress_callback: None,
+            last_progress: Mutex::default(),
+            profiler: None,
+        }
+    }
+
+    /// Creates a new BoolFheAes instance evaluating the selected AES-128 `circuit`
+    pub fn with_circuit(backend: B, parameter_set: ParameterSet, circuit: AesCircuit) -> Self {
+        match circuit {
+            AesCircuit::ScaleMamba => Self::with_parameter_set(backend, parameter_set),
+            circuit => Self::from_circuit(backend, parameter_set, circuit.circuit()),
         }
     }
 
+    /// Reports the progress of `execute` and `aes_ctr_blocks` to `callback`, every
+    /// few gates and after each block. Pass a closure sending on a channel to
+    /// receive the updates on another thread.
+    pub fn with_progress(mut self, callback: impl Fn(Progress) + Send + Sync + 'static) -> Self {
+        self.progress_callback = Some(Arc::new(callback));
+        self
+    }
+
+    /// Stops key expansion and block evaluation once `token` is cancelled
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = token;
+        self
+    }
+
+    /// Records the wall time of every gate evaluated from now on, see `profile`
+    pub fn with_profiler(mut self) -> Self {
+        self.profiler = Some(Profiler::new(&self.instructions, self.output_end));
+        self
+    }
+
+    /// Timings recorded since `with_profiler`, or `None` when profiling is disabled
+    pub fn profile(&self) -> Option<Profile> {
+        self.profiler.as_ref().map(Profiler::report)
+    }
+
+    /// Progress of the last `execute` or `aes_ctr_blocks` call started; concurrent
+    /// calls each report their own progress to the `with_progress` callback
+    pub fn progress(&self) -> Progress {
+        self.last_progress.lock().unwrap().snapshot()
+    }
+
+    /// Starts tracking the progress of a call over `blocks` blocks
+    pub(crate) fn start_progress(&self, blocks: usize) -> Arc<ProgressTracker> {
+        let tracker = Arc::new(ProgressTracker::new(self.progress_callback.clone(), blocks, self.block_gate_count()));
+        *self.last_progress.lock().unwrap() = Arc::clone(&tracker);
+        tracker
+    }
+
+    /// Number of gates evaluated per block once the key is expanded
+    pub(crate) fn block_gate_count(&self) -> usize {
+        self.instructions
+            .iter()
+            .flat_map(Gate::wires)
+            .filter(|(_, output)| !self.key_expand_reachable.contains(output))
+            .count()
+    }
+
+    /// Number of bootstrapped operations per block once the key is expanded
+    pub(crate) fn block_bootstraps(&self) -> usize {
+        self.instructions
+            .iter()
+            .flat_map(|gate| gate.wires().into_iter().map(move |(_, output)| (gate.bootstraps(), output)))
+            .filter(|(_, output)| !self.key_expand_reachable.contains(output))
+            .map(|(bootstraps, _)| bootstraps)
+            .sum()
+    }
+
+    /// Returns the backend used to evaluate gates
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
     // Helper methods from original implementation
     #[inline]
-    fn was_computed(&self, output: &u32, values: &DashMap<u32, Ciphertext>) -> bool {
+    fn was_computed(&self, output: &u32, values: &DashMap<u32, B::Ciphertext>) -> bool {
         values.contains_key(output) || self.expanded_key_outputs.contains_key(output)
     }
 
@@ -85,7 +233,7 @@ impl BoolFheAes {
     fn expand_key_check(
         &self,
         output: &u32,
-        values: &DashMap<u32, Ciphertext>,
+        values: &DashMap<u32, B::Ciphertext>,
         expand_key: bool,
     ) -> bool {
         self.was_computed(output, values)
@@ -93,205 +241,163 @@ impl BoolFheAes {
     }
 
     #[inline]
-    fn get_output(&self, output: &u32, values: &DashMap<u32, Ciphertext>) -> Ciphertext {
-        values
-            .get(output)
-            .unwrap_or_else(|| {
-                self.expanded_key_outputs
-                    .get(output)
-                    .expect("Output not found")
-            })
-            .clone()
+    fn get_output(&self, output: &u32, values: &DashMap<u32, B::Ciphertext>) -> B::Ciphertext {
+        let value = values.get(output).unwrap_or_else(|| {
+            self.expanded_key_outputs
+                .get(output)
+                .expect("Output not found")
+        });
+        self.backend.clone_ciphertext(&value)
     }
 
     #[inline]
-    fn yield_or_compute(&self, values: &DashMap<u32, Ciphertext>, ilen: usize, i: usize) {
+    fn yield_or_compute(&self, run: &Run<B>, ilen: usize, i: usize) {
         if let Some(rayon::Yield::Idle) = rayon::yield_now() {
             (0..i).into_par_iter().for_each(|j| {
-                self.execute_gate(&self.instructions[j], values);
+                self.execute_gate(j, run);
             });
             (i + 1..ilen).into_par_iter().for_each(|j| {
-                self.execute_gate(&self.instructions[j], values);
+                self.execute_gate(j, run);
             });
         }
     }
 
     #[inline]
-    fn wait_for_inputs(
-        &self,
-        values: &DashMap<u32, Ciphertext>,
-        ilen: usize,
-        i: usize,
-        input1: &u32,
-        input2: &u32,
-    ) {
-        while !self.was_computed(input1, values) || !self.was_computed(input2, values) {
-            self.yield_or_compute(values, ilen, i);
+    fn wait_for_inputs(&self, run: &Run<B>, ilen: usize, i: usize, inputs: &[u32]) -> bool {
+        let wait = self.profiler.as_ref().map(Profiler::start_wait);
+        let mut ready = true;
+        while !inputs.iter().all(|input| self.was_computed(input, run.values)) {
+            if self.cancellation.is_cancelled() {
+                ready = false;
+                break;
+            }
+            self.yield_or_compute(run, ilen, i);
+        }
+        if let (Some(profiler), Some(wait)) = (&self.profiler, wait) {
+            profiler.finish_wait(wait);
         }
+        ready
+    }
+
+    /// Evaluates the `i`-th gate on `inputs`, timing it when profiling is enabled
+    #[inline]
+    fn apply_gate(&self, i: usize, output: u32, inputs: &[B::Ciphertext]) -> B::Ciphertext {
+        let Some(profiler) = &self.profiler else {
+            return self.instructions[i].apply(&self.backend, inputs);
+        };
+        let start = Instant::now();
+        let output_ct = self.instructions[i].apply(&self.backend, inputs);
+        profiler.record_gate(i, self.key_expand_reachable.contains(&output), start.elapsed());
+        output_ct
     }
 
-    // Core execution methods remain here...
-    // (execute_all, execute_gate, and helper methods from original code)
-        /// Executes all the gates in the circuit in parallel:
+    /// Executes all the gates in the circuit in parallel:
     /// - if `expand_key` is `true`, it will only compute the gates that are reachable from the key input wires
     ///
     /// for each gate, it will wait until the inputs are computed and then compute the output.
-    fn execute_all(&self, values: &DashMap<u32, Ciphertext>, expand_key: bool) {
+    /// The elementary gates of a batch (MAND, MXOR, MINV) run in parallel. A thread
+    /// claims an output wire before computing it, so every gate is evaluated once.
+    ///
+    /// Gates outside key expansion are counted in `progress`, if given.
+    ///
+    /// Returns `Err(Cancelled)` as soon as the cancellation token is set, leaving
+    /// the remaining gates unevaluated.
+    fn execute_all(
+        &self,
+        values: &DashMap<u32, B::Ciphertext>,
+        expand_key: bool,
+        progress: Option<&ProgressTracker>,
+    ) -> Result<(), Cancelled> {
+        if let Some(profiler) = &self.profiler {
+            profiler.start_run();
+        }
+        let run = Run { values, claimed: DashSet::new(), progress };
         let ilen = self.instructions.len();
         (0..ilen).into_par_iter().for_each(|i| {
             let instr = &self.instructions[i];
-            match instr {
-                Gate::And {
-                    input1,
-                    input2,
-                    output,
-                } => {
-                    if self.expand_key_check(output, values, expand_key) {
-                        return;
-                    }
-                    self.wait_for_inputs(values, ilen, i, input1, input2);
-
-                    let input1_ct = self.get_output(input1, values);
-                    let input2_ct = self.get_output(input2, values);
-                    if self.expand_key_check(output, values, expand_key) {
-                        return;
-                    }
-                    let output_ct = self.server_key.and(&input1_ct, &input2_ct);
-                    values.insert(*output, output_ct);
-                }
-                Gate::Inv { input, output } => {
-                    if self.expand_key_check(output, values, expand_key) {
-                        return;
-                    }
-                    while !self.was_computed(input, values) {
-                        self.yield_or_compute(values, ilen, i);
-                    }
-                    let input_ct = self.get_output(input, values);
-                    if self.expand_key_check(output, values, expand_key) {
-                        return;
-                    }
-                    let output_ct = self.server_key.not(&input_ct);
-                    values.insert(*output, output_ct);
+            let execute = |(inputs, output): &(Vec<u32>, u32)| {
+                if self.cancellation.is_cancelled()
+                    || self.expand_key_check(output, values, expand_key)
+                {
+                    return;
                 }
-                Gate::Xor {
-                    input1,
-                    input2,
-                    output,
-                } => {
-                    if self.expand_key_check(output, values, expand_key) {
-                        return;
-                    }
-                    self.wait_for_inputs(values, ilen, i, input1, input2);
-
-                    let input1_ct = self.get_output(input1, values);
-                    let input2_ct = self.get_output(input2, values);
-                    if self.expand_key_check(output, values, expand_key) {
-                        return;
-                    }
-                    let output_ct = self.server_key.xor(&input1_ct, &input2_ct);
-                    values.insert(*output, output_ct);
+                if !self.wait_for_inputs(&run, ilen, i, inputs) || !run.claimed.insert(*output) {
+                    return;
                 }
-                Gate::Mand { gates } => {
-                    gates.par_iter().for_each(|(input1, input2, output)| {
-                        if self.expand_key_check(output, values, expand_key) {
-                            return;
-                        }
-                        self.wait_for_inputs(values, ilen, i, input1, input2);
-
-                        let input1_ct = self.get_output(input1, values);
-                        let input2_ct = self.get_output(input2, values);
-                        if self.expand_key_check(output, values, expand_key) {
-                            return;
-                        }
-                        let output_ct = self.server_key.and(&input1_ct, &input2_ct);
-                        values.insert(*output, output_ct);
-                    });
+
+                let input_cts: Vec<_> =
+                    inputs.iter().map(|input| self.get_output(input, values)).collect();
+                let output_ct = self.apply_gate(i, *output, &input_cts);
+                values.insert(*output, output_ct);
+                if !expand_key {
+                    run.gate_done();
                 }
+            };
+
+            match instr.wires().as_slice() {
+                [wires] => execute(wires),
+                batch => batch.par_iter().for_each(execute),
             }
         });
+
+        if let Some(profiler) = &self.profiler {
+            profiler.finish_run();
+        }
+        if self.cancellation.is_cancelled() {
+            return Err(Cancelled);
+        }
+        Ok(())
     }
 
-    /// Executes a single gate in the circuit:
-    /// - if the output wire was already computed, it will return immediately
+    /// Executes the `i`-th gate of the circuit:
+    /// - if the output wire was already computed or claimed, it will return immediately
     /// - if the inputs are not computed, it will return immediately
     /// - otherwise, it will compute the output and store it in the values map.
-    fn execute_gate(&self, instr: &Gate, values: &DashMap<u32, Ciphertext>) {
-        match instr {
-            Gate::And {
-                input1,
-                input2,
-                output,
-            } => {
-                if self.was_computed(output, values)
-                    || !self.was_computed(input1, values)
-                    || !self.was_computed(input2, values)
-                {
-                    return;
-                }
-
-                let input1_ct = self.get_output(input1, values);
-                let input2_ct = self.get_output(input2, values);
-                if values.contains_key(output) {
-                    return;
-                }
-                let output_ct = self.server_key.and(&input1_ct, &input2_ct);
-                values.insert(*output, output_ct);
+    fn execute_gate(&self, i: usize, run: &Run<B>) {
+        let instr = &self.instructions[i];
+        let values = run.values;
+        let execute = |(inputs, output): &(Vec<u32>, u32)| {
+            if self.cancellation.is_cancelled()
+                || self.was_computed(output, values)
+                || !inputs.iter().all(|input| self.was_computed(input, values))
+                || !run.claimed.insert(*output)
+            {
+                return;
             }
-            Gate::Inv { input, output } => {
-                if self.was_computed(output, values) || !self.was_computed(input, values) {
-                    return;
-                }
 
-                let input_ct = self.get_output(input, values);
-                if self.was_computed(output, values) {
-                    return;
-                }
-                let output_ct = self.server_key.not(&input_ct);
-                values.insert(*output, output_ct);
+            let input_cts: Vec<_> =
+                inputs.iter().map(|input| self.get_output(input, values)).collect();
+            let output_ct = self.apply_gate(i, *output, &input_cts);
+            values.insert(*output, output_ct);
+            if !self.key_expand_reachable.contains(output) {
+                run.gate_done();
             }
-            Gate::Xor {
-                input1,
-                input2,
-                output,
-            } => {
-                if self.was_computed(output, values)
-                    || !self.was_computed(input1, values)
-                    || !self.was_computed(input2, values)
-                {
-                    return;
-                }
+        };
 
-                let input1_ct = self.get_output(input1, values);
-                let input2_ct = self.get_output(input2, values);
-                if self.was_computed(output, values) {
-                    return;
-                }
-                let output_ct = self.server_key.xor(&input1_ct, &input2_ct);
-                values.insert(*output, output_ct);
-            }
-            Gate::Mand { gates } => {
-                gates.par_iter().for_each(|(input1, input2, output)| {
-                    if self.was_computed(output, values)
-                        || !self.was_computed(input1, values)
-                        || !self.was_computed(input2, values)
-                    {
-                        return;
-                    }
-
-                    let input1_ct = self.get_output(input1, values);
-                    let input2_ct = self.get_output(input2, values);
-                    if self.was_computed(output, values) {
-                        return;
-                    }
-                    let output_ct = self.server_key.and(&input1_ct, &input2_ct);
-                    values.insert(*output, output_ct);
-                });
-            }
+        match instr.wires().as_slice() {
+            [wires] => execute(wires),
+            batch => batch.par_iter().for_each(execute),
         }
     }
 
 }
 
+/// Wires of one `execute_all` call, the output wires claimed by a thread and the
+/// progress the call reports to
+struct Run<'a, B: BooleanBackend> {
+    values: &'a DashMap<u32, B::Ciphertext>,
+    claimed: DashSet<u32>,
+    progress: Option<&'a ProgressTracker>,
+}
+
+impl<B: BooleanBackend> Run<'_, B> {
+    fn gate_done(&self) {
+        if let Some(progress) = self.progress {
+            progress.gate_done();
+        }
+    }
+}
+
 fn parse_header(header: &str) -> (usize, u32) {
     let parts: Vec<&str> = header.split_whitespace().collect();
     (
@@ -336,24 +442,9 @@ fn parse_mand(parts: &[&str]) -> Gate {
 }
 
 fn update_reachability(gate: &Gate, reachable: &mut HashSet<u32>) {
-    match gate {
-        Gate::And { input1, input2, output } |
-        Gate::Xor { input1, input2, output } => {
-            if reachable.contains(input1) && reachable.contains(input2) {
-                reachable.insert(*output);
-            }
-        }
-        Gate::Inv { input, output } => {
-            if reachable.contains(input) {
-                reachable.insert(*output);
-            }
-        }
-        Gate::Mand { gates } => {
-            for (in1, in2, out) in gates {
-                if reachable.contains(in1) && reachable.contains(in2) {
-                    reachable.insert(*out);
-                }
-            }
+    for (inputs, output) in gate.wires() {
+        if inputs.iter().all(|wire| reachable.contains(wire)) {
+            reachable.insert(output);
         }
     }
 }
@@ -0,0 +1,204 @@
+//! Noise diagnostics: bootstrap failure statistics per gate type
+//!
+//! The tfhe boolean API does not expose ciphertext noise variance, so noise is
+//! measured indirectly: every gate output is decrypted and compared with the gate
+//! applied to its decrypted inputs. A mismatch means that bootstrap failed. The
+//! observed rates are reported next to the per-block failure probability implied
+//! by the parameter set's published failure bound.
+
+use super::*;
+use crate::backend::Cleartext;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Evaluation and failure counts for one gate type
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct GateFailureStats {
+    /// Number of elementary gates checked
+    pub evaluated: usize,
+    /// Number of gates whose output decrypted to the wrong value
+    pub failures: usize,
+}
+
+impl GateFailureStats {
+    /// Observed failure rate
+    pub fn failure_rate(&self) -> f64 {
+        if self.evaluated == 0 {
+            return 0.0;
+        }
+        self.failures as f64 / self.evaluated as f64
+    }
+}
+
+/// Result of a noise measurement run
+#[derive(Debug, Clone)]
+pub struct NoiseReport {
+    /// Number of blocks evaluated
+    pub samples: usize,
+    /// Failure statistics keyed by gate opcode
+    pub per_gate: BTreeMap<&'static str, GateFailureStats>,
+    /// Bootstrapped operations needed for one block (key expansion excluded)
+    pub block_bootstraps: usize,
+    /// log2 of the per-bootstrap failure probability of the parameter set, if known
+    pub bootstrap_failure_log2: Option<f64>,
+}
+
+impl NoiseReport {
+    /// Total number of failed gates across all gate types
+    pub fn total_failures(&self) -> usize {
+        self.per_gate.values().map(|stats| stats.failures).sum()
+    }
+
+    /// Estimated probability that at least one bootstrap fails in a block,
+    /// `1 - (1 - p)^n` for `n` bootstraps, or `None` for unknown parameter sets
+    pub fn block_failure_probability(&self) -> Option<f64> {
+        self.bootstrap_failure_log2.map(|log2_p| {
+            let p = log2_p.exp2();
+            -(self.block_bootstraps as f64 * (-p).ln_1p()).exp_m1()
+        })
+    }
+}
+
+impl fmt::Display for NoiseReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Noise report over {} block(s)", self.samples)?;
+        for (gate, stats) in &self.per_gate {
+            writeln!(
+                f,
+                "  {:<5} evaluated {:>10}  failures {:>6}  rate {:.3e}",
+                gate,
+                stats.evaluated,
+                stats.failures,
+                stats.failure_rate()
+            )?;
+        }
+        writeln!(f, "  bootstraps per block: {}", self.block_bootstraps)?;
+        match (self.bootstrap_failure_log2, self.block_failure_probability()) {
+            (Some(log2_p), Some(block_p)) => {
+                writeln!(f, "  bootstrap failure probability: 2^{:.0}", log2_p)?;
+                write!(f, "  estimated block failure probability: {:.3e}", block_p)
+            }
+            _ => write!(f, "  bootstrap failure probability: unknown for this parameter set"),
+        }
+    }
+}
+
+/// Returns log2 of the published per-bootstrap failure probability for the
+/// built-in tfhe boolean parameter sets
+pub fn bootstrap_failure_log2(parameters: &BooleanParameters) -> Option<f64> {
+    if *parameters == DEFAULT_PARAMETERS || *parameters == DEFAULT_PARAMETERS_KS_PBS {
+        Some(-64.0)
+    } else if *parameters == PARAMETERS_ERROR_PROB_2_POW_MINUS_165
+        || *parameters == PARAMETERS_ERROR_PROB_2_POW_MINUS_165_KS_PBS
+    {
+        Some(-165.0)
+    } else {
+        None
+    }
+}
+
+impl BoolFheAes {
+    /// Expands `key` and evaluates `samples` pseudorandom blocks, decrypting every
+    /// gate output to collect bootstrap failure statistics per gate type.
+    ///
+    /// This is a diagnostic facility: it needs the `ClientKey`.
+    pub fn measure_noise(
+        &self,
+        client_key: &ClientKey,
+        key: &[u8; AES_128_KEY_SIZE],
+        samples: usize,
+    ) -> NoiseReport {
+        let mut per_gate = BTreeMap::new();
+
+        self.expand_key(BoolFheAes::encrypt_key(client_key, key));
+        self.count_failures(client_key, &self.expanded_key_outputs, &mut per_gate);
+
+        // AES in counter mode under `key` gives cheap pseudorandom sample blocks
+        let counters = (0..samples).map(|i| (i as u128).to_be_bytes()).collect();
+        for block in encrypt_reference_aes128(counters, *key) {
+            let block: [u8; AES_BLOCK_SIZE] = block.into();
+            let values = self
+                .execute_wires(BoolFheAes::encrypt_iv(client_key, &block), None)
+                .expect("Block evaluation was cancelled");
+            self.count_failures(client_key, &values, &mut per_gate);
+        }
+
+        NoiseReport {
+            samples,
+            per_gate,
+            block_bootstraps: self.block_bootstraps(),
+            bootstrap_failure_log2: bootstrap_failure_log2(&self.parameter_set.parameters()),
+        }
+    }
+
+    /// Checks every gate whose output is in `values` against its decrypted inputs
+    fn count_failures(
+        &self,
+        client_key: &ClientKey,
+        values: &DashMap<u32, Ciphertext>,
+        per_gate: &mut BTreeMap<&'static str, GateFailureStats>,
+    ) {
+        let decrypt = |wire: &u32| {
+            let ct = values
+                .get(wire)
+                .unwrap_or_else(|| self.expanded_key_outputs.get(wire).expect("Output not found"));
+            client_key.decrypt(&ct)
+        };
+
+        for gate in &self.instructions {
+            let stats = per_gate.entry(gate.name()).or_default();
+            for (inputs, output) in gate.wires() {
+                if !values.contains_key(&output) {
+                    continue;
+                }
+                let inputs: Vec<bool> = inputs.iter().map(decrypt).collect();
+                let expected = gate.apply(&Cleartext, &inputs);
+                stats.evaluated += 1;
+                if decrypt(&output) != expected {
+                    stats.failures += 1;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_block_failure_probability() {
+        let report = NoiseReport {
+            samples: 0,
+            per_gate: BTreeMap::new(),
+            block_bootstraps: 1 << 14,
+            bootstrap_failure_log2: Some(-64.0),
+        };
+        let p = report.block_failure_probability().unwrap();
+        assert!((p.log2() - (-50.0)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_measure_noise_counts_gates() {
+        let (client_key, server_key) = gen_keys();
+        let fhe_aes = BoolFheAes::new(server_key);
+        let report = fhe_aes.measure_noise(&client_key, &[0; 16], 1);
+
+        assert_eq!(report.total_failures(), 0);
+        assert!(report.per_gate["MAND"].evaluated > 0);
+        assert!(report.per_gate["XOR"].evaluated > 0);
+        assert_eq!(report.bootstrap_failure_log2, Some(-64.0));
+        assert_eq!(report.block_bootstraps, Circuit::aes_128().stats().block_bootstraps);
+    }
+
+    #[test]
+    fn test_measure_noise_on_other_circuit() {
+        let (client_key, server_key) = gen_keys();
+        let fhe_aes = BoolFheAes::with_circuit(server_key, ParameterSet::Default, AesCircuit::BoyarPeralta);
+        let report = fhe_aes.measure_noise(&client_key, &[0; 16], 1);
+
+        let bootstraps = AesCircuit::BoyarPeralta.circuit().stats().block_bootstraps;
+        assert_eq!(report.block_bootstraps, bootstraps);
+        assert_ne!(bootstraps, Circuit::aes_128().stats().block_bootstraps);
+    }
+}
@@ -0,0 +1,263 @@
+//! Wall-time profiler for key expansion and block evaluation
+
+use crate::circuit::{depth, Gate};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Number of gates evaluated and the wall time spent evaluating them
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct GateTiming {
+    pub gates: u64,
+    pub nanos: u64,
+}
+
+impl GateTiming {
+    fn add(&mut self, other: GateTiming) {
+        self.gates += other.gates;
+        self.nanos += other.nanos;
+    }
+}
+
+/// Time a worker thread spent evaluating gates and spinning on missing inputs
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct ThreadTiming {
+    /// Rayon thread index, or the pool size for a thread outside the pool
+    pub thread: usize,
+    pub busy_nanos: u64,
+    pub spin_nanos: u64,
+}
+
+/// Timings collected by a `BoolFheAes` built with `with_profiler`
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Profile {
+    /// Wall time during which a key expansion or a block was being evaluated
+    pub wall_nanos: u64,
+    /// Share of the pool threads' wall time spent evaluating gates
+    pub utilization: f64,
+    /// Time spent in `wait_for_inputs` without evaluating any gate
+    pub spin_nanos: u64,
+    /// `expand_key` or `block`
+    pub by_phase: BTreeMap<String, GateTiming>,
+    /// Gate opcode, as returned by `Gate::name`
+    pub by_gate: BTreeMap<String, GateTiming>,
+    /// AES round, see `Circuit::gate_rounds`
+    pub by_round: BTreeMap<u32, GateTiming>,
+    /// Dependency level of the gate outputs
+    pub by_level: BTreeMap<u32, GateTiming>,
+    pub threads: Vec<ThreadTiming>,
+    #[serde(skip)]
+    stacks: BTreeMap<(&'static str, u32, u32, &'static str), GateTiming>,
+}
+
+impl Profile {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Profile serialization failed")
+    }
+
+    /// Folded stacks (`phase;round;level;gate microseconds`) for flamegraph.pl or inferno
+    pub fn to_folded(&self) -> String {
+        let mut folded = String::new();
+        for ((phase, round, level, gate), timing) in &self.stacks {
+            let micros = timing.nanos / 1000;
+            if micros > 0 {
+                writeln!(folded, "{};round {};level {};{} {}", phase, round, level, gate, micros).unwrap();
+            }
+        }
+        if self.spin_nanos >= 1000 {
+            writeln!(folded, "spin {}", self.spin_nanos / 1000).unwrap();
+        }
+        folded
+    }
+}
+
+/// Start of a `wait_for_inputs` loop on the current thread
+pub(crate) struct Wait {
+    start: Instant,
+    accounted: u64,
+}
+
+/// Per-thread counters, so that recording a gate never contends with other threads
+#[derive(Default)]
+struct ThreadRecorder {
+    /// Timings per `(key expansion, gate index)`
+    gates: Mutex<HashMap<(bool, usize), GateTiming>>,
+    busy: AtomicU64,
+    spin: AtomicU64,
+}
+
+/// Wall time during which at least one evaluation was running; concurrent blocks
+/// of `aes_ctr_blocks` are only counted once
+#[derive(Default)]
+struct WallClock {
+    active: usize,
+    since: Option<Instant>,
+    total: Duration,
+}
+
+/// Records gate timings of `execute_all` when profiling is enabled
+pub(crate) struct Profiler {
+    names: Vec<&'static str>,
+    rounds: Vec<u32>,
+    levels: Vec<u32>,
+    pool_threads: usize,
+    threads: Vec<ThreadRecorder>,
+    wall: Mutex<WallClock>,
+}
+
+impl Profiler {
+    pub(crate) fn new(gates: &[Gate], output_end: u32) -> Self {
+        let wire_levels = depth::wire_levels(gates, output_end);
+        let pool_threads = rayon::current_num_threads();
+        Self {
+            names: gates.iter().map(Gate::name).collect(),
+            rounds: depth::gate_rounds(gates, output_end),
+            levels: gates
+                .iter()
+                .map(|gate| gate.wires().iter().map(|(_, out)| wire_levels[*out as usize]).max().unwrap_or(0))
+                .collect(),
+            pool_threads,
+            // One more slot for threads outside the pool
+            threads: (0..=pool_threads).map(|_| ThreadRecorder::default()).collect(),
+            wall: Mutex::new(WallClock::default()),
+        }
+    }
+
+    fn thread(&self) -> &ThreadRecorder {
+        let index = rayon::current_thread_index().unwrap_or(self.pool_threads);
+        &self.threads[index.min(self.pool_threads)]
+    }
+
+    pub(crate) fn start_run(&self) {
+        let mut wall = self.wall.lock().unwrap();
+        if wall.active == 0 {
+            wall.since = Some(Instant::now());
+        }
+        wall.active += 1;
+    }
+
+    pub(crate) fn finish_run(&self) {
+        let mut wall = self.wall.lock().unwrap();
+        wall.active -= 1;
+        if wall.active == 0 {
+            let since = wall.since.take().expect("Run was not started");
+            wall.total += since.elapsed();
+        }
+    }
+
+    pub(crate) fn record_gate(&self, index: usize, expand_key: bool, elapsed: Duration) {
+        let thread = self.thread();
+        let nanos = elapsed.as_nanos() as u64;
+        thread.busy.fetch_add(nanos, Ordering::Relaxed);
+        thread.gates.lock().unwrap().entry((expand_key, index)).or_default().add(GateTiming { gates: 1, nanos });
+    }
+
+    pub(crate) fn start_wait(&self) -> Wait {
+        Wait { start: Instant::now(), accounted: self.accounted() }
+    }
+
+    /// Records the part of the wait not spent on gates (or nested waits) run by
+    /// `yield_or_compute` on this thread
+    pub(crate) fn finish_wait(&self, wait: Wait) {
+        let elapsed = wait.start.elapsed().as_nanos() as u64;
+        let nested = self.accounted() - wait.accounted;
+        self.thread().spin.fetch_add(elapsed.saturating_sub(nested), Ordering::Relaxed);
+    }
+
+    fn accounted(&self) -> u64 {
+        let thread = self.thread();
+        thread.busy.load(Ordering::Relaxed) + thread.spin.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn report(&self) -> Profile {
+        let mut profile = Profile::default();
+        let wall = self.wall.lock().unwrap();
+        let running = wall.since.map_or(Duration::ZERO, |since| since.elapsed());
+        profile.wall_nanos = (wall.total + running).as_nanos() as u64;
+
+        let mut busy = 0;
+        for (i, thread) in self.threads.iter().enumerate() {
+            let timing = ThreadTiming {
+                thread: i,
+                busy_nanos: thread.busy.load(Ordering::Relaxed),
+                spin_nanos: thread.spin.load(Ordering::Relaxed),
+            };
+            busy += timing.busy_nanos;
+            profile.spin_nanos += timing.spin_nanos;
+            if timing.busy_nanos > 0 || timing.spin_nanos > 0 {
+                profile.threads.push(timing);
+            }
+
+            for (&(expand_key, index), &timing) in thread.gates.lock().unwrap().iter() {
+                let phase = if expand_key { "expand_key" } else { "block" };
+                let (name, round, level) = (self.names[index], self.rounds[index], self.levels[index]);
+                profile.by_phase.entry(phase.to_string()).or_default().add(timing);
+                profile.by_gate.entry(name.to_string()).or_default().add(timing);
+                profile.by_round.entry(round).or_default().add(timing);
+                profile.by_level.entry(level).or_default().add(timing);
+                profile.stacks.entry((phase, round, level, name)).or_default().add(timing);
+            }
+        }
+
+        if profile.wall_nanos > 0 {
+            profile.utilization = busy as f64 / (profile.wall_nanos as f64 * self.pool_threads as f64);
+        }
+        profile
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::Cleartext;
+    use crate::BoolFheAes;
+
+    #[test]
+    fn test_profile_counts_every_gate() {
+        let fhe_aes = BoolFheAes::new(Cleartext).with_profiler();
+        fhe_aes.expand_key([false; 128]);
+        fhe_aes.aes_ctr_blocks([false; 128], 2);
+
+        let profile = fhe_aes.profile().unwrap();
+        let block_gates = fhe_aes.block_gate_count() as u64;
+        let key_gates = fhe_aes.instructions.iter().map(|gate| gate.wires().len() as u64).sum::<u64>() - block_gates;
+        assert_eq!(profile.by_phase["block"].gates, 2 * block_gates);
+        assert_eq!(profile.by_phase["expand_key"].gates, key_gates);
+
+        let total = |timings: Vec<&GateTiming>| timings.into_iter().fold(GateTiming::default(), |mut sum, t| {
+            sum.add(*t);
+            sum
+        });
+        let by_phase = total(profile.by_phase.values().collect());
+        assert_eq!(total(profile.by_gate.values().collect()), by_phase);
+        assert_eq!(total(profile.by_round.values().collect()), by_phase);
+        assert_eq!(total(profile.by_level.values().collect()), by_phase);
+        assert_eq!(profile.by_round.keys().max(), Some(&10));
+        assert!(profile.wall_nanos > 0 && profile.utilization > 0.0);
+    }
+
+    #[test]
+    fn test_profile_outputs() {
+        let fhe_aes = BoolFheAes::new(Cleartext).with_profiler();
+        fhe_aes.expand_key([false; 128]);
+        fhe_aes.execute([false; 128]);
+        let profile = fhe_aes.profile().unwrap();
+
+        let json: serde_json::Value = serde_json::from_str(&profile.to_json()).unwrap();
+        assert_eq!(json["by_phase"]["block"]["gates"], profile.by_phase["block"].gates);
+
+        for line in profile.to_folded().lines() {
+            let (stack, micros) = line.rsplit_once(' ').unwrap();
+            assert!(micros.parse::<u64>().unwrap() > 0);
+            assert!(stack == "spin" || stack.split(';').count() == 4, "{}", line);
+        }
+    }
+
+    #[test]
+    fn test_profiler_is_optional() {
+        assert!(BoolFheAes::new(Cleartext).profile().is_none());
+    }
+}
@@ -0,0 +1,193 @@
+//! Progress reporting and cancellation for long evaluations
+
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// Number of gates between two progress reports
+const REPORT_INTERVAL: usize = 1024;
+
+/// Shared flag that stops a running evaluation.
+///
+/// Clones share the flag, so a token kept by the caller (or set from a signal
+/// handler through `flag`) cancels the evaluator it was given to.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Returns the underlying flag, e.g. to register it with a signal handler
+    pub fn flag(&self) -> Arc<AtomicBool> {
+        self.0.clone()
+    }
+}
+
+/// Error returned by an evaluation stopped through its `CancellationToken`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("evaluation was cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+/// Progress of one `execute` or `aes_ctr_blocks` call
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Progress {
+    /// Gates evaluated so far, key expansion excluded
+    pub gates_done: usize,
+    /// Gates to evaluate for all blocks
+    pub gates_total: usize,
+    pub blocks_done: usize,
+    pub blocks_total: usize,
+}
+
+/// Callback receiving the progress of every call of an evaluator
+pub(crate) type ProgressCallback = Arc<dyn Fn(Progress) + Send + Sync>;
+
+/// Progress counters of one evaluation call and the callback they are reported to.
+/// Every call gets its own tracker, so concurrent calls do not mix their counts.
+#[derive(Default)]
+pub(crate) struct ProgressTracker {
+    callback: Option<ProgressCallback>,
+    gates_done: AtomicUsize,
+    gates_total: usize,
+    blocks_done: AtomicUsize,
+    blocks_total: usize,
+}
+
+impl ProgressTracker {
+    /// Starts counting a call over `blocks` blocks and reports it
+    pub(crate) fn new(callback: Option<ProgressCallback>, blocks: usize, gates_per_block: usize) -> Self {
+        let tracker = Self {
+            callback,
+            gates_total: blocks * gates_per_block,
+            blocks_total: blocks,
+            ..Default::default()
+        };
+        tracker.report();
+        tracker
+    }
+
+    /// Counts one evaluated gate, reporting every `REPORT_INTERVAL` gates
+    pub(crate) fn gate_done(&self) {
+        let done = self.gates_done.fetch_add(1, Ordering::Relaxed) + 1;
+        if done % REPORT_INTERVAL == 0 {
+            self.report();
+        }
+    }
+
+    pub(crate) fn block_done(&self) {
+        self.blocks_done.fetch_add(1, Ordering::Relaxed);
+        self.report();
+    }
+
+    pub(crate) fn snapshot(&self) -> Progress {
+        Progress {
+            gates_done: self.gates_done.load(Ordering::Relaxed),
+            gates_total: self.gates_total,
+            blocks_done: self.blocks_done.load(Ordering::Relaxed),
+            blocks_total: self.blocks_total,
+        }
+    }
+
+    fn report(&self) {
+        if let Some(callback) = &self.callback {
+            callback(self.snapshot());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::Cleartext;
+    use crate::circuit::Circuit;
+    use crate::fhe_aes::{Scheduler, SchedulerConfig};
+    use crate::BoolFheAes;
+    use std::sync::mpsc;
+
+    #[test]
+    fn test_progress_reaches_totals() {
+        let (sender, receiver) = mpsc::channel();
+        let fhe_aes = BoolFheAes::new(Cleartext).with_progress(move |progress| {
+            sender.send(progress).unwrap();
+        });
+        fhe_aes.expand_key([false; 128]);
+        let outputs = fhe_aes.aes_ctr_blocks([false; 128], 3);
+        assert_eq!(outputs.len(), 3);
+
+        let reports: Vec<Progress> = receiver.try_iter().collect();
+        let last = *reports.last().unwrap();
+        assert_eq!(last, fhe_aes.progress());
+        assert_eq!(last.blocks_done, 3);
+        assert_eq!(last.blocks_total, 3);
+        assert_eq!(last.gates_done, last.gates_total);
+        assert!(reports.len() > 3);
+    }
+
+    #[test]
+    fn test_scheduled_job_reports_progress() {
+        let (sender, receiver) = mpsc::channel();
+        let fhe_aes = BoolFheAes::new(Cleartext).with_progress(move |progress| {
+            sender.send(progress).unwrap();
+        });
+        fhe_aes.expand_key([false; 128]);
+        let fhe_aes = Arc::new(fhe_aes);
+
+        let scheduler = Arc::new(Scheduler::new(&Circuit::aes_128(), SchedulerConfig::default()));
+        scheduler.start();
+        let job = scheduler.submit("tenant", Arc::clone(&fhe_aes), vec![[false; 128]; 2]).unwrap();
+        assert_eq!(job.wait().unwrap().len(), 2);
+        scheduler.shutdown();
+
+        let last = *receiver.try_iter().collect::<Vec<Progress>>().last().unwrap();
+        assert_eq!(last, fhe_aes.progress());
+        assert_eq!((last.blocks_done, last.blocks_total), (2, 2));
+        assert_eq!(last.gates_done, last.gates_total);
+    }
+
+    #[test]
+    fn test_cancelled_evaluation_stops() {
+        let token = CancellationToken::new();
+        let fhe_aes = BoolFheAes::new(Cleartext).with_cancellation(token.clone());
+        fhe_aes.expand_key([false; 128]);
+
+        token.cancel();
+        assert_eq!(fhe_aes.try_aes_ctr_blocks([false; 128], 4).err(), Some(Cancelled));
+        assert_eq!(fhe_aes.try_execute([false; 128]).err(), Some(Cancelled));
+        assert_eq!(fhe_aes.progress().gates_done, 0);
+        assert!(fhe_aes.progress().gates_total > 0);
+    }
+
+    #[test]
+    fn test_cancel_from_callback() {
+        // Cancelling mid-block returns instead of waiting for inputs forever
+        let token = CancellationToken::new();
+        let canceller = token.clone();
+        let fhe_aes = BoolFheAes::new(Cleartext)
+            .with_cancellation(token)
+            .with_progress(move |progress| {
+                if progress.gates_done >= 4 * REPORT_INTERVAL {
+                    canceller.cancel();
+                }
+            });
+        fhe_aes.expand_key([false; 128]);
+        assert_eq!(fhe_aes.try_execute([false; 128]).err(), Some(Cancelled));
+        assert!(fhe_aes.progress().gates_done < fhe_aes.progress().gates_total);
+    }
+}
@@ -0,0 +1,595 @@
+//! Job queue batching block evaluations of several evaluators into shared levels
+//!
+//! Concurrent `aes_ctr_blocks` calls each spin up their own `execute_all` and
+//! fight over the rayon pool. The scheduler instead queues blocks from every
+//! evaluator of the same circuit, whatever their keys, and evaluates a batch of
+//! them one dependency level at a time, so that every level offers
+//! `blocks * width` independent gates to the pool.
+
+use super::*;
+use anyhow::{ensure, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Condvar, Mutex, Weak};
+use std::time::Duration;
+
+type Block<B> = [<B as BooleanBackend>::Ciphertext; AES_128_OUTPUT_BITSIZE];
+type BlockResult<B> = Result<(usize, Block<B>), Cancelled>;
+
+/// Limits of a `Scheduler`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SchedulerConfig {
+    /// Most blocks evaluated together in one batch
+    pub max_batch_blocks: usize,
+    /// Most blocks a tenant may have queued; larger submissions are rejected
+    /// before any block is computed
+    pub max_queued_blocks_per_tenant: usize,
+}
+
+impl Default for SchedulerConfig {
+    fn default() -> Self {
+        Self {
+            max_batch_blocks: 16,
+            max_queued_blocks_per_tenant: 1024,
+        }
+    }
+}
+
+/// Queue depth, throughput and latency of a `Scheduler`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SchedulerMetrics {
+    /// Blocks waiting for a batch
+    pub queued_blocks: usize,
+    pub queued_jobs: usize,
+    /// Queued blocks per tenant
+    pub tenant_queued_blocks: BTreeMap<String, usize>,
+    pub completed_jobs: u64,
+    pub completed_blocks: u64,
+    /// Jobs stopped by their evaluator's cancellation token or by dropping their
+    /// `JobHandle`, with all their remaining blocks dequeued
+    pub cancelled_jobs: u64,
+    /// Jobs rejected by the per-tenant limit
+    pub rejected_jobs: u64,
+    pub batches: u64,
+    pub mean_batch_blocks: f64,
+    /// Time from submission to the start of the batch evaluating a block
+    pub mean_queue_latency: Duration,
+    pub max_queue_latency: Duration,
+    /// Time from submission to the last block of a job
+    pub mean_job_latency: Duration,
+    pub max_job_latency: Duration,
+}
+
+/// Results of a submitted job; yields every block with its index as soon as its
+/// batch completes, or `Err(Cancelled)` if the evaluator's token was cancelled.
+/// Dropping the handle dequeues the blocks not evaluated yet.
+pub struct JobHandle<B: BooleanBackend> {
+    receiver: mpsc::Receiver<BlockResult<B>>,
+    blocks: usize,
+    progress: Arc<JobProgress>,
+    queue: Weak<Queue<B>>,
+}
+
+impl<B: BooleanBackend> JobHandle<B> {
+    /// Waits for every block and returns them in submission order
+    pub fn wait(self) -> Result<Vec<Block<B>>, Cancelled> {
+        let mut blocks: Vec<Option<Block<B>>> = (0..self.blocks).map(|_| None).collect();
+        for result in self {
+            let (index, block) = result?;
+            blocks[index] = Some(block);
+        }
+        Ok(blocks.into_iter().map(|block| block.expect("Scheduler dropped a block")).collect())
+    }
+}
+
+impl<B: BooleanBackend> Iterator for JobHandle<B> {
+    type Item = BlockResult<B>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.receiver.recv().ok()
+    }
+}
+
+impl<B: BooleanBackend> Drop for JobHandle<B> {
+    fn drop(&mut self) {
+        self.progress.abandoned.store(true, Ordering::Relaxed);
+        let Some(queue) = self.queue.upgrade() else { return };
+        let mut state = queue.state.lock().unwrap();
+        let Some(position) = state.tenants.iter().position(|tenant| {
+            state.jobs[tenant].iter().any(|job| Arc::ptr_eq(&job.progress, &self.progress))
+        }) else {
+            return;
+        };
+
+        let tenant = state.tenants[position].clone();
+        let jobs = state.jobs.get_mut(&tenant).expect("Tenant without jobs");
+        let index = jobs.iter().position(|job| Arc::ptr_eq(&job.progress, &self.progress)).unwrap();
+        let job = jobs.remove(index).unwrap();
+        if jobs.is_empty() {
+            state.jobs.remove(&tenant);
+            state.tenants.remove(position);
+        }
+        drop(state);
+        queue.settle(&job.progress, job.pending.len(), false);
+    }
+}
+
+/// Completion of the blocks of one job, shared by its handle and its blocks in flight
+struct JobProgress {
+    submitted: Instant,
+    /// Blocks neither evaluated nor dropped yet
+    remaining: AtomicUsize,
+    /// Set once a block is dropped unevaluated: the job counts as cancelled
+    cancelled: AtomicBool,
+    /// Set when the `JobHandle` is dropped: nobody waits for the blocks anymore
+    abandoned: AtomicBool,
+    /// Gates and blocks reported to the evaluator's progress callback
+    tracker: Arc<ProgressTracker>,
+}
+
+struct Job<B: BooleanBackend> {
+    fhe_aes: Arc<BoolFheAes<B>>,
+    pending: VecDeque<(usize, Block<B>)>,
+    sender: mpsc::Sender<BlockResult<B>>,
+    progress: Arc<JobProgress>,
+}
+
+/// A block taken from the queue, with the wires computed for it so far
+struct BatchBlock<B: BooleanBackend> {
+    fhe_aes: Arc<BoolFheAes<B>>,
+    index: usize,
+    values: DashMap<u32, B::Ciphertext>,
+    sender: mpsc::Sender<BlockResult<B>>,
+    progress: Arc<JobProgress>,
+}
+
+struct QueueState<B: BooleanBackend> {
+    /// Tenants with queued jobs, in round-robin order
+    tenants: VecDeque<String>,
+    jobs: HashMap<String, VecDeque<Job<B>>>,
+    closed: bool,
+}
+
+impl<B: BooleanBackend> QueueState<B> {
+    fn queued_blocks(&self, tenant: &str) -> usize {
+        self.jobs.get(tenant).map_or(0, |jobs| jobs.iter().map(|job| job.pending.len()).sum())
+    }
+}
+
+#[derive(Default)]
+struct MetricsState {
+    completed_jobs: u64,
+    completed_blocks: u64,
+    cancelled_jobs: u64,
+    rejected_jobs: u64,
+    batches: u64,
+    batched_blocks: u64,
+    queue_latency: Duration,
+    max_queue_latency: Duration,
+    job_latency: Duration,
+    max_job_latency: Duration,
+}
+
+/// Queued jobs and metrics, shared by the scheduler and the job handles
+struct Queue<B: BooleanBackend> {
+    state: Mutex<QueueState<B>>,
+    queued: Condvar,
+    metrics: Mutex<MetricsState>,
+}
+
+impl<B: BooleanBackend> Queue<B> {
+    /// Counts `blocks` blocks of a job as evaluated, or as dropped unevaluated,
+    /// and the job as completed or cancelled once none is left
+    fn settle(&self, progress: &JobProgress, blocks: usize, evaluated: bool) {
+        if blocks == 0 {
+            return;
+        }
+        let mut metrics = self.metrics.lock().unwrap();
+        if evaluated {
+            metrics.completed_blocks += blocks as u64;
+        } else {
+            progress.cancelled.store(true, Ordering::Relaxed);
+        }
+        if progress.remaining.fetch_sub(blocks, Ordering::Relaxed) == blocks {
+            if progress.cancelled.load(Ordering::Relaxed) {
+                metrics.cancelled_jobs += 1;
+            } else {
+                let latency = progress.submitted.elapsed();
+                metrics.completed_jobs += 1;
+                metrics.job_latency += latency;
+                metrics.max_job_latency = metrics.max_job_latency.max(latency);
+            }
+        }
+    }
+}
+
+/// One elementary gate of the block evaluation: gate index, inputs and output
+type Element = (usize, Vec<u32>, u32);
+
+/// Queues block evaluations and runs them in shared batches, see the module docs
+pub struct Scheduler<B: BooleanBackend> {
+    config: SchedulerConfig,
+    circuit_fingerprint: String,
+    /// Gates outside key expansion, grouped by dependency level
+    levels: Vec<Vec<Element>>,
+    output_end: u32,
+    queue: Arc<Queue<B>>,
+}
+
+impl<B: BooleanBackend + 'static> Scheduler<B> {
+    /// Creates a scheduler for evaluators of `circuit`; call `start` to run it
+    pub fn new(circuit: &Circuit, config: SchedulerConfig) -> Self {
+        let mut wire_levels = vec![0usize; circuit.output_end as usize];
+        let mut levels: Vec<Vec<Element>> = Vec::new();
+        for (i, gate) in circuit.gates.iter().enumerate() {
+            for (inputs, output) in gate.wires() {
+                if circuit.key_expand_reachable.contains(&output) {
+                    continue;
+                }
+                let level = inputs.iter().map(|wire| wire_levels[*wire as usize]).max().unwrap_or(0);
+                wire_levels[output as usize] = level + 1;
+                if levels.len() <= level {
+                    levels.resize_with(level + 1, Vec::new);
+                }
+                levels[level].push((i, inputs, output));
+            }
+        }
+
+        Self {
+            config,
+            circuit_fingerprint: circuit.fingerprint(),
+            levels,
+            output_end: circuit.output_end,
+            queue: Arc::new(Queue {
+                state: Mutex::new(QueueState { tenants: VecDeque::new(), jobs: HashMap::new(), closed: false }),
+                queued: Condvar::new(),
+                metrics: Mutex::new(MetricsState::default()),
+            }),
+        }
+    }
+
+    /// Spawns the worker thread evaluating batches until `shutdown`
+    pub fn start(self: &Arc<Self>) {
+        let scheduler = Arc::clone(self);
+        std::thread::spawn(move || {
+            while let Some(batch) = scheduler.next_batch() {
+                scheduler.evaluate_batch(batch);
+            }
+        });
+    }
+
+    /// Rejects further jobs; the worker stops once the queue is drained
+    pub fn shutdown(&self) {
+        self.queue.state.lock().unwrap().closed = true;
+        self.queue.queued.notify_all();
+    }
+
+    /// Queues the evaluation of `blocks` under `fhe_aes`, whose key must be expanded.
+    /// Tenants are served round-robin, one block at a time.
+    pub fn submit(
+        &self,
+        tenant: &str,
+        fhe_aes: Arc<BoolFheAes<B>>,
+        blocks: Vec<[B::Ciphertext; AES_BLOCK_SIZE_BITS]>,
+    ) -> Result<JobHandle<B>> {
+        ensure!(
+            fhe_aes.circuit_fingerprint() == self.circuit_fingerprint,
+            "Evaluator runs circuit {}, the scheduler runs {}",
+            fhe_aes.circuit_fingerprint(),
+            self.circuit_fingerprint
+        );
+        ensure!(!fhe_aes.expanded_key_outputs.is_empty(), "No AES key was expanded in this evaluator");
+
+        let count = blocks.len();
+        let mut state = self.queue.state.lock().unwrap();
+        if count > 0 {
+            self.check_limit(&state, tenant, count)?;
+        }
+
+        let (sender, receiver) = mpsc::channel();
+        let progress = Arc::new(JobProgress {
+            submitted: Instant::now(),
+            remaining: AtomicUsize::new(count),
+            cancelled: AtomicBool::new(false),
+            abandoned: AtomicBool::new(false),
+            tracker: fhe_aes.start_progress(count),
+        });
+        if count == 0 {
+            return Ok(JobHandle { receiver, blocks: count, progress, queue: Weak::new() });
+        }
+
+        let pending = blocks.into_iter().enumerate().collect();
+        let job = Job { fhe_aes, pending, sender, progress: Arc::clone(&progress) };
+        if !state.jobs.contains_key(tenant) {
+            state.tenants.push_back(tenant.to_string());
+        }
+        state.jobs.entry(tenant.to_string()).or_default().push_back(job);
+        drop(state);
+
+        self.queue.queued.notify_one();
+        Ok(JobHandle { receiver, blocks: count, progress, queue: Arc::downgrade(&self.queue) })
+    }
+
+    /// Queues `count` CTR blocks starting at the encrypted `iv`, like `aes_ctr_blocks`
+    pub fn submit_ctr(
+        &self,
+        tenant: &str,
+        fhe_aes: Arc<BoolFheAes<B>>,
+        iv: [B::Ciphertext; AES_BLOCK_SIZE_BITS],
+        count: usize,
+    ) -> Result<JobHandle<B>> {
+        // Checked before computing any counter; `submit` checks again under the lock
+        self.check_limit(&self.queue.state.lock().unwrap(), tenant, count)?;
+        let mut blocks = Vec::with_capacity(count);
+        if count > 0 {
+            blocks.extend(fhe_aes.get_blocks(&iv, count - 1));
+            blocks.insert(0, iv);
+        }
+        self.submit(tenant, fhe_aes, blocks)
+    }
+
+    /// Fails if the scheduler is shut down or `blocks` more blocks would exceed the
+    /// tenant's limit
+    fn check_limit(&self, state: &QueueState<B>, tenant: &str, blocks: usize) -> Result<()> {
+        ensure!(!state.closed, "Scheduler is shut down");
+        let queued = state.queued_blocks(tenant);
+        if queued.saturating_add(blocks) > self.config.max_queued_blocks_per_tenant {
+            self.queue.metrics.lock().unwrap().rejected_jobs += 1;
+            anyhow::bail!(
+                "Tenant '{}' has {} blocks queued, {} more exceed the limit of {}",
+                tenant,
+                queued,
+                blocks,
+                self.config.max_queued_blocks_per_tenant
+            );
+        }
+        Ok(())
+    }
+
+    pub fn metrics(&self) -> SchedulerMetrics {
+        let state = self.queue.state.lock().unwrap();
+        let metrics = self.queue.metrics.lock().unwrap();
+        let tenant_queued_blocks: BTreeMap<String, usize> =
+            state.jobs.keys().map(|tenant| (tenant.clone(), state.queued_blocks(tenant))).collect();
+
+        SchedulerMetrics {
+            queued_blocks: tenant_queued_blocks.values().sum(),
+            queued_jobs: state.jobs.values().map(VecDeque::len).sum(),
+            tenant_queued_blocks,
+            completed_jobs: metrics.completed_jobs,
+            completed_blocks: metrics.completed_blocks,
+            cancelled_jobs: metrics.cancelled_jobs,
+            rejected_jobs: metrics.rejected_jobs,
+            batches: metrics.batches,
+            mean_batch_blocks: metrics.batched_blocks as f64 / metrics.batches.max(1) as f64,
+            mean_queue_latency: metrics.queue_latency / metrics.batched_blocks.max(1) as u32,
+            max_queue_latency: metrics.max_queue_latency,
+            mean_job_latency: metrics.job_latency / metrics.completed_jobs.max(1) as u32,
+            max_job_latency: metrics.max_job_latency,
+        }
+    }
+
+    /// Waits for queued blocks and takes up to `max_batch_blocks` of them, one per
+    /// tenant in turn; returns `None` once shut down and drained
+    fn next_batch(&self) -> Option<Vec<BatchBlock<B>>> {
+        let mut state = self.queue.state.lock().unwrap();
+        while state.tenants.is_empty() {
+            if state.closed {
+                return None;
+            }
+            state = self.queue.queued.wait(state).unwrap();
+        }
+
+        let mut batch = Vec::with_capacity(self.config.max_batch_blocks);
+        let mut queue_latency = Duration::ZERO;
+        let mut max_queue_latency = Duration::ZERO;
+        while batch.len() < self.config.max_batch_blocks {
+            let Some(tenant) = state.tenants.pop_front() else { break };
+            let jobs = state.jobs.get_mut(&tenant).expect("Tenant without jobs");
+            let job = jobs.front_mut().expect("Tenant without jobs");
+            let (index, block) = job.pending.pop_front().expect("Job without blocks");
+
+            let values = DashMap::with_capacity(job.fhe_aes.output_end as usize);
+            for (i, input) in block.into_iter().enumerate() {
+                values.insert((AES_128_OUTPUT_BITSIZE + i) as u32, input);
+            }
+            let waited = job.progress.submitted.elapsed();
+            queue_latency += waited;
+            max_queue_latency = max_queue_latency.max(waited);
+            batch.push(BatchBlock {
+                fhe_aes: Arc::clone(&job.fhe_aes),
+                index,
+                values,
+                sender: job.sender.clone(),
+                progress: Arc::clone(&job.progress),
+            });
+
+            if job.pending.is_empty() {
+                jobs.pop_front();
+            }
+            if jobs.is_empty() {
+                state.jobs.remove(&tenant);
+            } else {
+                state.tenants.push_back(tenant);
+            }
+        }
+        drop(state);
+
+        let mut metrics = self.queue.metrics.lock().unwrap();
+        metrics.batches += 1;
+        metrics.batched_blocks += batch.len() as u64;
+        metrics.queue_latency += queue_latency;
+        metrics.max_queue_latency = metrics.max_queue_latency.max(max_queue_latency);
+        Some(batch)
+    }
+
+    /// Evaluates every block of `batch` level by level and delivers the outputs
+    fn evaluate_batch(&self, mut batch: Vec<BatchBlock<B>>) {
+        for level in &self.levels {
+            // Blocks of cancelled evaluators and dropped handles leave the rest of the batch
+            batch.retain(|block| {
+                let cancelled = block.fhe_aes.cancellation.is_cancelled();
+                if cancelled {
+                    let _ = block.sender.send(Err(Cancelled));
+                }
+                let dropped = cancelled || block.progress.abandoned.load(Ordering::Relaxed);
+                if dropped {
+                    self.queue.settle(&block.progress, 1, false);
+                }
+                !dropped
+            });
+
+            (0..batch.len() * level.len()).into_par_iter().for_each(|k| {
+                let block = &batch[k / level.len()];
+                let (gate, inputs, output) = &level[k % level.len()];
+                let input_cts: Vec<_> =
+                    inputs.iter().map(|input| block.fhe_aes.get_output(input, &block.values)).collect();
+                let output_ct = block.fhe_aes.apply_gate(*gate, *output, &input_cts);
+                block.values.insert(*output, output_ct);
+                block.progress.tracker.gate_done();
+            });
+        }
+
+        let output_start = self.output_end - AES_128_OUTPUT_BITSIZE as u32;
+        for block in batch {
+            let output = std::array::from_fn(|i| block.fhe_aes.get_output(&(output_start + i as u32), &block.values));
+            // A dropped handle only means nobody waits for the result anymore
+            block.progress.tracker.block_done();
+            let _ = block.sender.send(Ok((block.index, output)));
+            self.queue.settle(&block.progress, 1, true);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::Cleartext;
+    use crate::circuit::{bytes_to_wires, wires_to_bytes};
+
+    fn evaluator(key: u8) -> Arc<BoolFheAes<Cleartext>> {
+        let fhe_aes = BoolFheAes::new(Cleartext);
+        fhe_aes.expand_key(bytes_to_wires(&[key; 16]).try_into().unwrap());
+        Arc::new(fhe_aes)
+    }
+
+    fn block(byte: u8) -> [bool; AES_BLOCK_SIZE_BITS] {
+        bytes_to_wires(&[byte; 16]).try_into().unwrap()
+    }
+
+    fn scheduler(config: SchedulerConfig) -> Arc<Scheduler<Cleartext>> {
+        Arc::new(Scheduler::new(&Circuit::aes_128(), config))
+    }
+
+    #[test]
+    fn test_jobs_under_different_keys() {
+        let scheduler = scheduler(SchedulerConfig::default());
+        scheduler.start();
+
+        let jobs: Vec<_> = (0..3u8)
+            .map(|key| {
+                let blocks = (0..4).map(|i| block(key + i)).collect();
+                scheduler.submit(&format!("tenant-{}", key), evaluator(key), blocks).unwrap()
+            })
+            .collect();
+        let ctr = scheduler.submit_ctr("tenant-0", evaluator(9), block(0xff), 3).unwrap();
+
+        for (key, job) in jobs.into_iter().enumerate() {
+            let expected = encrypt_reference_aes128((0..4).map(|i| [key as u8 + i; 16]).collect(), [key as u8; 16]);
+            let outputs: Vec<_> = job.wait().unwrap().iter().map(|block| wires_to_bytes(block)).collect();
+            assert_eq!(outputs, expected.iter().map(|block| <[u8; 16]>::from(*block)).collect::<Vec<_>>());
+        }
+        let counters = vec![[0xff; 16], [0; 16], 1u128.to_be_bytes()];
+        let expected = encrypt_reference_aes128(counters, [9; 16]);
+        let outputs: Vec<_> = ctr.wait().unwrap().iter().map(|block| wires_to_bytes(block)).collect();
+        assert_eq!(outputs, expected.iter().map(|block| <[u8; 16]>::from(*block)).collect::<Vec<_>>());
+
+        let metrics = scheduler.metrics();
+        assert_eq!((metrics.completed_jobs, metrics.completed_blocks), (4, 15));
+        assert_eq!(metrics.queued_blocks, 0);
+        assert!(metrics.batches >= 1 && metrics.mean_batch_blocks >= 1.0);
+        assert!(metrics.max_job_latency >= metrics.mean_job_latency);
+        scheduler.shutdown();
+    }
+
+    #[test]
+    fn test_tenants_share_batches() {
+        // Not started: batches are taken by hand
+        let scheduler = scheduler(SchedulerConfig { max_batch_blocks: 3, ..Default::default() });
+        let busy = scheduler.submit("busy", evaluator(1), (0..5).map(block).collect()).unwrap();
+        let quiet = scheduler.submit("quiet", evaluator(2), vec![block(7)]).unwrap();
+        assert_eq!(scheduler.metrics().tenant_queued_blocks["busy"], 5);
+
+        let batch = scheduler.next_batch().unwrap();
+        let indices: Vec<_> = batch.iter().map(|block| block.index).collect();
+        assert_eq!(indices, [0, 0, 1]);
+        assert_eq!(batch.iter().filter(|block| Arc::ptr_eq(&block.progress, &batch[1].progress)).count(), 1);
+        scheduler.evaluate_batch(batch);
+        assert_eq!(quiet.wait().unwrap().len(), 1);
+
+        while scheduler.metrics().queued_blocks > 0 {
+            let batch = scheduler.next_batch().unwrap();
+            scheduler.evaluate_batch(batch);
+        }
+        assert_eq!(busy.wait().unwrap().len(), 5);
+        assert_eq!(scheduler.metrics().batches, 2);
+    }
+
+    #[test]
+    fn test_limits_and_validation() {
+        let scheduler = scheduler(SchedulerConfig { max_queued_blocks_per_tenant: 4, ..Default::default() });
+        let _queued = scheduler.submit("tenant", evaluator(1), (0..3).map(block).collect()).unwrap();
+        assert!(scheduler.submit("tenant", evaluator(1), (0..2).map(block).collect()).is_err());
+        assert!(scheduler.submit("other", evaluator(1), (0..2).map(block).collect()).is_ok());
+        assert!(scheduler.submit_ctr("tenant", evaluator(1), block(0), usize::MAX).is_err());
+        assert_eq!(scheduler.metrics().rejected_jobs, 2);
+
+        let unexpanded = Arc::new(BoolFheAes::new(Cleartext));
+        assert!(scheduler.submit("tenant", unexpanded, vec![block(0)]).is_err());
+        let other_circuit = BoolFheAes::with_circuit(Cleartext, ParameterSet::Default, AesCircuit::BoyarPeralta);
+        other_circuit.expand_key([false; 128]);
+        assert!(scheduler.submit("tenant", Arc::new(other_circuit), vec![block(0)]).is_err());
+
+        scheduler.shutdown();
+        assert!(scheduler.submit("new", evaluator(1), vec![block(0)]).is_err());
+    }
+
+    #[test]
+    fn test_cancelled_evaluator() {
+        let scheduler = scheduler(SchedulerConfig::default());
+        let token = CancellationToken::new();
+        let fhe_aes = BoolFheAes::new(Cleartext).with_cancellation(token.clone());
+        fhe_aes.expand_key([false; 128]);
+
+        let job = scheduler.submit("tenant", Arc::new(fhe_aes), vec![block(0), block(1)]).unwrap();
+        token.cancel();
+        let batch = scheduler.next_batch().unwrap();
+        scheduler.evaluate_batch(batch);
+        assert_eq!(job.wait().err(), Some(Cancelled));
+
+        let metrics = scheduler.metrics();
+        assert_eq!((metrics.completed_jobs, metrics.cancelled_jobs, metrics.completed_blocks), (0, 1, 0));
+    }
+
+    #[test]
+    fn test_dropped_handles() {
+        let scheduler = scheduler(SchedulerConfig { max_batch_blocks: 2, ..Default::default() });
+        drop(scheduler.submit("gone", evaluator(1), (0..3).map(block).collect()).unwrap());
+        let metrics = scheduler.metrics();
+        assert_eq!((metrics.queued_blocks, metrics.cancelled_jobs), (0, 1));
+        assert!(!metrics.tenant_queued_blocks.contains_key("gone"));
+
+        // Blocks already taken into a batch are dropped from it
+        let kept = scheduler.submit("kept", evaluator(2), vec![block(0)]).unwrap();
+        let left = scheduler.submit("left", evaluator(3), (0..2).map(block).collect()).unwrap();
+        let batch = scheduler.next_batch().unwrap();
+        drop(left);
+        assert_eq!(scheduler.metrics().queued_blocks, 0);
+        scheduler.evaluate_batch(batch);
+        assert_eq!(kept.wait().unwrap().len(), 1);
+
+        let metrics = scheduler.metrics();
+        assert_eq!((metrics.completed_jobs, metrics.cancelled_jobs, metrics.completed_blocks), (1, 2, 1));
+    }
+}
@@ -0,0 +1,68 @@
+//! Startup self-test of the embedded AES-128 circuit
+//!
+//! The circuit is embedded twice, once for `Circuit::aes_128` and once for
+//! `BoolFheAes`. Both copies are checked against the pinned fingerprint and
+//! evaluated in cleartext on the FIPS-197 known-answer vector, the first one with
+//! the circuit evaluator and the second one with the gate scheduler.
+
+use super::*;
+use crate::backend::Cleartext;
+use crate::circuit::{bytes_to_wires, wires_to_bytes};
+use anyhow::{ensure, Result};
+
+/// FIPS-197 appendix C.1 key
+const KAT_KEY: [u8; AES_128_KEY_SIZE] = [
+    0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e, 0x0f,
+];
+
+/// FIPS-197 appendix C.1 plaintext
+const KAT_PLAINTEXT: [u8; AES_BLOCK_SIZE] = [
+    0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff,
+];
+
+/// FIPS-197 appendix C.1 ciphertext
+const KAT_CIPHERTEXT: [u8; AES_BLOCK_SIZE] = [
+    0x69, 0xc4, 0xe0, 0xd8, 0x6a, 0x7b, 0x04, 0x30, 0xd8, 0xcd, 0xb7, 0x80, 0x70, 0xb4, 0xc5, 0x5a,
+];
+
+/// Checks that both embedded copies of the AES-128 circuit are unmodified and
+/// compute AES on the known-answer vector
+pub fn self_test() -> Result<()> {
+    let circuit = Circuit::aes_128();
+    ensure!(
+        circuit.fingerprint() == AES_128_CIRCUIT_FINGERPRINT,
+        "Embedded circuit has fingerprint {}, expected {}",
+        circuit.fingerprint(),
+        AES_128_CIRCUIT_FINGERPRINT
+    );
+    ensure!(
+        circuit.evaluate_aes(&KAT_KEY, &KAT_PLAINTEXT) == KAT_CIPHERTEXT,
+        "Embedded circuit fails the known-answer test"
+    );
+
+    let fhe_aes = BoolFheAes::new(Cleartext);
+    ensure!(
+        fhe_aes.circuit_fingerprint() == AES_128_CIRCUIT_FINGERPRINT,
+        "Circuit embedded in BoolFheAes has fingerprint {}, expected {}",
+        fhe_aes.circuit_fingerprint(),
+        AES_128_CIRCUIT_FINGERPRINT
+    );
+    fhe_aes.expand_key(bytes_to_wires(&KAT_KEY).try_into().unwrap());
+    let output = fhe_aes.execute(bytes_to_wires(&KAT_PLAINTEXT).try_into().unwrap());
+    ensure!(
+        wires_to_bytes(&output) == KAT_CIPHERTEXT,
+        "Circuit embedded in BoolFheAes fails the known-answer test"
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_self_test() {
+        self_test().unwrap();
+    }
+}
@@ -0,0 +1,74 @@
+//! Serialization of expanded keys and output blocks
+
+use super::*;
+use crate::params::ParameterSet;
+use crate::serialization::Artifact;
+use anyhow::{ensure, Result};
+use serde::{de::DeserializeOwned, Serialize};
+
+impl<B: BooleanBackend> BoolFheAes<B>
+where
+    B::Ciphertext: Serialize + DeserializeOwned,
+{
+    /// Serializes the expanded key wires, tagged with the parameter set and circuit
+    pub fn export_expanded_key(&self) -> Result<Vec<u8>> {
+        let mut wires: Vec<(u32, B::Ciphertext)> = self
+            .expanded_key_outputs
+            .iter()
+            .map(|entry| (*entry.key(), entry.value().clone()))
+            .collect();
+        wires.sort_by_key(|(wire, _)| *wire);
+
+        Artifact::new(&self.parameter_set, wires)
+            .with_circuit_fingerprint(&self.circuit_fingerprint)
+            .to_bytes()
+    }
+
+    /// Replaces the expanded key with one written by `export_expanded_key`. The key
+    /// wires are only meaningful for the circuit they were computed with.
+    pub fn import_expanded_key(&self, bytes: &[u8]) -> Result<()> {
+        let artifact = Artifact::from_bytes(bytes)?;
+        artifact.check_circuit_fingerprint(&self.circuit_fingerprint)?;
+        let wires: Vec<(u32, B::Ciphertext)> = artifact.into_payload(&self.parameter_set)?;
+
+        self.expanded_key_outputs.clear();
+        for (wire, ct) in wires {
+            self.expanded_key_outputs.insert(wire, ct);
+        }
+        Ok(())
+    }
+
+    /// Serializes output blocks, tagged with the parameter set and circuit
+    pub fn export_outputs(&self, outputs: &[[B::Ciphertext; AES_128_OUTPUT_BITSIZE]]) -> Result<Vec<u8>> {
+        let blocks: Vec<Vec<B::Ciphertext>> = outputs.iter().map(|block| block.to_vec()).collect();
+        Artifact::new(&self.parameter_set, blocks)
+            .with_circuit_fingerprint(&self.circuit_fingerprint)
+            .to_bytes()
+    }
+
+    /// Deserializes output blocks written by `export_outputs`
+    pub fn import_outputs(&self, bytes: &[u8]) -> Result<Vec<[B::Ciphertext; AES_128_OUTPUT_BITSIZE]>> {
+        let blocks: Vec<Vec<B::Ciphertext>> =
+            Artifact::from_bytes(bytes)?.into_payload(&self.parameter_set)?;
+
+        blocks
+            .into_iter()
+            .map(|block| {
+                ensure!(block.len() == AES_128_OUTPUT_BITSIZE, "Invalid output block length");
+                Ok(block.try_into().unwrap_or_else(|_| unreachable!()))
+            })
+            .collect()
+    }
+}
+
+impl<B: BooleanBackend> BoolFheAes<B> {
+    /// Returns the parameter set the backend keys were generated with
+    pub fn parameter_set(&self) -> &ParameterSet {
+        &self.parameter_set
+    }
+
+    /// Returns the fingerprint of the evaluated circuit, see `Circuit::fingerprint`
+    pub fn circuit_fingerprint(&self) -> &str {
+        &self.circuit_fingerprint
+    }
+}
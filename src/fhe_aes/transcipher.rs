@@ -0,0 +1,121 @@
+//! Transciphering of AES-CTR ciphertexts into FHE ciphertexts
+
+use super::*;
+use crate::circuit::bytes_to_wires;
+use crate::stream::{check_iv_size, Transcipher};
+use anyhow::ensure;
+
+impl<B: BooleanBackend> BoolFheAes<B> {
+    /// Homomorphically decrypts the AES-CTR `ciphertext`, encrypted under the expanded
+    /// key starting from the cleartext `counter`, into FHE-encrypted plaintext blocks.
+    /// Blocks use the `execute` output layout; the last one is zero-padded.
+    pub fn transcipher(
+        &self,
+        counter: &[u8; AES_BLOCK_SIZE],
+        ciphertext: &[u8],
+    ) -> Vec<[B::Ciphertext; AES_128_OUTPUT_BITSIZE]> {
+        let blocks = DashMap::new();
+        self.try_transcipher_for_each(counter, ciphertext, |i, block| {
+            blocks.insert(i, block);
+        })
+        .expect("Transciphering was cancelled");
+
+        (0..blocks.len()).map(|i| blocks.remove(&i).unwrap().1).collect()
+    }
+
+    /// Same as `transcipher`, but hands every block to `on_block` with its index as
+    /// soon as it is computed, and returns `Err(Cancelled)` if the token is set
+    pub fn try_transcipher_for_each(
+        &self,
+        counter: &[u8; AES_BLOCK_SIZE],
+        ciphertext: &[u8],
+        on_block: impl Fn(usize, [B::Ciphertext; AES_128_OUTPUT_BITSIZE]) + Send + Sync,
+    ) -> Result<(), Cancelled> {
+        let chunks: Vec<&[u8]> = ciphertext.chunks(AES_BLOCK_SIZE).collect();
+        let progress = self.start_progress(chunks.len());
+
+        chunks.into_par_iter().enumerate().try_for_each(|(i, chunk)| {
+            let block = self.trivial_counter(counter, i);
+            let keystream = self.execute_block(block, &progress)?;
+            on_block(i, self.xor_cleartext(&keystream, chunk));
+            Ok(())
+        })
+    }
+
+    /// The `i`-th counter block after `counter`. Counters are public, so they enter
+    /// the circuit as trivial ciphertexts.
+    pub fn trivial_counter(&self, counter: &[u8; AES_BLOCK_SIZE], i: usize) -> [B::Ciphertext; AES_BLOCK_SIZE_BITS] {
+        let counter = u128::from_be_bytes(*counter).wrapping_add(i as u128);
+        let bits = bytes_to_wires(&counter.to_be_bytes());
+        std::array::from_fn(|wire| self.backend.trivial(bits[wire]))
+    }
+
+    /// XORs the cleartext `chunk`, zero-padded to a block, into the encrypted `keystream`
+    pub fn xor_cleartext(
+        &self,
+        keystream: &[B::Ciphertext; AES_128_OUTPUT_BITSIZE],
+        chunk: &[u8],
+    ) -> [B::Ciphertext; AES_128_OUTPUT_BITSIZE] {
+        let mut padded = [0; AES_BLOCK_SIZE];
+        padded[..chunk.len()].copy_from_slice(chunk);
+        match self.backend.xor_public(keystream, &bytes_to_wires(&padded)).try_into() {
+            Ok(block) => block,
+            Err(_) => unreachable!("A block was XORed with a block of bits"),
+        }
+    }
+}
+
+/// AES-CTR with a public initial counter block as the IV
+impl<B: BooleanBackend> Transcipher<B> for BoolFheAes<B> {
+    fn iv_size(&self) -> usize {
+        AES_BLOCK_SIZE
+    }
+
+    fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    fn keystream_bytes(&self, iv: &[u8], len: usize) -> anyhow::Result<Vec<B::Ciphertext>> {
+        check_iv_size(self, iv)?;
+        ensure!(!self.expanded_key_outputs.is_empty(), "No AES key was expanded in this evaluator");
+        let counter: [u8; AES_BLOCK_SIZE] = iv.try_into()?;
+        let count = len.div_ceil(AES_BLOCK_SIZE);
+        let progress = self.start_progress(count);
+
+        let blocks = (0..count)
+            .into_par_iter()
+            .map(|i| self.execute_block(self.trivial_counter(&counter, i), &progress))
+            .collect::<Result<Vec<_>, Cancelled>>()?;
+        Ok(blocks_to_stream_bytes(&blocks, len))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::Cleartext;
+    use crate::circuit::wires_to_bytes;
+
+    #[test]
+    fn test_transcipher_recovers_plaintext() {
+        let key = [0x2b; AES_128_KEY_SIZE];
+        let counter = [0xf0; AES_BLOCK_SIZE];
+        let plaintext: Vec<u8> = (0..40).collect();
+
+        let counters = (0..3).map(|i| (u128::from_be_bytes(counter) + i).to_be_bytes()).collect();
+        let keystream = encrypt_reference_aes128(counters, key);
+        let ciphertext: Vec<u8> = keystream.iter().flatten().zip(&plaintext).map(|(k, p)| k ^ p).collect();
+
+        let fhe_aes = BoolFheAes::new(Cleartext);
+        fhe_aes.expand_key(bytes_to_wires(&key).try_into().unwrap());
+        let blocks = fhe_aes.transcipher(&counter, &ciphertext);
+
+        assert_eq!(blocks.len(), 3);
+        let decrypted: Vec<u8> = blocks.iter().flat_map(|block| wires_to_bytes(block)).collect();
+        assert_eq!(&decrypted[..plaintext.len()], plaintext);
+
+        let bits = fhe_aes.transcipher_bytes(&counter, &ciphertext).unwrap();
+        assert_eq!(crate::stream::bits_to_bytes(&bits), plaintext);
+        assert!(fhe_aes.keystream_bytes(&counter[1..], 1).is_err());
+    }
+}
@@ -3,7 +3,6 @@
 use super::*;
 use dashmap::DashMap;
 use rayon::prelude::*;
-use tfhe::boolean::prelude::*;
 use std::collections::HashSet;
 
 /// Parses MAND gate from circuit line parts
@@ -22,25 +21,25 @@ pub fn parse_mand(parts: &[&str]) -> Gate {
 }
 
 /// Executes a batch of AND operations in parallel
-pub fn execute_mand(
+pub fn execute_mand<B: BooleanBackend>(
     gates: &[(u32, u32, u32)],
-    server_key: &ServerKey,
-    values: &DashMap<u32, Ciphertext>,
+    backend: &B,
+    values: &DashMap<u32, B::Ciphertext>,
     expand_key: bool,
     key_reachable: &HashSet<u32>,
 ) {
     gates.par_iter().for_each(|(in1, in2, out)| {
-        execute_and(*in1, *in2, *out, server_key, values, expand_key, key_reachable);
+        execute_and(*in1, *in2, *out, backend, values, expand_key, key_reachable);
     });
 }
 
 /// Single AND gate execution logic
-pub fn execute_and(
+pub fn execute_and<B: BooleanBackend>(
     input1: u32,
     input2: u32,
     output: u32,
-    server_key: &ServerKey,
-    values: &DashMap<u32, Ciphertext>,
+    backend: &B,
+    values: &DashMap<u32, B::Ciphertext>,
     expand_key: bool,
     key_reachable: &HashSet<u32>,
 ) {
@@ -48,19 +47,19 @@ pub fn execute_and(
         return;
     }
 
-    let a = get_value(input1, values);
-    let b = get_value(input2, values);
+    let a = get_value(input1, backend, values);
+    let b = get_value(input2, backend, values);
     
-    let result = server_key.and(&a, &b);
+    let result = backend.and(&a, &b);
     values.insert(output, result);
 }
 
 /// Inverter execution logic
-pub fn execute_inv(
+pub fn execute_inv<B: BooleanBackend>(
     input: u32,
     output: u32,
-    server_key: &ServerKey,
-    values: &DashMap<u32, Ciphertext>,
+    backend: &B,
+    values: &DashMap<u32, B::Ciphertext>,
     expand_key: bool,
     key_reachable: &HashSet<u32>,
 ) {
@@ -68,18 +67,18 @@ pub fn execute_inv(
         return;
     }
 
-    let a = get_value(input, values);
-    let result = server_key.not(&a);
+    let a = get_value(input, backend, values);
+    let result = backend.not(&a);
     values.insert(output, result);
 }
 
 /// XOR gate execution logic
-pub fn execute_xor(
+pub fn execute_xor<B: BooleanBackend>(
     input1: u32,
     input2: u32,
     output: u32,
-    server_key: &ServerKey,
-    values: &DashMap<u32, Ciphertext>,
+    backend: &B,
+    values: &DashMap<u32, B::Ciphertext>,
     expand_key: bool,
     key_reachable: &HashSet<u32>,
 ) {
@@ -87,16 +86,16 @@ pub fn execute_xor(
         return;
     }
 
-    let a = get_value(input1, values);
-    let b = get_value(input2, values);
-    let result = server_key.xor(&a, &b);
+    let a = get_value(input1, backend, values);
+    let b = get_value(input2, backend, values);
+    let result = backend.xor(&a, &b);
     values.insert(output, result);
 }
 
 /// Helper to check if output should be computed
-fn should_compute(
+fn should_compute<T>(
     output: u32,
-    values: &DashMap<u32, Ciphertext>,
+    values: &DashMap<u32, T>,
     expand_key: bool,
     key_reachable: &HashSet<u32>,
 ) -> bool {
@@ -105,11 +104,12 @@ fn should_compute(
 }
 
 /// Helper to get value from storage with fallback
-fn get_value(
+fn get_value<B: BooleanBackend>(
     input: u32,
-    values: &DashMap<u32, Ciphertext>,
-) -> Ciphertext {
-    values.get(&input)
-        .expect("Missing input value")
-        .clone()
+    backend: &B,
+    values: &DashMap<u32, B::Ciphertext>,
+) -> B::Ciphertext {
+    let value = values.get(&input)
+        .expect("Missing input value");
+    backend.clone_ciphertext(&value)
 }
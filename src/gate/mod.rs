@@ -4,7 +4,7 @@ mod mand;
 
 use rayon::prelude::*;
 use dashmap::DashMap;
-use tfhe::boolean::prelude::*;
+use crate::backend::BooleanBackend;
 use std::collections::HashSet;
 
 /// Represents a single gate in the boolean circuit
@@ -35,32 +35,32 @@ pub enum Gate {
 
 /// Common interface for gate operations
 pub trait GateExecutor {
-    fn execute(
+    fn execute<B: BooleanBackend>(
         &self,
-        server_key: &ServerKey,
-        values: &DashMap<u32, Ciphertext>,
+        backend: &B,
+        values: &DashMap<u32, B::Ciphertext>,
         expand_key: bool,
         key_reachable: &HashSet<u32>,
     );
 }
 
 impl GateExecutor for Gate {
-    fn execute(
+    fn execute<B: BooleanBackend>(
         &self,
-        server_key: &ServerKey,
-        values: &DashMap<u32, Ciphertext>,
+        backend: &B,
+        values: &DashMap<u32, B::Ciphertext>,
         expand_key: bool,
         key_reachable: &HashSet<u32>,
     ) {
         match self {
             Gate::And { input1, input2, output } => 
-                mand::execute_and(*input1, *input2, *output, server_key, values, expand_key, key_reachable),
+                mand::execute_and(*input1, *input2, *output, backend, values, expand_key, key_reachable),
             Gate::Inv { input, output } => 
-                mand::execute_inv(*input, *output, server_key, values, expand_key, key_reachable),
+                mand::execute_inv(*input, *output, backend, values, expand_key, key_reachable),
             Gate::Xor { input1, input2, output } => 
-                mand::execute_xor(*input1, *input2, *output, server_key, values, expand_key, key_reachable),
+                mand::execute_xor(*input1, *input2, *output, backend, values, expand_key, key_reachable),
             Gate::Mand { gates } => 
-                mand::execute_mand(gates, server_key, values, expand_key, key_reachable),
+                mand::execute_mand(gates, backend, values, expand_key, key_reachable),
         }
     }
 }
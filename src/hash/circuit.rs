@@ -0,0 +1,88 @@
+//! SHA-256 compression function circuit, built with `CircuitBuilder`
+
+use crate::circuit::{Circuit, CircuitBuilder};
+
+/// SHA-256 round constants
+const K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+/// Builds the SHA-256 compression function. Inputs are the chaining value (8 words)
+/// and the message block (16 words), outputs the next chaining value; every word
+/// is 32 wires, least significant bit first.
+pub fn compression_circuit() -> Circuit {
+    let mut builder = CircuitBuilder::new();
+    let state: Vec<Vec<u32>> = (0..8).map(|_| builder.input(32)).collect();
+    let mut w: Vec<Vec<u32>> = (0..16).map(|_| builder.input(32)).collect();
+
+    for t in 16..64 {
+        let s0 = sigma(&mut builder, &w[t - 15], [7, 18], 3);
+        let s1 = sigma(&mut builder, &w[t - 2], [17, 19], 10);
+        let sum = builder.add(&w[t - 16], &s0);
+        let sum = builder.add(&sum, &w[t - 7]);
+        w.push(builder.add(&sum, &s1));
+    }
+
+    let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = state.clone().try_into().unwrap();
+    for t in 0..64 {
+        let s1 = big_sigma(&mut builder, &e, [6, 11, 25]);
+        let ch: Vec<u32> = (0..32).map(|i| builder.mux(e[i], f[i], g[i])).collect();
+        let k = builder.constant_word(K[t] as u128, 32);
+        let t1 = builder.add(&h, &s1);
+        let t1 = builder.add(&t1, &ch);
+        let t1 = builder.add(&t1, &k);
+        let t1 = builder.add(&t1, &w[t]);
+
+        let s0 = big_sigma(&mut builder, &a, [2, 13, 22]);
+        let maj = majority(&mut builder, &a, &b, &c);
+        let t2 = builder.add(&s0, &maj);
+
+        h = g;
+        g = f;
+        f = e;
+        e = builder.add(&d, &t1);
+        d = c;
+        c = b;
+        b = a;
+        a = builder.add(&t1, &t2);
+    }
+
+    for (word, update) in state.iter().zip([a, b, c, d, e, f, g, h]) {
+        let next = builder.add(word, &update);
+        builder.output(&next);
+    }
+    builder.build()
+}
+
+/// `ROTR^r0(x) ^ ROTR^r1(x) ^ SHR^s(x)`, the message schedule functions
+fn sigma(builder: &mut CircuitBuilder, x: &[u32], [r0, r1]: [usize; 2], s: usize) -> Vec<u32> {
+    let a = builder.rotate_right(x, r0);
+    let b = builder.rotate_right(x, r1);
+    let c = builder.shift_right(x, s);
+    let ab = builder.xor_word(&a, &b);
+    builder.xor_word(&ab, &c)
+}
+
+/// `ROTR^r0(x) ^ ROTR^r1(x) ^ ROTR^r2(x)`, the round functions
+fn big_sigma(builder: &mut CircuitBuilder, x: &[u32], [r0, r1, r2]: [usize; 3]) -> Vec<u32> {
+    let a = builder.rotate_right(x, r0);
+    let b = builder.rotate_right(x, r1);
+    let c = builder.rotate_right(x, r2);
+    let ab = builder.xor_word(&a, &b);
+    builder.xor_word(&ab, &c)
+}
+
+/// `Maj(a, b, c) = ((a ^ b) & (b ^ c)) ^ b`, one AND per bit
+fn majority(builder: &mut CircuitBuilder, a: &[u32], b: &[u32], c: &[u32]) -> Vec<u32> {
+    let a_b = builder.xor_word(a, b);
+    let b_c = builder.xor_word(b, c);
+    let and = builder.and_word(&a_b, &b_c);
+    builder.xor_word(&and, b)
+}
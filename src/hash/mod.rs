@@ -0,0 +1,181 @@
+//! Homomorphic SHA-256 and HMAC-SHA256
+//!
+//! The compression function is a boolean circuit run on the gate engine with
+//! `Circuit::evaluate_on`. Message lengths are public, so padding is added in the
+//! clear as trivial ciphertexts. Messages and digests are encrypted bytes, 8
+//! ciphertexts each with the least significant bit first, as in `stream`.
+
+mod circuit;
+
+pub use circuit::compression_circuit;
+
+use crate::backend::BooleanBackend;
+use crate::circuit::Circuit;
+use crate::stream::bytes_to_bits;
+use anyhow::{ensure, Result};
+use tfhe::boolean::prelude::*;
+
+/// SHA-256 block size in bytes
+pub const SHA256_BLOCK_SIZE: usize = 64;
+
+/// SHA-256 digest size in bytes
+pub const SHA256_DIGEST_SIZE: usize = 32;
+
+/// SHA-256 initial chaining value
+const H0: [u32; 8] = [
+    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+];
+
+/// Evaluates SHA-256 and HMAC-SHA256 on encrypted bytes through the
+/// [`BooleanBackend`] `B`
+pub struct FheSha256<B: BooleanBackend = ServerKey> {
+    backend: B,
+    compression: Circuit,
+}
+
+impl<B: BooleanBackend> FheSha256<B> {
+    pub fn new(backend: B) -> Self {
+        Self { backend, compression: compression_circuit() }
+    }
+
+    /// Uses a loaded compression function instead of `compression_circuit`, for
+    /// example one optimized and saved in extended Bristol format. It must have the
+    /// same 256 + 512 input and 256 output wires.
+    pub fn with_compression(backend: B, compression: Circuit) -> Result<Self> {
+        ensure!(
+            compression.input_count() == 768 && compression.output_wires().len() == 256,
+            "A SHA-256 compression circuit has 768 inputs and 256 outputs, got {} and {}",
+            compression.input_count(),
+            compression.output_wires().len()
+        );
+        Ok(Self { backend, compression })
+    }
+
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    /// Hashes the encrypted `message`, returning the encrypted 32-byte digest
+    pub fn sha256(&self, message: &[B::Ciphertext]) -> Result<Vec<B::Ciphertext>> {
+        self.sha256_chunks(&[message])
+    }
+
+    /// HMAC-SHA256 of the encrypted `message` under the encrypted `key`, which may
+    /// have any length; keys longer than a block are hashed first
+    pub fn hmac_sha256(&self, key: &[B::Ciphertext], message: &[B::Ciphertext]) -> Result<Vec<B::Ciphertext>> {
+        ensure!(key.len() % 8 == 0, "Key of {} bits is not a whole number of bytes", key.len());
+        let mut key = match key.len() > SHA256_BLOCK_SIZE * 8 {
+            true => self.sha256(key)?,
+            false => key.to_vec(),
+        };
+        key.resize_with(SHA256_BLOCK_SIZE * 8, || self.backend.trivial(false));
+
+        let inner_key = self.xor_constant(&key, 0x36);
+        let inner = self.sha256_chunks(&[&inner_key, message])?;
+        let outer_key = self.xor_constant(&key, 0x5c);
+        self.sha256_chunks(&[&outer_key, &inner])
+    }
+
+    /// Hashes the concatenation of `chunks`
+    fn sha256_chunks(&self, chunks: &[&[B::Ciphertext]]) -> Result<Vec<B::Ciphertext>> {
+        let len: usize = chunks.iter().map(|chunk| chunk.len()).sum();
+        ensure!(len % 8 == 0, "Message of {} bits is not a whole number of bytes", len);
+
+        let mut message: Vec<B::Ciphertext> = chunks.concat();
+        for bit in bytes_to_bits(&padding(len / 8)) {
+            message.push(self.backend.trivial(bit));
+        }
+
+        let mut state: Vec<B::Ciphertext> = H0
+            .iter()
+            .flat_map(|word| (0..32).map(move |bit| word >> bit & 1 == 1))
+            .map(|bit| self.backend.trivial(bit))
+            .collect();
+        for block in message.chunks(SHA256_BLOCK_SIZE * 8) {
+            let mut inputs = state;
+            inputs.extend(words(block));
+            state = self.compression.evaluate_on(&self.backend, &inputs);
+        }
+        Ok(words(&state).collect())
+    }
+
+    /// XORs every byte of `bits` with the constant `byte`
+    fn xor_constant(&self, bits: &[B::Ciphertext], byte: u8) -> Vec<B::Ciphertext> {
+        let constant: Vec<bool> = (0..bits.len()).map(|i| byte >> (i % 8) & 1 == 1).collect();
+        self.backend.xor_public(bits, &constant)
+    }
+}
+
+/// SHA-256 padding of a `len`-byte message: `0x80`, zeros up to 56 bytes modulo
+/// 64, then the big-endian bit length
+fn padding(len: usize) -> Vec<u8> {
+    let mut padding = vec![0x80];
+    padding.resize((SHA256_BLOCK_SIZE + 55 - len % SHA256_BLOCK_SIZE) % SHA256_BLOCK_SIZE + 1, 0);
+    padding.extend((len as u64 * 8).to_be_bytes());
+    padding
+}
+
+/// Reorders bytes, least significant bit first, into big-endian 32-bit words,
+/// least significant bit first, and back: byte `4i + j` holds bits
+/// `8(3 - j)..8(4 - j)` of word `i`
+fn words<T: Clone>(bits: &[T]) -> impl Iterator<Item = T> + '_ {
+    bits.chunks(32).flat_map(|word| word.chunks(8).rev().flatten().cloned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::Cleartext;
+    use crate::circuit::CircuitParser;
+    use crate::stream::bits_to_bytes;
+    use hmac::{Hmac, Mac};
+    use sha2::{Digest, Sha256};
+
+    fn message(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 31 + 7) as u8).collect()
+    }
+
+    #[test]
+    fn test_padding_length() {
+        for len in [0, 1, 55, 56, 63, 64, 119, 120] {
+            assert_eq!((len + padding(len).len()) % SHA256_BLOCK_SIZE, 0, "{}", len);
+            assert!(padding(len).len() > 8);
+        }
+    }
+
+    #[test]
+    fn test_sha256_matches_sha2() {
+        let sha = FheSha256::new(Cleartext);
+        // Empty, one block, padding spilling into a second block and three blocks
+        for len in [0, 3, 55, 56, 64, 150] {
+            let message = message(len);
+            let digest = sha.sha256(&bytes_to_bits(&message)).unwrap();
+            assert_eq!(bits_to_bytes(&digest), Sha256::digest(&message).as_slice(), "{} bytes", len);
+        }
+        assert!(sha.sha256(&[true; 7]).is_err());
+    }
+
+    #[test]
+    fn test_loaded_compression_circuit() {
+        let circuit = CircuitParser::parse(&compression_circuit().to_bristol());
+        let sha = FheSha256::with_compression(Cleartext, circuit).unwrap();
+        let digest = sha.sha256(&bytes_to_bits(b"abc")).unwrap();
+        assert_eq!(bits_to_bytes(&digest), Sha256::digest(b"abc").as_slice());
+
+        assert!(FheSha256::with_compression(Cleartext, Circuit::aes_128()).is_err());
+    }
+
+    #[test]
+    fn test_hmac_sha256_matches_hmac() {
+        let sha = FheSha256::new(Cleartext);
+        // Short, block-sized and hashed keys
+        for (key_len, message_len) in [(0, 10), (20, 50), (64, 64), (100, 3)] {
+            let (key, message) = (message(key_len), message(message_len));
+            let mac = sha.hmac_sha256(&bytes_to_bits(&key), &bytes_to_bits(&message)).unwrap();
+
+            let mut expected = Hmac::<Sha256>::new_from_slice(&key).unwrap();
+            expected.update(&message);
+            assert_eq!(bits_to_bytes(&mac), expected.finalize().into_bytes().as_slice(), "{} byte key", key_len);
+        }
+    }
+}
@@ -3,9 +3,20 @@
 //! This crate provides fully homomorphic AES-128 encryption using boolean circuits and TFHE.
 
 pub mod utils;
+pub mod backend;
 pub mod circuit;
 pub mod gate;
 pub mod fhe_aes;
+pub mod hash;
+pub mod params;
+pub mod serialization;
+pub mod stream;
+pub mod client;
+pub mod server;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "python")]
+pub mod python;
 pub mod cli;  // Add this line to expose the CLI module
-pub use fhe_aes::{BoolFheAes, encrypt_reference_aes128};
+pub use fhe_aes::{BoolFheAes, encrypt_reference_aes128, self_test};
 
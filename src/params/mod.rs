@@ -0,0 +1,148 @@
+//! Selectable tfhe boolean parameter sets
+
+use anyhow::{bail, Context, Result};
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use tfhe::boolean::prelude::*;
+
+/// A named tfhe boolean parameter set, or custom parameters loaded from a file
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum ParameterSet {
+    /// `DEFAULT_PARAMETERS`, used by `tfhe::boolean::gen_keys`
+    #[default]
+    Default,
+    /// `DEFAULT_PARAMETERS_KS_PBS`
+    DefaultKsPbs,
+    /// `PARAMETERS_ERROR_PROB_2_POW_MINUS_165`
+    ErrorProb2PowMinus165,
+    /// `PARAMETERS_ERROR_PROB_2_POW_MINUS_165_KS_PBS`
+    ErrorProb2PowMinus165KsPbs,
+    /// `TFHE_LIB_PARAMETERS`
+    TfheLib,
+    /// User-provided parameters
+    Custom(BooleanParameters),
+}
+
+impl ParameterSet {
+    /// Names accepted by [`ParameterSet::from_str`] for the built-in sets
+    pub const NAMES: [&'static str; 5] = [
+        "default",
+        "default-ks-pbs",
+        "error-prob-2-pow-minus-165",
+        "error-prob-2-pow-minus-165-ks-pbs",
+        "tfhe-lib",
+    ];
+
+    /// Returns the tfhe parameters of this set
+    pub fn parameters(&self) -> BooleanParameters {
+        match self {
+            ParameterSet::Default => DEFAULT_PARAMETERS,
+            ParameterSet::DefaultKsPbs => DEFAULT_PARAMETERS_KS_PBS,
+            ParameterSet::ErrorProb2PowMinus165 => PARAMETERS_ERROR_PROB_2_POW_MINUS_165,
+            ParameterSet::ErrorProb2PowMinus165KsPbs => PARAMETERS_ERROR_PROB_2_POW_MINUS_165_KS_PBS,
+            ParameterSet::TfheLib => TFHE_LIB_PARAMETERS,
+            ParameterSet::Custom(parameters) => *parameters,
+        }
+    }
+
+    /// Returns the stable identifier recorded in serialized artifacts.
+    ///
+    /// Custom sets are identified by a hash of their JSON encoding, so two files
+    /// with the same parameters share an id.
+    pub fn id(&self) -> String {
+        match self {
+            ParameterSet::Default => Self::NAMES[0].to_string(),
+            ParameterSet::DefaultKsPbs => Self::NAMES[1].to_string(),
+            ParameterSet::ErrorProb2PowMinus165 => Self::NAMES[2].to_string(),
+            ParameterSet::ErrorProb2PowMinus165KsPbs => Self::NAMES[3].to_string(),
+            ParameterSet::TfheLib => Self::NAMES[4].to_string(),
+            ParameterSet::Custom(parameters) => {
+                let json = serde_json::to_vec(parameters).expect("Parameters are serializable");
+                format!("custom-{:016x}", fnv1a(&json))
+            }
+        }
+    }
+
+    /// Loads custom parameters from a `.toml` or `.json` file
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read parameter file {}", path.display()))?;
+
+        let parameters = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => toml::from_str(&text).context("Failed to parse TOML parameters")?,
+            Some("json") => serde_json::from_str(&text).context("Failed to parse JSON parameters")?,
+            _ => bail!("Parameter file must have a .toml or .json extension"),
+        };
+        Ok(ParameterSet::Custom(parameters))
+    }
+
+    /// Generates a client/server key pair for this parameter set
+    pub fn gen_keys(&self) -> (ClientKey, ServerKey) {
+        let client_key = ClientKey::new(&self.parameters());
+        let server_key = ServerKey::new(&client_key);
+        (client_key, server_key)
+    }
+}
+
+impl fmt::Display for ParameterSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.id())
+    }
+}
+
+impl FromStr for ParameterSet {
+    type Err = anyhow::Error;
+
+    /// Parses a built-in set name, or loads custom parameters if `s` is a file path
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "default" => Ok(ParameterSet::Default),
+            "default-ks-pbs" => Ok(ParameterSet::DefaultKsPbs),
+            "error-prob-2-pow-minus-165" => Ok(ParameterSet::ErrorProb2PowMinus165),
+            "error-prob-2-pow-minus-165-ks-pbs" => Ok(ParameterSet::ErrorProb2PowMinus165KsPbs),
+            "tfhe-lib" => Ok(ParameterSet::TfheLib),
+            path if Path::new(path).is_file() => ParameterSet::load(path),
+            _ => bail!(
+                "Unknown parameter set '{}', expected one of {} or a .toml/.json file",
+                s,
+                Self::NAMES.join(", ")
+            ),
+        }
+    }
+}
+
+/// 64-bit FNV-1a hash, stable across platforms and Rust versions
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_named_sets_round_trip() {
+        for name in ParameterSet::NAMES {
+            let set: ParameterSet = name.parse().unwrap();
+            assert_eq!(set.id(), name);
+        }
+        assert!("unknown".parse::<ParameterSet>().is_err());
+    }
+
+    #[test]
+    fn test_load_custom_json() {
+        let path = std::env::temp_dir().join(format!("fhe_aes_test_params-{}.json", std::process::id()));
+        std::fs::write(&path, serde_json::to_string(&TFHE_LIB_PARAMETERS).unwrap()).unwrap();
+
+        let set = ParameterSet::load(&path).unwrap();
+        assert_eq!(set.parameters(), TFHE_LIB_PARAMETERS);
+        assert!(set.id().starts_with("custom-"));
+        assert_eq!(set.id(), ParameterSet::Custom(TFHE_LIB_PARAMETERS).id());
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,268 @@
+//! Python module built with the `python` feature, see `pyproject.toml`
+//!
+//! Wraps key generation, encryption of keys and counters, key expansion, CTR
+//! evaluation and decryption. Keys and ciphertexts serialize to bytes as the same
+//! parameter-set-tagged `Artifact`s the CLI writes, and are read back for a
+//! parameter set named by the caller. Evaluation releases the GIL.
+
+use crate::circuit::AesCircuit;
+use crate::params::ParameterSet;
+use crate::serialization::Artifact;
+use crate::utils::constants::{AES_128_KEY_SIZE, AES_128_OUTPUT_BITSIZE, AES_BLOCK_SIZE};
+use crate::BoolFheAes;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tfhe::boolean::prelude::{Ciphertext, ClientKey, ServerKey};
+
+fn value_error(error: impl std::fmt::Display) -> PyErr {
+    PyValueError::new_err(error.to_string())
+}
+
+fn parameter_set(name: Option<&str>) -> PyResult<ParameterSet> {
+    name.map_or(Ok(ParameterSet::Default), |name| name.parse().map_err(value_error))
+}
+
+fn array<const N: usize>(bytes: &[u8], name: &str) -> PyResult<[u8; N]> {
+    bytes
+        .try_into()
+        .map_err(|_| value_error(format!("`{}` must be {} bytes, got {}", name, N, bytes.len())))
+}
+
+fn to_bytes<'py, T: serde::Serialize>(py: Python<'py>, parameter_set: &ParameterSet, payload: &T) -> PyResult<Bound<'py, PyBytes>> {
+    let bytes = Artifact::new(parameter_set, payload).to_bytes().map_err(value_error)?;
+    Ok(PyBytes::new(py, &bytes))
+}
+
+/// Reads an artifact produced under `parameter_set`, a name or file accepted by
+/// `gen_keys`; the artifact only carries a hash of custom parameters
+fn from_bytes<T: serde::de::DeserializeOwned>(bytes: &[u8], parameter_set: Option<&str>) -> PyResult<(ParameterSet, T)> {
+    let parameter_set = self::parameter_set(parameter_set)?;
+    let artifact = Artifact::<T>::from_bytes(bytes).map_err(value_error)?;
+    let payload = artifact.into_payload(&parameter_set).map_err(value_error)?;
+    Ok((parameter_set, payload))
+}
+
+/// Client key, used to encrypt inputs and decrypt outputs
+#[pyclass(name = "ClientKey", module = "fhe_aes", frozen)]
+pub struct PyClientKey {
+    parameter_set: ParameterSet,
+    client_key: ClientKey,
+}
+
+#[pymethods]
+impl PyClientKey {
+    #[getter]
+    fn parameter_set(&self) -> String {
+        self.parameter_set.id()
+    }
+
+    fn to_bytes<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyBytes>> {
+        to_bytes(py, &self.parameter_set, &self.client_key)
+    }
+
+    #[staticmethod]
+    #[pyo3(signature = (bytes, parameter_set=None))]
+    fn from_bytes(bytes: &[u8], parameter_set: Option<&str>) -> PyResult<Self> {
+        let (parameter_set, client_key) = from_bytes(bytes, parameter_set)?;
+        Ok(Self { parameter_set, client_key })
+    }
+}
+
+/// Server key, the evaluation key sent to whoever runs `FheAes`
+#[pyclass(name = "ServerKey", module = "fhe_aes", frozen)]
+pub struct PyServerKey {
+    parameter_set: ParameterSet,
+    server_key: ServerKey,
+}
+
+#[pymethods]
+impl PyServerKey {
+    #[getter]
+    fn parameter_set(&self) -> String {
+        self.parameter_set.id()
+    }
+
+    fn to_bytes<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyBytes>> {
+        to_bytes(py, &self.parameter_set, &self.server_key)
+    }
+
+    #[staticmethod]
+    #[pyo3(signature = (bytes, parameter_set=None))]
+    fn from_bytes(bytes: &[u8], parameter_set: Option<&str>) -> PyResult<Self> {
+        let (parameter_set, server_key) = from_bytes(bytes, parameter_set)?;
+        Ok(Self { parameter_set, server_key })
+    }
+}
+
+/// Encrypted bits of a key, counter block or output block, least significant first
+#[pyclass(name = "Ciphertexts", module = "fhe_aes", frozen)]
+pub struct PyCiphertexts {
+    parameter_set: ParameterSet,
+    bits: Vec<Ciphertext>,
+}
+
+impl PyCiphertexts {
+    fn array<const N: usize>(&self, expected: &ParameterSet) -> PyResult<[Ciphertext; N]> {
+        if self.parameter_set != *expected {
+            return Err(value_error(format!(
+                "Ciphertexts use parameter set '{}', expected '{}'",
+                self.parameter_set,
+                expected
+            )));
+        }
+        self.bits
+            .clone()
+            .try_into()
+            .map_err(|_| value_error(format!("Expected {} ciphertexts, got {}", N, self.bits.len())))
+    }
+}
+
+#[pymethods]
+impl PyCiphertexts {
+    #[getter]
+    fn parameter_set(&self) -> String {
+        self.parameter_set.id()
+    }
+
+    fn __len__(&self) -> usize {
+        self.bits.len()
+    }
+
+    fn to_bytes<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyBytes>> {
+        to_bytes(py, &self.parameter_set, &self.bits)
+    }
+
+    #[staticmethod]
+    #[pyo3(signature = (bytes, parameter_set=None))]
+    fn from_bytes(bytes: &[u8], parameter_set: Option<&str>) -> PyResult<Self> {
+        let (parameter_set, bits) = from_bytes(bytes, parameter_set)?;
+        Ok(Self { parameter_set, bits })
+    }
+}
+
+/// AES-128 evaluator over a server key, see `BoolFheAes`
+#[pyclass(name = "FheAes", module = "fhe_aes", frozen)]
+pub struct PyFheAes {
+    fhe_aes: BoolFheAes,
+    key_expanded: AtomicBool,
+}
+
+#[pymethods]
+impl PyFheAes {
+    /// `circuit` is a name accepted by the CLI, `scale-mamba` by default
+    #[new]
+    #[pyo3(signature = (server_key, circuit=None))]
+    fn new(server_key: &PyServerKey, circuit: Option<&str>) -> PyResult<Self> {
+        let circuit = circuit.map_or(Ok(AesCircuit::default()), str::parse).map_err(value_error)?;
+        let fhe_aes = BoolFheAes::with_circuit(server_key.server_key.clone(), server_key.parameter_set, circuit);
+        Ok(Self { fhe_aes, key_expanded: AtomicBool::new(false) })
+    }
+
+    #[getter]
+    fn circuit_fingerprint(&self) -> &str {
+        self.fhe_aes.circuit_fingerprint()
+    }
+
+    /// Expands the key returned by `encrypt_key`
+    fn expand_key(&self, py: Python<'_>, key: &PyCiphertexts) -> PyResult<()> {
+        let key = key.array(self.fhe_aes.parameter_set())?;
+        self.key_expanded.store(false, Ordering::SeqCst);
+        py.allow_threads(|| self.fhe_aes.expand_key(key));
+        self.key_expanded.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// Computes `count` CTR blocks from the counter returned by `encrypt_iv`
+    fn aes_ctr_blocks(&self, py: Python<'_>, iv: &PyCiphertexts, count: usize) -> PyResult<Vec<PyCiphertexts>> {
+        if !self.key_expanded.load(Ordering::SeqCst) {
+            return Err(value_error("No AES key was expanded"));
+        }
+        let iv = iv.array(self.fhe_aes.parameter_set())?;
+        let blocks = py.allow_threads(|| self.fhe_aes.aes_ctr_blocks(iv, count));
+
+        let parameter_set = *self.fhe_aes.parameter_set();
+        Ok(blocks.into_iter().map(|block| PyCiphertexts { parameter_set, bits: block.to_vec() }).collect())
+    }
+
+    /// Serializes the expanded key, see `export_expanded_key`
+    fn export_expanded_key<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyBytes>> {
+        let bytes = self.fhe_aes.export_expanded_key().map_err(value_error)?;
+        Ok(PyBytes::new(py, &bytes))
+    }
+
+    /// Replaces the expanded key with one from `export_expanded_key`
+    fn import_expanded_key(&self, bytes: &[u8]) -> PyResult<()> {
+        self.fhe_aes.import_expanded_key(bytes).map_err(value_error)?;
+        self.key_expanded.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+/// Generates a client and server key for `parameter_set`, `default` if omitted
+#[pyfunction]
+#[pyo3(signature = (parameter_set=None))]
+fn gen_keys(py: Python<'_>, parameter_set: Option<&str>) -> PyResult<(PyClientKey, PyServerKey)> {
+    let parameter_set = self::parameter_set(parameter_set)?;
+    let (client_key, server_key) = py.allow_threads(|| parameter_set.gen_keys());
+    Ok((PyClientKey { parameter_set, client_key }, PyServerKey { parameter_set, server_key }))
+}
+
+/// JSON encoding of `parameter_set`, `default` if omitted, as read from a custom
+/// parameter file
+#[pyfunction]
+#[pyo3(signature = (parameter_set=None))]
+fn parameters_json(parameter_set: Option<&str>) -> PyResult<String> {
+    serde_json::to_string_pretty(&self::parameter_set(parameter_set)?.parameters()).map_err(value_error)
+}
+
+/// Encrypts a 16-byte AES key
+#[pyfunction]
+fn encrypt_key(client_key: &PyClientKey, key: &[u8]) -> PyResult<PyCiphertexts> {
+    let key = array::<AES_128_KEY_SIZE>(key, "key")?;
+    let bits = BoolFheAes::encrypt_key(&client_key.client_key, &key).to_vec();
+    Ok(PyCiphertexts { parameter_set: client_key.parameter_set, bits })
+}
+
+/// Encrypts a 16-byte initial counter block
+#[pyfunction]
+fn encrypt_iv(client_key: &PyClientKey, iv: &[u8]) -> PyResult<PyCiphertexts> {
+    let iv = array::<AES_BLOCK_SIZE>(iv, "iv")?;
+    let bits = BoolFheAes::encrypt_iv(&client_key.client_key, &iv).to_vec();
+    Ok(PyCiphertexts { parameter_set: client_key.parameter_set, bits })
+}
+
+/// Decrypts an output block of `aes_ctr_blocks` into 16 bytes
+#[pyfunction]
+fn decrypt_output<'py>(py: Python<'py>, client_key: &PyClientKey, block: &PyCiphertexts) -> PyResult<Bound<'py, PyBytes>> {
+    let block: [Ciphertext; AES_128_OUTPUT_BITSIZE] = block.array(&client_key.parameter_set)?;
+    Ok(PyBytes::new(py, &BoolFheAes::decrypt_output(&client_key.client_key, &block)))
+}
+
+/// Encrypts 16-byte `blocks` under `key` with the `aes` crate
+#[pyfunction]
+fn encrypt_reference_aes128<'py>(py: Python<'py>, blocks: Vec<Vec<u8>>, key: &[u8]) -> PyResult<Vec<Bound<'py, PyBytes>>> {
+    let key = array::<AES_128_KEY_SIZE>(key, "key")?;
+    let blocks = blocks
+        .iter()
+        .map(|block| array::<AES_BLOCK_SIZE>(block, "block"))
+        .collect::<PyResult<Vec<_>>>()?;
+    let encrypted = crate::encrypt_reference_aes128(blocks, key);
+    Ok(encrypted.iter().map(|block| PyBytes::new(py, block)).collect())
+}
+
+#[pymodule]
+fn fhe_aes(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyClientKey>()?;
+    m.add_class::<PyServerKey>()?;
+    m.add_class::<PyCiphertexts>()?;
+    m.add_class::<PyFheAes>()?;
+    m.add_function(wrap_pyfunction!(gen_keys, m)?)?;
+    m.add_function(wrap_pyfunction!(parameters_json, m)?)?;
+    m.add_function(wrap_pyfunction!(encrypt_key, m)?)?;
+    m.add_function(wrap_pyfunction!(encrypt_iv, m)?)?;
+    m.add_function(wrap_pyfunction!(decrypt_output, m)?)?;
+    m.add_function(wrap_pyfunction!(encrypt_reference_aes128, m)?)?;
+    Ok(())
+}
@@ -0,0 +1,99 @@
+//! Serialized artifacts tagged with the parameter set they were produced under
+
+use crate::params::ParameterSet;
+use anyhow::{ensure, Context, Result};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+/// Envelope for every serialized key, ciphertext or result.
+///
+/// Ciphertexts are only meaningful under the parameters they were encrypted with,
+/// so the parameter set id travels with the payload and is checked on load.
+/// Artifacts holding circuit wires also record the fingerprint of the circuit.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Artifact<T> {
+    /// Id of the parameter set, see [`ParameterSet::id`]
+    pub parameter_set: String,
+    /// Fingerprint of the circuit the payload belongs to, see [`Circuit::fingerprint`]
+    ///
+    /// [`Circuit::fingerprint`]: crate::circuit::Circuit::fingerprint
+    pub circuit_fingerprint: Option<String>,
+    pub payload: T,
+}
+
+impl<T> Artifact<T> {
+    pub fn new(parameter_set: &ParameterSet, payload: T) -> Self {
+        Self {
+            parameter_set: parameter_set.id(),
+            circuit_fingerprint: None,
+            payload,
+        }
+    }
+
+    /// Records the fingerprint of the circuit the payload was produced with
+    pub fn with_circuit_fingerprint(mut self, fingerprint: &str) -> Self {
+        self.circuit_fingerprint = Some(fingerprint.to_string());
+        self
+    }
+
+    /// Fails unless the artifact records the circuit fingerprint `expected`
+    pub fn check_circuit_fingerprint(&self, expected: &str) -> Result<()> {
+        ensure!(
+            self.circuit_fingerprint.as_deref() == Some(expected),
+            "Artifact was produced with circuit {}, expected {}",
+            self.circuit_fingerprint.as_deref().unwrap_or("<unknown>"),
+            expected
+        );
+        Ok(())
+    }
+
+    /// Returns the payload, failing if it was produced under another parameter set
+    pub fn into_payload(self, expected: &ParameterSet) -> Result<T> {
+        ensure!(
+            self.parameter_set == expected.id(),
+            "Artifact was produced with parameter set '{}', expected '{}'",
+            self.parameter_set,
+            expected.id()
+        );
+        Ok(self.payload)
+    }
+}
+
+impl<T: Serialize> Artifact<T> {
+    /// Serializes the artifact with bincode
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        bincode::serialize(self).context("Failed to serialize artifact")
+    }
+}
+
+impl<T: DeserializeOwned> Artifact<T> {
+    /// Deserializes an artifact written by [`Artifact::to_bytes`]
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        bincode::deserialize(bytes).context("Failed to deserialize artifact")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parameter_set_is_checked() {
+        let bytes = Artifact::new(&ParameterSet::TfheLib, vec![1u8, 2, 3]).to_bytes().unwrap();
+
+        let artifact = Artifact::<Vec<u8>>::from_bytes(&bytes).unwrap();
+        assert_eq!(artifact.parameter_set, "tfhe-lib");
+        assert!(artifact.clone().into_payload(&ParameterSet::Default).is_err());
+        assert_eq!(artifact.into_payload(&ParameterSet::TfheLib).unwrap(), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_circuit_fingerprint_is_checked() {
+        let artifact = Artifact::new(&ParameterSet::Default, ());
+        assert!(artifact.check_circuit_fingerprint("abc").is_err());
+
+        let bytes = artifact.with_circuit_fingerprint("abc").to_bytes().unwrap();
+        let artifact = Artifact::<()>::from_bytes(&bytes).unwrap();
+        assert!(artifact.check_circuit_fingerprint("abc").is_ok());
+        assert!(artifact.check_circuit_fingerprint("abd").is_err());
+    }
+}
@@ -0,0 +1,375 @@
+//! Long-lived evaluation server behind `fhe-aes serve`
+//!
+//! The server parses the AES-128 circuit once and keeps one `BoolFheAes` per
+//! registered server key, so clients upload their keys once and then request
+//! keystream blocks or transciphering without paying for setup on every run.
+//! Sessions belong to the connection that registered them and are dropped when
+//! it closes. Blocks of all sessions go through one `Scheduler`, which batches them.
+
+mod protocol;
+
+pub use protocol::{read_frame, write_frame, Endpoint, Request, Response, MAX_FRAME_LEN};
+
+use crate::backend::BooleanBackend;
+use crate::circuit::{AesCircuit, Circuit};
+use crate::params::ParameterSet;
+use crate::utils::constants::{AES_128_OUTPUT_BITSIZE, AES_BLOCK_SIZE, AES_BLOCK_SIZE_BITS};
+use crate::fhe_aes::{JobHandle, Scheduler, SchedulerConfig};
+use crate::BoolFheAes;
+use anyhow::{anyhow, ensure, Context, Result};
+use log::{info, warn};
+use serde::{de::DeserializeOwned, Serialize};
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use tfhe::boolean::prelude::*;
+
+/// Default for the most sessions open at once across all connections
+pub const DEFAULT_MAX_SESSIONS: usize = 64;
+
+/// One registered server key and the AES key expanded under it. A connection
+/// handles its requests in order, so a job completes before the key can be replaced.
+struct Session<B: BooleanBackend> {
+    fhe_aes: Arc<BoolFheAes<B>>,
+    key_expanded: bool,
+}
+
+/// Sessions registered on one connection, released from the server-wide count
+/// when the connection closes
+struct Connection<'a, B: BooleanBackend> {
+    /// Scheduler tenant of all the connection's jobs, so opening more sessions
+    /// does not raise the connection's queue limit
+    tenant: String,
+    sessions: HashMap<u64, Session<B>>,
+    next_session: u64,
+    open_sessions: &'a AtomicUsize,
+}
+
+impl<B: BooleanBackend> Connection<'_, B> {
+    fn session(&mut self, session: u64) -> Result<&mut Session<B>> {
+        self.sessions.get_mut(&session).ok_or_else(|| anyhow!("Unknown session {}", session))
+    }
+}
+
+impl<B: BooleanBackend> Drop for Connection<'_, B> {
+    fn drop(&mut self) {
+        self.open_sessions.fetch_sub(self.sessions.len(), Ordering::Relaxed);
+    }
+}
+
+/// Evaluation server; each connection only sees the sessions it registered
+pub struct Server<B: BooleanBackend = ServerKey> {
+    parameter_set: ParameterSet,
+    circuit: Circuit,
+    max_sessions: usize,
+    open_sessions: AtomicUsize,
+    next_connection: AtomicU64,
+    scheduler: Arc<Scheduler<B>>,
+}
+
+impl<B> Server<B>
+where
+    B: BooleanBackend + DeserializeOwned + 'static,
+    B::Ciphertext: Serialize + DeserializeOwned,
+{
+    /// Creates a server evaluating `circuit` for keys generated with `parameter_set`
+    pub fn new(parameter_set: ParameterSet, circuit: AesCircuit) -> Self {
+        Self::with_scheduler_config(parameter_set, circuit, SchedulerConfig::default())
+    }
+
+    /// Same as `new`, with custom batch size and per-connection queue limits
+    pub fn with_scheduler_config(parameter_set: ParameterSet, circuit: AesCircuit, config: SchedulerConfig) -> Self {
+        let circuit = circuit.circuit();
+        let scheduler = Arc::new(Scheduler::new(&circuit, config));
+        scheduler.start();
+        Self {
+            parameter_set,
+            circuit,
+            max_sessions: DEFAULT_MAX_SESSIONS,
+            open_sessions: AtomicUsize::new(0),
+            next_connection: AtomicU64::new(0),
+            scheduler,
+        }
+    }
+
+    /// Limits the sessions open at once across all connections; further
+    /// registrations are answered with `Response::Error`
+    pub fn with_max_sessions(mut self, max_sessions: usize) -> Self {
+        self.max_sessions = max_sessions;
+        self
+    }
+
+    /// Accepts connections on `endpoint` forever, serving each on its own thread
+    pub fn serve(self: &Arc<Self>, endpoint: &Endpoint) -> Result<()> {
+        match endpoint {
+            Endpoint::Tcp(address) => {
+                let listener = TcpListener::bind(address)
+                    .with_context(|| format!("Failed to listen on {}", address))?;
+                info!("Listening on tcp:{}", listener.local_addr()?);
+                for stream in listener.incoming() {
+                    let stream = stream?;
+                    info!("Accepted connection from {}", stream.peer_addr()?);
+                    self.spawn_connection(stream);
+                }
+            }
+            #[cfg(unix)]
+            Endpoint::Unix(path) => {
+                remove_stale_socket(path)?;
+                let listener = std::os::unix::net::UnixListener::bind(path)
+                    .with_context(|| format!("Failed to listen on {}", path.display()))?;
+                info!("Listening on {}", endpoint);
+                for stream in listener.incoming() {
+                    info!("Accepted connection on {}", endpoint);
+                    self.spawn_connection(stream?);
+                }
+            }
+            #[cfg(not(unix))]
+            Endpoint::Unix(_) => anyhow::bail!("Unix sockets are not supported on this platform"),
+        }
+        Ok(())
+    }
+
+    fn spawn_connection(self: &Arc<Self>, stream: impl Read + Write + Send + 'static) {
+        let server = Arc::clone(self);
+        std::thread::spawn(move || {
+            if let Err(error) = server.handle_connection(stream) {
+                warn!("Connection closed: {:#}", error);
+            }
+        });
+    }
+
+    /// Answers requests on `stream` until the peer disconnects, then drops the
+    /// sessions it registered. A failed request is answered with `Response::Error`
+    /// and the connection stays open.
+    pub fn handle_connection(&self, mut stream: impl Read + Write) -> Result<()> {
+        let id = self.next_connection.fetch_add(1, Ordering::Relaxed);
+        let mut connection = Connection {
+            tenant: format!("connection-{}", id),
+            sessions: HashMap::new(),
+            next_session: 0,
+            open_sessions: &self.open_sessions,
+        };
+        while let Some(request) = read_frame(&mut stream)? {
+            if let Err(error) = self.handle_request(&mut connection, request, &mut stream) {
+                write_frame(&mut stream, &Response::<B::Ciphertext>::Error(format!("{:#}", error)))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_request(
+        &self,
+        connection: &mut Connection<B>,
+        request: Request<B, B::Ciphertext>,
+        stream: &mut impl Write,
+    ) -> Result<()> {
+        match request {
+            Request::RegisterServerKey(key) => {
+                let backend = key.into_payload(&self.parameter_set)?;
+                let reserved = self.open_sessions.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |open| {
+                    (open < self.max_sessions).then_some(open + 1)
+                });
+                ensure!(reserved.is_ok(), "Server has reached its limit of {} sessions", self.max_sessions);
+                let fhe_aes = BoolFheAes::from_circuit(backend, self.parameter_set, self.circuit.clone());
+                let circuit_fingerprint = fhe_aes.circuit_fingerprint().to_string();
+
+                let session = connection.next_session;
+                connection.next_session += 1;
+                let state = Session { fhe_aes: Arc::new(fhe_aes), key_expanded: false };
+                connection.sessions.insert(session, state);
+                info!("Registered session {}", session);
+                write_frame(stream, &Response::<B::Ciphertext>::Registered { session, circuit_fingerprint })
+            }
+            Request::ExpandKey { session, key } => {
+                let key = block(key.into_payload(&self.parameter_set)?)?;
+                let session = connection.session(session)?;
+                session.fhe_aes.expand_key(key);
+                session.key_expanded = true;
+                write_frame(stream, &Response::<B::Ciphertext>::KeyExpanded)
+            }
+            Request::Keystream { session: id, iv, count } => {
+                let iv = block(iv.into_payload(&self.parameter_set)?)?;
+                let session = connection.session(id)?;
+                ensure!(session.key_expanded, "No AES key was expanded in this session");
+                let count = usize::try_from(count).context("Block count does not fit in memory")?;
+                let fhe_aes = Arc::clone(&session.fhe_aes);
+                let job = self.scheduler.submit_ctr(&connection.tenant, fhe_aes, iv, count)?;
+                stream_job(stream, job, |_, block| block)
+            }
+            Request::Transcipher { session: id, counter, ciphertext } => {
+                let session = connection.session(id)?;
+                ensure!(session.key_expanded, "No AES key was expanded in this session");
+                let fhe_aes = Arc::clone(&session.fhe_aes);
+                let chunks: Vec<&[u8]> = ciphertext.chunks(AES_BLOCK_SIZE).collect();
+                let counters = (0..chunks.len()).map(|i| fhe_aes.trivial_counter(&counter, i)).collect();
+                let job = self.scheduler.submit(&connection.tenant, Arc::clone(&fhe_aes), counters)?;
+                stream_job(stream, job, |i, keystream| fhe_aes.xor_cleartext(&keystream, chunks[i]))
+            }
+            Request::Metrics => write_frame(stream, &Response::<B::Ciphertext>::Metrics(self.scheduler.metrics())),
+        }
+    }
+}
+
+/// Removes the socket file left at `path` by a server that is no longer running
+#[cfg(unix)]
+fn remove_stale_socket(path: &std::path::Path) -> Result<()> {
+    use std::os::unix::fs::FileTypeExt;
+
+    let Ok(metadata) = std::fs::symlink_metadata(path) else { return Ok(()) };
+    ensure!(metadata.file_type().is_socket(), "{} exists and is not a socket", path.display());
+    ensure!(
+        std::os::unix::net::UnixStream::connect(path).is_err(),
+        "Another server is listening on {}",
+        path.display()
+    );
+    std::fs::remove_file(path).with_context(|| format!("Failed to remove stale socket {}", path.display()))
+}
+
+/// Writes every block of `job`, mapped through `output`, as a `Response::Block`
+/// frame as soon as it completes, followed by `Response::Done`
+fn stream_job<B: BooleanBackend>(
+    stream: &mut impl Write,
+    job: JobHandle<B>,
+    output: impl Fn(usize, [B::Ciphertext; AES_128_OUTPUT_BITSIZE]) -> [B::Ciphertext; AES_128_OUTPUT_BITSIZE],
+) -> Result<()>
+where
+    B::Ciphertext: Serialize,
+{
+    for result in job {
+        let (index, block) = result?;
+        let block = output(index, block).to_vec();
+        write_frame(stream, &Response::Block { index: index as u64, block })?;
+    }
+    write_frame(stream, &Response::<B::Ciphertext>::Done)
+}
+
+/// Converts the wires of one block received in a frame into an array
+pub(crate) fn block<C>(wires: Vec<C>) -> Result<[C; AES_BLOCK_SIZE_BITS]> {
+    wires
+        .try_into()
+        .map_err(|wires: Vec<C>| anyhow!("Expected {} ciphertexts, got {}", AES_BLOCK_SIZE_BITS, wires.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::Cleartext;
+    use crate::circuit::{bytes_to_wires, wires_to_bytes};
+    use crate::encrypt_reference_aes128;
+    use crate::serialization::Artifact;
+    use std::net::TcpStream;
+    use std::time::{Duration, Instant};
+
+    type ClearRequest = Request<Cleartext, bool>;
+
+    fn roundtrip(stream: &mut TcpStream, request: &ClearRequest) -> Response<bool> {
+        write_frame(stream, request).unwrap();
+        read_frame(stream).unwrap().unwrap()
+    }
+
+    /// Collects the blocks streamed for a request, ordered by index
+    fn blocks(stream: &mut TcpStream, request: &ClearRequest) -> Vec<[u8; 16]> {
+        write_frame(stream, request).unwrap();
+        let mut blocks = Vec::new();
+        loop {
+            match read_frame(stream).unwrap().unwrap() {
+                Response::Block { index, block } => blocks.push((index, wires_to_bytes(&block))),
+                Response::Done => break,
+                response => panic!("Unexpected response {:?}", response),
+            }
+        }
+        blocks.sort();
+        blocks.into_iter().map(|(_, block)| block).collect()
+    }
+
+    #[test]
+    fn test_serve_over_tcp() {
+        let parameter_set = ParameterSet::Default;
+        let server = Arc::new(Server::<Cleartext>::new(parameter_set, AesCircuit::ScaleMamba));
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        std::thread::spawn(move || server.handle_connection(listener.accept().unwrap().0));
+        let mut stream = TcpStream::connect(address).unwrap();
+
+        let key = [0x2b; 16];
+        let iv = [0xff; 16];
+        let wires = |bytes: &[u8]| Artifact::new(&parameter_set, bytes_to_wires(bytes));
+
+        let response = roundtrip(&mut stream, &Request::RegisterServerKey(Artifact::new(&parameter_set, Cleartext)));
+        let Response::Registered { session, circuit_fingerprint } = response else {
+            panic!("Expected a session, got {:?}", response);
+        };
+        assert_eq!(circuit_fingerprint, AesCircuit::ScaleMamba.circuit().fingerprint());
+
+        let response = roundtrip(&mut stream, &Request::Keystream { session, iv: wires(&iv), count: 1 });
+        assert!(matches!(response, Response::Error(message) if message.contains("No AES key")));
+
+        let response = roundtrip(&mut stream, &Request::ExpandKey { session, key: wires(&key) });
+        assert!(matches!(response, Response::KeyExpanded));
+
+        // The counter wraps around from all ones
+        let expected: Vec<[u8; 16]> = encrypt_reference_aes128(vec![iv, [0; 16], [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]], key)
+            .iter()
+            .map(|block| (*block).into())
+            .collect();
+        assert_eq!(blocks(&mut stream, &Request::Keystream { session, iv: wires(&iv), count: 3 }), expected);
+
+        let plaintext = *b"streamed over the loopback device";
+        let ciphertext: Vec<u8> = expected.iter().flatten().zip(plaintext).map(|(k, p)| k ^ p).collect();
+        let transciphered = blocks(&mut stream, &Request::Transcipher { session, counter: iv, ciphertext });
+        assert_eq!(&transciphered.concat()[..plaintext.len()], plaintext);
+
+        let Response::Metrics(metrics) = roundtrip(&mut stream, &Request::Metrics) else {
+            panic!("Expected metrics");
+        };
+        assert_eq!((metrics.completed_jobs, metrics.completed_blocks, metrics.queued_blocks), (2, 6, 0));
+
+        let response = roundtrip(&mut stream, &Request::ExpandKey { session: session + 1, key: wires(&key) });
+        assert!(matches!(response, Response::Error(message) if message.contains("Unknown session")));
+        let other_params = Artifact::new(&ParameterSet::TfheLib, bytes_to_wires(&key));
+        let response = roundtrip(&mut stream, &Request::ExpandKey { session, key: other_params });
+        assert!(matches!(response, Response::Error(message) if message.contains("parameter set")));
+    }
+
+    #[test]
+    fn test_sessions_are_per_connection() {
+        let parameter_set = ParameterSet::Default;
+        let server = Arc::new(Server::<Cleartext>::new(parameter_set, AesCircuit::ScaleMamba).with_max_sessions(1));
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let server = Arc::clone(&server);
+                let stream = stream.unwrap();
+                std::thread::spawn(move || server.handle_connection(stream));
+            }
+        });
+        let register = Request::RegisterServerKey(Artifact::new(&parameter_set, Cleartext));
+        let key = Artifact::new(&parameter_set, bytes_to_wires(&[0; 16]));
+
+        let mut first = TcpStream::connect(address).unwrap();
+        let Response::Registered { session, .. } = roundtrip(&mut first, &register) else {
+            panic!("Expected a session");
+        };
+
+        // Another connection neither sees the session nor gets past the limit
+        let mut second = TcpStream::connect(address).unwrap();
+        let response = roundtrip(&mut second, &Request::ExpandKey { session, key: key.clone() });
+        assert!(matches!(response, Response::Error(message) if message.contains("Unknown session")));
+        let response = roundtrip(&mut second, &register);
+        assert!(matches!(response, Response::Error(message) if message.contains("limit of 1 sessions")));
+
+        // Closing the first connection frees its session
+        drop(first);
+        let deadline = Instant::now() + Duration::from_secs(10);
+        loop {
+            match roundtrip(&mut second, &register) {
+                Response::Registered { .. } => break,
+                response => assert!(Instant::now() < deadline, "Session was not released: {:?}", response),
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+}
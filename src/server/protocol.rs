@@ -0,0 +1,152 @@
+//! Length-prefixed binary protocol spoken by `fhe-aes serve`
+//!
+//! Every frame is a big-endian `u32` length followed by that many bytes of a
+//! bincode-encoded [`Request`] or [`Response`].
+
+use crate::fhe_aes::SchedulerMetrics;
+use crate::serialization::Artifact;
+use anyhow::{bail, ensure, Context, Result};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::fmt;
+use std::io::{ErrorKind, Read, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
+use tfhe::boolean::prelude::*;
+
+/// Largest accepted frame (256 MiB); server keys are the biggest payloads
+pub const MAX_FRAME_LEN: u32 = 1 << 28;
+
+/// Request sent to the server; each one is answered by one or more `Response`s
+#[derive(Serialize, Deserialize)]
+pub enum Request<K = ServerKey, C = Ciphertext> {
+    /// Registers a server key in a new session; answered with `Registered`
+    RegisterServerKey(Artifact<K>),
+    /// Expands the 128 encrypted AES key bits under a session; answered with `KeyExpanded`
+    ExpandKey { session: u64, key: Artifact<Vec<C>> },
+    /// Computes `count` CTR keystream blocks from the 128 encrypted counter bits;
+    /// answered with one `Block` per keystream block, then `Done`
+    Keystream { session: u64, iv: Artifact<Vec<C>>, count: u64 },
+    /// Transciphers AES-CTR `ciphertext`, encrypted under the session key from the
+    /// cleartext `counter`; answered with one `Block` per 16 bytes, then `Done`
+    Transcipher { session: u64, counter: [u8; 16], ciphertext: Vec<u8> },
+    /// Answered with `Metrics`
+    Metrics,
+}
+
+/// Response sent by the server
+#[derive(Debug, Serialize, Deserialize)]
+pub enum Response<C = Ciphertext> {
+    Registered { session: u64, circuit_fingerprint: String },
+    KeyExpanded,
+    /// Output block `index` of a `Keystream` or `Transcipher` request. Blocks are
+    /// streamed as soon as they are computed, in no particular order.
+    Block { index: u64, block: Vec<C> },
+    /// Ends a stream of `Block`s
+    Done,
+    /// Queue depth and latency of the server's job scheduler
+    Metrics(SchedulerMetrics),
+    /// Ends a request that failed, possibly after some `Block`s
+    Error(String),
+}
+
+/// Writes `message` as one frame
+pub fn write_frame<T: Serialize>(writer: &mut impl Write, message: &T) -> Result<()> {
+    let bytes = bincode::serialize(message).context("Failed to serialize frame")?;
+    let len = u32::try_from(bytes.len()).ok().filter(|len| *len <= MAX_FRAME_LEN);
+    let Some(len) = len else {
+        bail!("Frame of {} bytes exceeds the {} byte limit", bytes.len(), MAX_FRAME_LEN);
+    };
+    writer.write_all(&len.to_be_bytes())?;
+    writer.write_all(&bytes)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Reads one frame, or returns `None` if the peer closed the connection between frames
+pub fn read_frame<T: DeserializeOwned>(reader: &mut impl Read) -> Result<Option<T>> {
+    let mut len = [0; 4];
+    match reader.read_exact(&mut len) {
+        Err(error) if error.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        result => result.context("Failed to read frame length")?,
+    }
+    let len = u32::from_be_bytes(len);
+    ensure!(len <= MAX_FRAME_LEN, "Frame of {} bytes exceeds the {} byte limit", len, MAX_FRAME_LEN);
+
+    // Read what actually arrives instead of allocating the announced length up front
+    let mut bytes = Vec::new();
+    reader.by_ref().take(len.into()).read_to_end(&mut bytes).context("Failed to read frame")?;
+    ensure!(bytes.len() == len as usize, "Truncated frame: {} of {} bytes", bytes.len(), len);
+    bincode::deserialize(&bytes).map(Some).context("Failed to deserialize frame")
+}
+
+/// Address the server listens on: `host:port` (optionally prefixed with `tcp:`) or
+/// `unix:path` for a Unix socket
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Endpoint {
+    Tcp(String),
+    Unix(PathBuf),
+}
+
+impl FromStr for Endpoint {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if let Some(path) = s.strip_prefix("unix:") {
+            ensure!(!path.is_empty(), "Missing Unix socket path");
+            return Ok(Endpoint::Unix(PathBuf::from(path)));
+        }
+        let address = s.strip_prefix("tcp:").unwrap_or(s);
+        ensure!(address.contains(':'), "Expected host:port or unix:path, got '{}'", s);
+        Ok(Endpoint::Tcp(address.to_string()))
+    }
+}
+
+impl fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Endpoint::Tcp(address) => write!(f, "tcp:{}", address),
+            Endpoint::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_frame_round_trip() {
+        let mut buffer = Vec::new();
+        write_frame(&mut buffer, &Response::<bool>::Block { index: 7, block: vec![true, false] }).unwrap();
+        write_frame(&mut buffer, &Response::<bool>::Done).unwrap();
+        assert_eq!(u32::from_be_bytes(buffer[..4].try_into().unwrap()) as usize, buffer.len() - 4 - 8);
+
+        let mut reader = Cursor::new(buffer);
+        let Some(Response::Block { index, block }) = read_frame::<Response<bool>>(&mut reader).unwrap() else {
+            panic!("Expected a block");
+        };
+        assert_eq!((index, block), (7, vec![true, false]));
+        assert!(matches!(read_frame(&mut reader).unwrap(), Some(Response::<bool>::Done)));
+        assert!(read_frame::<Response<bool>>(&mut reader).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_invalid_frames() {
+        let oversized = (MAX_FRAME_LEN + 1).to_be_bytes();
+        assert!(read_frame::<Response<bool>>(&mut Cursor::new(oversized)).is_err());
+
+        let truncated = [0, 0, 0, 9, 1];
+        assert!(read_frame::<Response<bool>>(&mut Cursor::new(truncated)).is_err());
+    }
+
+    #[test]
+    fn test_parse_endpoint() {
+        assert_eq!("127.0.0.1:7878".parse::<Endpoint>().unwrap(), Endpoint::Tcp("127.0.0.1:7878".into()));
+        assert_eq!("tcp:localhost:1".parse::<Endpoint>().unwrap(), Endpoint::Tcp("localhost:1".into()));
+        assert_eq!("unix:/tmp/fhe.sock".parse::<Endpoint>().unwrap(), Endpoint::Unix("/tmp/fhe.sock".into()));
+        assert!("unix:".parse::<Endpoint>().is_err());
+        assert!("localhost".parse::<Endpoint>().is_err());
+        assert_eq!(Endpoint::Unix("/a".into()).to_string().parse::<Endpoint>().unwrap(), Endpoint::Unix("/a".into()));
+    }
+}
@@ -0,0 +1,84 @@
+//! Step circuits of the stream ciphers, built with `CircuitBuilder`
+
+use super::{StreamCipher, TRIVIUM_STATE_BITS};
+use crate::circuit::{Circuit, CircuitBuilder};
+
+impl StreamCipher {
+    /// Builds the circuit running `rounds` rounds: the input is the state, the
+    /// outputs are the `rounds` keystream bits, in order, then the new state.
+    ///
+    /// Each round reads no state bit written less than 64 rounds earlier, so up to
+    /// 64 rounds evaluate in parallel and the circuit depth grows slowly with `rounds`.
+    pub fn step_circuit(&self, rounds: usize) -> Circuit {
+        let mut builder = CircuitBuilder::new();
+        let mut state = builder.input(self.state_bits() as u32);
+        let mut keystream = Vec::with_capacity(rounds);
+
+        for _ in 0..rounds {
+            let s = |i: usize| state[i - 1];
+            let mut t1 = builder.xor(s(66), s(93));
+            let t2 = builder.xor(s(162), s(177));
+            let mut t3 = builder.xor(s(243), s(288));
+            if *self == StreamCipher::Kreyvium {
+                t3 = builder.xor(t3, state[TRIVIUM_STATE_BITS]);
+            }
+            let z = builder.xor(t1, t2);
+            keystream.push(builder.xor(z, t3));
+
+            if *self == StreamCipher::Kreyvium {
+                t1 = builder.xor(t1, state[TRIVIUM_STATE_BITS + 128]);
+            }
+            let t1 = feedback(&mut builder, t1, (s(91), s(92)), s(171));
+            let t2 = feedback(&mut builder, t2, (s(175), s(176)), s(264));
+            let t3 = feedback(&mut builder, t3, (s(286), s(287)), s(69));
+
+            state[..TRIVIUM_STATE_BITS].rotate_right(1);
+            state[0] = t3;
+            state[93] = t1;
+            state[177] = t2;
+            if *self == StreamCipher::Kreyvium {
+                state[TRIVIUM_STATE_BITS..TRIVIUM_STATE_BITS + 128].rotate_left(1);
+                state[TRIVIUM_STATE_BITS + 128..].rotate_left(1);
+            }
+        }
+
+        builder.output(&keystream);
+        builder.output(&state);
+        builder.build()
+    }
+}
+
+/// `t + a AND b + c`, the nonlinear feedback of one register
+fn feedback(builder: &mut CircuitBuilder, t: u32, (a, b): (u32, u32), c: u32) -> u32 {
+    let and = builder.and(a, b);
+    let t = builder.xor(t, and);
+    builder.xor(t, c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream::{bits_to_bytes, STEP_ROUNDS, WARMUP_ROUNDS};
+
+    #[test]
+    fn test_step_circuit_matches_reference() {
+        for cipher in StreamCipher::ALL {
+            let key: Vec<u8> = (0..cipher.key_size() as u8).collect();
+            let iv: Vec<u8> = (0..cipher.iv_size() as u8).map(|i| i * 7).collect();
+            let circuit = cipher.step_circuit(STEP_ROUNDS);
+            assert_eq!(circuit.stats().and_gates, 3 * STEP_ROUNDS);
+
+            let mut state =
+                cipher.initial_state(&StreamCipher::key_bits(&key), &StreamCipher::key_bits(&iv), false, true);
+            let mut keystream = Vec::new();
+            for step in 0..WARMUP_ROUNDS / STEP_ROUNDS + 2 {
+                let mut outputs = circuit.evaluate_outputs(&state);
+                state = outputs.split_off(STEP_ROUNDS);
+                if step >= WARMUP_ROUNDS / STEP_ROUNDS {
+                    keystream.extend(outputs);
+                }
+            }
+            assert_eq!(bits_to_bytes(&keystream), cipher.reference_keystream(&key, &iv, 16), "{}", cipher);
+        }
+    }
+}
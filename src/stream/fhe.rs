@@ -0,0 +1,97 @@
+//! Homomorphic evaluation of the stream cipher step circuits
+
+use super::{check_iv_size, StreamCipher, Transcipher, STEP_ROUNDS, WARMUP_ROUNDS};
+use crate::backend::BooleanBackend;
+use crate::circuit::Circuit;
+use anyhow::{ensure, Context, Result};
+use std::sync::RwLock;
+use tfhe::boolean::prelude::*;
+
+/// Evaluates a `StreamCipher` under an encrypted key, with gates evaluated through
+/// the [`BooleanBackend`] `B`
+pub struct FheStreamCipher<B: BooleanBackend = ServerKey> {
+    cipher: StreamCipher,
+    backend: B,
+    step: Circuit,
+    key: RwLock<Option<Vec<B::Ciphertext>>>,
+}
+
+impl<B: BooleanBackend> FheStreamCipher<B> {
+    pub fn new(backend: B, cipher: StreamCipher) -> Self {
+        Self { cipher, backend, step: cipher.step_circuit(STEP_ROUNDS), key: RwLock::new(None) }
+    }
+
+    pub fn cipher(&self) -> StreamCipher {
+        self.cipher
+    }
+
+    /// Sets the key bits returned by `StreamCipher::encrypt_key`
+    pub fn set_key(&self, key: Vec<B::Ciphertext>) -> Result<()> {
+        let expected = self.cipher.key_size() * 8;
+        ensure!(key.len() == expected, "{} keys are {} bits, got {}", self.cipher, expected, key.len());
+        *self.key.write().unwrap() = Some(key);
+        Ok(())
+    }
+
+    /// Computes `bits` encrypted keystream bits for the public `iv`, after the warm-up
+    pub fn keystream_bits(&self, iv: &[u8], bits: usize) -> Result<Vec<B::Ciphertext>> {
+        check_iv_size(self, iv)?;
+        let key = self.key.read().unwrap();
+        let key = key.as_ref().context("No stream cipher key was set")?;
+
+        let iv: Vec<B::Ciphertext> =
+            StreamCipher::key_bits(iv).into_iter().map(|bit| self.backend.trivial(bit)).collect();
+        let mut state = self.cipher.initial_state(key, &iv, self.backend.trivial(false), self.backend.trivial(true));
+        let warmup_steps = WARMUP_ROUNDS / STEP_ROUNDS;
+        let mut keystream = Vec::with_capacity(bits.next_multiple_of(STEP_ROUNDS));
+        for step in 0..warmup_steps + bits.div_ceil(STEP_ROUNDS) {
+            let mut outputs = self.step.evaluate_on(&self.backend, &state);
+            state = outputs.split_off(STEP_ROUNDS);
+            if step >= warmup_steps {
+                keystream.extend(outputs);
+            }
+        }
+        keystream.truncate(bits);
+        Ok(keystream)
+    }
+}
+
+impl<B: BooleanBackend> Transcipher<B> for FheStreamCipher<B> {
+    fn iv_size(&self) -> usize {
+        self.cipher.iv_size()
+    }
+
+    fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    fn keystream_bytes(&self, iv: &[u8], len: usize) -> Result<Vec<B::Ciphertext>> {
+        self.keystream_bits(iv, len * 8)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::Cleartext;
+    use crate::stream::bits_to_bytes;
+
+    #[test]
+    fn test_transcipher_recovers_plaintext() {
+        for cipher in StreamCipher::ALL {
+            let key: Vec<u8> = (0..cipher.key_size() as u8).map(|i| i * 3 + 1).collect();
+            let iv = vec![0x5a; cipher.iv_size()];
+            let plaintext = b"cheaper than AES";
+            let keystream = cipher.reference_keystream(&key, &iv, plaintext.len());
+            let ciphertext: Vec<u8> = plaintext.iter().zip(&keystream).map(|(p, k)| p ^ k).collect();
+
+            let fhe = FheStreamCipher::new(Cleartext, cipher);
+            assert!(fhe.keystream_bytes(&iv, 1).is_err());
+            fhe.set_key(StreamCipher::key_bits(&key)).unwrap();
+            assert!(fhe.keystream_bytes(&iv[1..], 1).is_err());
+
+            assert_eq!(bits_to_bytes(&fhe.keystream_bytes(&iv, 3).unwrap()), keystream[..3]);
+            assert_eq!(bits_to_bytes(&fhe.transcipher_bytes(&iv, &ciphertext).unwrap()), plaintext);
+        }
+    }
+}
@@ -0,0 +1,259 @@
+//! Trivium and Kreyvium stream ciphers for cheaper transciphering
+//!
+//! Both ciphers need three ANDs per keystream bit, against thousands per AES
+//! block, so they are much cheaper to evaluate under FHE. They run as boolean
+//! circuits on the same gate engine as AES: one circuit advances the cipher state
+//! by `STEP_ROUNDS` rounds and outputs the keystream bits of those rounds.
+//!
+//! [`Transcipher`] is the interface shared with AES-CTR, so a workload can choose
+//! its cipher. Encrypted bytes are 8 wires each, least significant bit first.
+
+mod circuit;
+mod fhe;
+
+pub use fhe::FheStreamCipher;
+
+use crate::backend::BooleanBackend;
+use anyhow::{ensure, Result};
+use std::fmt;
+use std::str::FromStr;
+use tfhe::boolean::prelude::*;
+
+/// Rounds evaluated by one step circuit
+pub const STEP_ROUNDS: usize = 64;
+
+/// Rounds run before the first keystream bit is output
+pub const WARMUP_ROUNDS: usize = 4 * 288;
+
+/// Bits in the Trivium state `s1..s288`
+const TRIVIUM_STATE_BITS: usize = 288;
+
+/// An FHE-friendly stream cipher
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StreamCipher {
+    /// Trivium, with an 80-bit key and IV
+    #[default]
+    Trivium,
+    /// Kreyvium, the 128-bit security variant of Trivium
+    Kreyvium,
+}
+
+impl StreamCipher {
+    pub const ALL: [StreamCipher; 2] = [StreamCipher::Trivium, StreamCipher::Kreyvium];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            StreamCipher::Trivium => "trivium",
+            StreamCipher::Kreyvium => "kreyvium",
+        }
+    }
+
+    /// Key size in bytes
+    pub fn key_size(&self) -> usize {
+        match self {
+            StreamCipher::Trivium => 10,
+            StreamCipher::Kreyvium => 16,
+        }
+    }
+
+    /// IV size in bytes
+    pub fn iv_size(&self) -> usize {
+        self.key_size()
+    }
+
+    /// Bits of state carried between steps: `s1..s288`, followed for Kreyvium by
+    /// the `K*` and `IV*` registers
+    pub fn state_bits(&self) -> usize {
+        match self {
+            StreamCipher::Trivium => TRIVIUM_STATE_BITS,
+            StreamCipher::Kreyvium => TRIVIUM_STATE_BITS + 2 * 128,
+        }
+    }
+
+    /// Loads `key` and `iv`, as returned by `key_bits`, into the initial state
+    pub(crate) fn initial_state<T: Clone>(&self, key: &[T], iv: &[T], zero: T, one: T) -> Vec<T> {
+        let mut state = vec![zero; self.state_bits()];
+        match self {
+            StreamCipher::Trivium => {
+                state[..80].clone_from_slice(key);
+                state[93..173].clone_from_slice(iv);
+                state[285..288].fill(one);
+            }
+            StreamCipher::Kreyvium => {
+                state[..93].clone_from_slice(&key[..93]);
+                state[93..221].clone_from_slice(iv);
+                state[221..287].fill(one);
+                // K* and IV* hold the key and IV reversed, K*_0 = K_127
+                for i in 0..128 {
+                    state[288 + i] = key[127 - i].clone();
+                    state[416 + i] = iv[127 - i].clone();
+                }
+            }
+        }
+        state
+    }
+
+    /// Splits a key or IV into the bits `K_1, K_2, ...` of the specification.
+    /// As in the eSTREAM and Kreyvium reference code, `K_1` is the most significant
+    /// bit of the last byte.
+    pub fn key_bits(bytes: &[u8]) -> Vec<bool> {
+        let mut bits = bytes_to_bits(bytes);
+        bits.reverse();
+        bits
+    }
+
+    /// Encrypts `key` bit by bit for `FheStreamCipher::set_key`
+    pub fn encrypt_key(&self, client_key: &ClientKey, key: &[u8]) -> Result<Vec<Ciphertext>> {
+        ensure!(key.len() == self.key_size(), "{} keys are {} bytes, got {}", self, self.key_size(), key.len());
+        Ok(Self::key_bits(key).into_iter().map(|bit| client_key.encrypt(bit)).collect())
+    }
+
+    /// Computes `len` keystream bytes in cleartext, directly from the specification
+    pub fn reference_keystream(&self, key: &[u8], iv: &[u8], len: usize) -> Vec<u8> {
+        let mut state = self.initial_state(&Self::key_bits(key), &Self::key_bits(iv), false, true);
+        let mut keystream = Vec::with_capacity(len * 8);
+        for round in 0..WARMUP_ROUNDS + len * 8 {
+            let z = self.reference_round(&mut state);
+            if round >= WARMUP_ROUNDS {
+                keystream.push(z);
+            }
+        }
+        bits_to_bytes(&keystream)
+    }
+
+    /// Runs one round on the cleartext `state` and returns its keystream bit
+    fn reference_round(&self, state: &mut [bool]) -> bool {
+        let s = |i: usize| state[i - 1];
+        let (k, iv) = match self {
+            StreamCipher::Trivium => (false, false),
+            StreamCipher::Kreyvium => (state[288], state[416]),
+        };
+        let t1 = s(66) ^ s(93);
+        let t2 = s(162) ^ s(177);
+        let t3 = s(243) ^ s(288) ^ k;
+        let z = t1 ^ t2 ^ t3;
+        let t1 = t1 ^ (s(91) & s(92)) ^ s(171) ^ iv;
+        let t2 = t2 ^ (s(175) & s(176)) ^ s(264);
+        let t3 = t3 ^ (s(286) & s(287)) ^ s(69);
+
+        state[..TRIVIUM_STATE_BITS].rotate_right(1);
+        state[0] = t3;
+        state[93] = t1;
+        state[177] = t2;
+        if *self == StreamCipher::Kreyvium {
+            state[288..416].rotate_left(1);
+            state[416..].rotate_left(1);
+        }
+        z
+    }
+}
+
+impl fmt::Display for StreamCipher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for StreamCipher {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        StreamCipher::ALL
+            .into_iter()
+            .find(|cipher| cipher.name() == s)
+            .ok_or_else(|| {
+                let names: Vec<_> = StreamCipher::ALL.iter().map(StreamCipher::name).collect();
+                format!("Unknown stream cipher '{}', expected one of: {}", s, names.join(", "))
+            })
+    }
+}
+
+/// Homomorphic decryption of symmetric ciphertexts produced under an encrypted key
+/// and a public IV, implemented by AES-CTR (`BoolFheAes`) and `FheStreamCipher`
+pub trait Transcipher<B: BooleanBackend> {
+    /// Size of the public IV in bytes; for AES-CTR, the initial counter block
+    fn iv_size(&self) -> usize;
+
+    fn backend(&self) -> &B;
+
+    /// Computes `len` encrypted keystream bytes for `iv`
+    fn keystream_bytes(&self, iv: &[u8], len: usize) -> Result<Vec<B::Ciphertext>>;
+
+    /// Decrypts `ciphertext`, encrypted with `iv`, into encrypted plaintext bytes
+    fn transcipher_bytes(&self, iv: &[u8], ciphertext: &[u8]) -> Result<Vec<B::Ciphertext>> {
+        let keystream = self.keystream_bytes(iv, ciphertext.len())?;
+        Ok(xor_cleartext(self.backend(), &keystream, ciphertext))
+    }
+}
+
+/// Fails unless the IV has the size `transcipher` expects
+pub(crate) fn check_iv_size<B: BooleanBackend>(transcipher: &impl Transcipher<B>, iv: &[u8]) -> Result<()> {
+    ensure!(iv.len() == transcipher.iv_size(), "Expected a {} byte IV, got {}", transcipher.iv_size(), iv.len());
+    Ok(())
+}
+
+/// XORs cleartext `bytes` into encrypted `bits`; set bits become a NOT
+pub fn xor_cleartext<B: BooleanBackend>(backend: &B, bits: &[B::Ciphertext], bytes: &[u8]) -> Vec<B::Ciphertext> {
+    backend.xor_public(bits, &bytes_to_bits(bytes))
+}
+
+/// Decrypts encrypted bytes, 8 ciphertexts each, least significant bit first
+pub fn decrypt_bytes(client_key: &ClientKey, bits: &[Ciphertext]) -> Vec<u8> {
+    let bits: Vec<bool> = bits.iter().map(|bit| client_key.decrypt(bit)).collect();
+    bits_to_bytes(&bits)
+}
+
+/// Splits bytes into bits, least significant bit of each byte first
+pub fn bytes_to_bits(bytes: &[u8]) -> Vec<bool> {
+    bytes.iter().flat_map(|byte| (0..8).map(move |bit| byte >> bit & 1 == 1)).collect()
+}
+
+/// Inverse of `bytes_to_bits`; a trailing partial byte is zero-padded
+pub fn bits_to_bytes(bits: &[bool]) -> Vec<u8> {
+    bits.chunks(8)
+        .map(|byte| byte.iter().enumerate().fold(0, |acc, (bit, set)| acc | (*set as u8) << bit))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trivium_estream_vector() {
+        // eSTREAM Trivium test vectors, set 6, vector 0
+        let key = hex::decode("0053A6F94C9FF24598EB").unwrap();
+        let iv = hex::decode("0D74DB42A91077DE45AC").unwrap();
+        let expected = hex::decode(concat!(
+            "F4CD954A717F26A7D6930830C4E7CF0819F80E03F25F342C64ADC66ABA7F8A8E",
+            "6EAA49F23632AE3CD41A7BD290A0132F81C6D4043B6E397D7388F3A03B5FE358"
+        ))
+        .unwrap();
+        assert_eq!(StreamCipher::Trivium.reference_keystream(&key, &iv, 64), expected);
+    }
+
+    #[test]
+    fn test_kreyvium_reference_vector() {
+        // Kreyvium reference implementation, all-zero key and IV
+        let expected = hex::decode("26DCF1F4BC0F1922").unwrap();
+        assert_eq!(StreamCipher::Kreyvium.reference_keystream(&[0; 16], &[0; 16], 8), expected);
+
+        // Independent model of the specification; asymmetric key and IV catch
+        // swapped bit or byte orders and the K*/IV* register loading
+        let key = hex::decode("000102030405060708090A0B0C0D0E0F").unwrap();
+        let iv = hex::decode("F0E1D2C3B4A5968778695A4B3C2D1E0F").unwrap();
+        let expected = hex::decode("BDD1FD0D6514F2215684F6CB1B4CB921CF86C2F69D3C35132075ACD89E19C85D").unwrap();
+        assert_eq!(StreamCipher::Kreyvium.reference_keystream(&key, &iv, 32), expected);
+    }
+
+    #[test]
+    fn test_bits_round_trip() {
+        let bytes = [0x01, 0x80, 0xa5];
+        let bits = bytes_to_bits(&bytes);
+        assert!(bits[0] && !bits[7] && bits[15]);
+        assert_eq!(bits_to_bytes(&bits), bytes);
+        assert!(StreamCipher::key_bits(&bytes)[0]);
+        assert_eq!("kreyvium".parse::<StreamCipher>().unwrap(), StreamCipher::Kreyvium);
+        assert!("rc4".parse::<StreamCipher>().is_err());
+    }
+}
@@ -22,6 +22,17 @@ pub const EXPANDED_KEY_SIZE: usize = 176;
 /// The number of columns comprising a state in AES.
 pub const NB: usize = 4;
 
+/// Number of rounds in AES-128.
+pub const AES_128_ROUNDS: usize = 10;
+
+/// Multiplicative (AND) depth of one S-box layer in the embedded AES-128 circuit.
+pub const AND_DEPTH_PER_ROUND: u32 = 6;
+
+/// Fingerprint of the embedded AES-128 circuit, see `Circuit::fingerprint`. Both
+/// embedded copies must parse to a circuit with this fingerprint.
+pub const AES_128_CIRCUIT_FINGERPRINT: &str =
+    "27c8b19c9fb33c1e7f115cac1c64c0846b95bc0cc30f9679b0af102e1867453f";
+
 /// The S-box is a substitution box used in the SubBytes step of the AES
 /// encryption process.
 /// It's a fixed (non-key-dependent) table used in the byte substitution
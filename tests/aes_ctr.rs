@@ -57,8 +57,39 @@ fn test_multiple_blocks() {
     let outputs = fhe_aes.aes_ctr_blocks(fhe_iv, 3);
     
     assert_eq!(outputs.len(), 3);
-    outputs.iter().for_each(|output| {
+    let counter = u128::from_be_bytes(iv);
+    let counters = (0..3).map(|i| (counter + i).to_be_bytes()).collect();
+    let expected = encrypt_reference_aes128(counters, key);
+    outputs.iter().zip(&expected).for_each(|(output, expected)| {
         let decrypted = BoolFheAes::decrypt_output(&client_key, output);
         assert_ne!(decrypted, [0u8; 16]);
+        assert_eq!(decrypted.as_slice(), expected.as_slice());
     });
 }
+
+#[test]
+fn test_serialized_outputs_round_trip() {
+    let params = fhe_aes::params::ParameterSet::DefaultKsPbs;
+    let (client_key, server_key) = params.gen_keys();
+    let key = [0x2bu8; 16];
+    let iv = [0x3cu8; 16];
+
+    let fhe_aes = BoolFheAes::with_parameter_set(server_key, params);
+    fhe_aes.expand_key(BoolFheAes::encrypt_key(&client_key, &key));
+    let expanded = fhe_aes.export_expanded_key().unwrap();
+
+    let restored = BoolFheAes::with_parameter_set(fhe_aes.backend().clone(), params);
+    restored.import_expanded_key(&expanded).unwrap();
+    let outputs = restored.aes_ctr_blocks(BoolFheAes::encrypt_iv(&client_key, &iv), 1);
+
+    let bytes = restored.export_outputs(&outputs).unwrap();
+    let imported = restored.import_outputs(&bytes).unwrap();
+    let expected = encrypt_reference_aes128(vec![iv], key);
+    assert_eq!(
+        BoolFheAes::decrypt_output(&client_key, &imported[0]).as_slice(),
+        expected[0].as_slice()
+    );
+
+    let other = BoolFheAes::new(fhe_aes.backend().clone());
+    assert!(other.import_outputs(&bytes).is_err());
+}
@@ -0,0 +1,89 @@
+//! End-to-end runs of `client::Client` against `server::Server` over loopback
+//! sockets, on the cleartext backend so that they stay fast
+
+use fhe_aes::backend::Cleartext;
+use fhe_aes::circuit::AesCircuit;
+use fhe_aes::client::Client;
+use fhe_aes::encrypt_reference_aes128;
+use fhe_aes::params::ParameterSet;
+use fhe_aes::server::{Endpoint, Server};
+use fhe_aes::utils::constants::AES_128_CIRCUIT_FINGERPRINT;
+use std::net::TcpListener;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const KEY: [u8; 16] = [0x2b, 0x7e, 0x15, 0x16, 0x28, 0xae, 0xd2, 0xa6, 0xab, 0xf7, 0x15, 0x88, 0x09, 0xcf, 0x4f, 0x3c];
+const COUNTER: [u8; 16] = [0xf0, 0xf1, 0xf2, 0xf3, 0xf4, 0xf5, 0xf6, 0xf7, 0xf8, 0xf9, 0xfa, 0xfb, 0xfc, 0xfd, 0xfe, 0xff];
+
+fn server() -> Arc<Server<Cleartext>> {
+    Arc::new(Server::new(ParameterSet::Default, AesCircuit::ScaleMamba))
+}
+
+fn connect(endpoint: &Endpoint) -> Client<Cleartext> {
+    Client::connect(endpoint, ParameterSet::Default).unwrap()
+}
+
+fn reference_keystream(count: usize) -> Vec<[u8; 16]> {
+    let counter = u128::from_be_bytes(COUNTER);
+    let counters = (0..count).map(|i| (counter + i as u128).to_be_bytes()).collect();
+    encrypt_reference_aes128(counters, KEY).iter().map(|block| (*block).into()).collect()
+}
+
+#[test]
+fn test_keystream_and_transcipher_over_tcp() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let endpoint = Endpoint::Tcp(listener.local_addr().unwrap().to_string());
+    let server = server();
+    thread::spawn(move || server.handle_connection(listener.accept().unwrap().0));
+
+    let mut client = connect(&endpoint);
+    assert_eq!(client.circuit_fingerprint(), AES_128_CIRCUIT_FINGERPRINT);
+    client.upload_key(&KEY).unwrap();
+
+    let keystream = reference_keystream(2);
+    assert_eq!(client.keystream(&COUNTER, 2).unwrap(), keystream);
+
+    let plaintext = b"transciphered bytes";
+    let ciphertext: Vec<u8> = keystream.iter().flatten().zip(plaintext).map(|(k, p)| k ^ p).collect();
+    assert_eq!(client.transcipher(&COUNTER, &ciphertext).unwrap(), plaintext);
+
+    let metrics = client.metrics().unwrap();
+    assert_eq!((metrics.completed_jobs, metrics.completed_blocks), (2, 4));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_keystream_over_unix_socket() {
+    let path = std::env::temp_dir().join(format!("fhe-aes-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let endpoint = Endpoint::Unix(path.clone());
+    let server = server();
+    let serve_endpoint = endpoint.clone();
+    let serving = thread::spawn(move || server.serve(&serve_endpoint));
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while !path.exists() {
+        if serving.is_finished() {
+            panic!("Server stopped: {:?}", serving.join().unwrap());
+        }
+        assert!(Instant::now() < deadline, "Server did not bind {}", path.display());
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    let mut client = connect(&endpoint);
+    client.upload_key(&KEY).unwrap();
+    assert_eq!(client.keystream(&COUNTER, 1).unwrap(), reference_keystream(1));
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_keystream_requires_a_key() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let endpoint = Endpoint::Tcp(listener.local_addr().unwrap().to_string());
+    let server = server();
+    thread::spawn(move || server.handle_connection(listener.accept().unwrap().0));
+
+    let mut client = connect(&endpoint);
+    let error = client.keystream(&COUNTER, 1).unwrap_err();
+    assert!(error.to_string().contains("No AES key"), "{}", error);
+}
@@ -0,0 +1,57 @@
+//! Builds the cdylib, then compiles and runs the C round trip in tests/ffi against it
+#![cfg(all(feature = "ffi", unix))]
+
+use std::path::Path;
+use std::process::Command;
+
+#[test]
+fn test_header_is_current() {
+    let generated = std::fs::read_to_string(Path::new(env!("OUT_DIR")).join("fhe_aes.h")).unwrap();
+    let committed = std::fs::read_to_string(Path::new(env!("CARGO_MANIFEST_DIR")).join("include/fhe_aes.h")).unwrap();
+    assert!(
+        generated == committed,
+        "include/fhe_aes.h is stale, regenerate it with `cbindgen --config cbindgen.toml --output include/fhe_aes.h`"
+    );
+}
+
+#[test]
+fn test_c_round_trip() {
+    // The test binary is in target/<profile>/deps; the cdylib goes to target/<profile>
+    let exe = std::env::current_exe().unwrap();
+    let profile_dir = exe.parent().unwrap().parent().unwrap();
+    let target_dir = profile_dir.parent().unwrap();
+    let profile = match profile_dir.file_name().unwrap().to_str().unwrap() {
+        "debug" => "dev",
+        profile => profile,
+    };
+    let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let program = Path::new(env!("CARGO_TARGET_TMPDIR")).join("round_trip");
+
+    let status = Command::new(env!("CARGO"))
+        .args(["rustc", "--lib", "--features", "ffi", "--crate-type", "cdylib", "--profile", profile])
+        .arg("--manifest-path")
+        .arg(manifest_dir.join("Cargo.toml"))
+        .arg("--target-dir")
+        .arg(target_dir)
+        .status()
+        .expect("Failed to run cargo");
+    assert!(status.success(), "Failed to build the cdylib");
+
+    let status = Command::new(std::env::var("CC").unwrap_or_else(|_| "cc".to_string()))
+        .arg(manifest_dir.join("tests/ffi/round_trip.c"))
+        .arg("-I")
+        .arg(manifest_dir.join("include"))
+        .arg("-L")
+        .arg(profile_dir)
+        .arg(format!("-Wl,-rpath,{}", profile_dir.display()))
+        .arg("-lfhe_aes")
+        .arg("-o")
+        .arg(&program)
+        .status()
+        .expect("Failed to run the C compiler");
+    assert!(status.success(), "Failed to compile round_trip.c");
+
+    let output = Command::new(&program).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "ok\n");
+}
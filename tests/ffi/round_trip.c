@@ -0,0 +1,66 @@
+/*
+ * Round trip through the C ABI: generate keys, expand an encrypted AES key,
+ * compute two CTR keystream blocks and check them against SP 800-38A F.5.1.
+ *
+ * Built and run by tests/ffi.rs; by hand:
+ *   cargo rustc --lib --release --features ffi --crate-type cdylib
+ *   cc tests/ffi/round_trip.c -Iinclude -Ltarget/release -lfhe_aes -o round_trip
+ *   LD_LIBRARY_PATH=target/release ./round_trip
+ */
+
+#include <stdio.h>
+#include <string.h>
+
+#include "fhe_aes.h"
+
+#define CHECK(call)                                                              \
+    do {                                                                         \
+        FheAesStatus status = (call);                                            \
+        if (status != FHE_AES_STATUS_OK) {                                       \
+            fprintf(stderr, "%s failed with %d: %s\n", #call, (int)status,       \
+                    fhe_aes_last_error());                                       \
+            return 1;                                                            \
+        }                                                                        \
+    } while (0)
+
+static const uint8_t KEY[16] = {0x2b, 0x7e, 0x15, 0x16, 0x28, 0xae, 0xd2, 0xa6,
+                                0xab, 0xf7, 0x15, 0x88, 0x09, 0xcf, 0x4f, 0x3c};
+static const uint8_t COUNTER[16] = {0xf0, 0xf1, 0xf2, 0xf3, 0xf4, 0xf5, 0xf6, 0xf7,
+                                    0xf8, 0xf9, 0xfa, 0xfb, 0xfc, 0xfd, 0xfe, 0xff};
+static const uint8_t KEYSTREAM[32] = {
+    0xec, 0x8c, 0xdf, 0x73, 0x98, 0x60, 0x7c, 0xb0, 0xf2, 0xd2, 0x16, 0x75, 0xea, 0x9e, 0xa1, 0xe4,
+    0x36, 0x2b, 0x7c, 0x3c, 0x67, 0x73, 0x51, 0x63, 0x18, 0xa0, 0x77, 0xd7, 0xfc, 0x50, 0x73, 0xae};
+
+int main(void) {
+    FheAesClientKey *client_key = NULL;
+    FheAes *fhe_aes = NULL;
+    FheAesBuffer server_key = {0}, key = {0}, iv = {0}, outputs = {0}, keystream = {0};
+
+    CHECK(fhe_aes_client_key_generate(NULL, &client_key, &server_key));
+    CHECK(fhe_aes_new(NULL, NULL, server_key.data, server_key.len, &fhe_aes));
+    fhe_aes_buffer_free(server_key);
+
+    CHECK(fhe_aes_encrypt_key(client_key, KEY, &key));
+    CHECK(fhe_aes_encrypt_iv(client_key, COUNTER, &iv));
+    if (fhe_aes_ctr_blocks(fhe_aes, iv.data, iv.len, 2, &outputs) != FHE_AES_STATUS_KEY_NOT_EXPANDED) {
+        fprintf(stderr, "fhe_aes_ctr_blocks ran without an expanded key\n");
+        return 1;
+    }
+
+    CHECK(fhe_aes_expand_key(fhe_aes, key.data, key.len));
+    CHECK(fhe_aes_ctr_blocks(fhe_aes, iv.data, iv.len, 2, &outputs));
+    CHECK(fhe_aes_decrypt_outputs(client_key, outputs.data, outputs.len, &keystream));
+    if (keystream.len != sizeof(KEYSTREAM) || memcmp(keystream.data, KEYSTREAM, sizeof(KEYSTREAM)) != 0) {
+        fprintf(stderr, "Keystream does not match SP 800-38A\n");
+        return 1;
+    }
+
+    fhe_aes_buffer_free(key);
+    fhe_aes_buffer_free(iv);
+    fhe_aes_buffer_free(outputs);
+    fhe_aes_buffer_free(keystream);
+    fhe_aes_free(fhe_aes);
+    fhe_aes_client_key_free(client_key);
+    printf("ok\n");
+    return 0;
+}
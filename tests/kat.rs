@@ -0,0 +1,127 @@
+//! NIST known-answer tests: the AESAVS GFSbox, KeySbox, VarTxt and VarKey ECB
+//! vectors and the SP 800-38A CTR vectors, checked against every AES-128 circuit
+//! with the cleartext evaluator. The `fhe-kat` feature also runs a sample of them
+//! under tfhe.
+
+use fhe_aes::backend::Cleartext;
+use fhe_aes::circuit::{bytes_to_wires, wires_to_bytes, AesCircuit};
+use fhe_aes::params::ParameterSet;
+use fhe_aes::BoolFheAes;
+use std::collections::HashMap;
+use test_case::test_case;
+
+const GFSBOX: &str = include_str!("data/ECBGFSbox128.rsp");
+const KEYSBOX: &str = include_str!("data/ECBKeySbox128.rsp");
+const VARTXT: &str = include_str!("data/ECBVarTxt128.rsp");
+const VARKEY: &str = include_str!("data/ECBVarKey128.rsp");
+const CTR: &str = include_str!("data/CTR-AES128.rsp");
+
+/// Parses the `[ENCRYPT]` section of a CAVS response file into one map of
+/// hex-decoded fields per `COUNT`
+fn parse_rsp(text: &str) -> Vec<HashMap<String, Vec<u8>>> {
+    let mut vectors: Vec<HashMap<String, Vec<u8>>> = Vec::new();
+    let mut encrypt = false;
+
+    for line in text.lines().map(str::trim) {
+        if line.starts_with('#') {
+            continue;
+        }
+        if line.starts_with('[') {
+            encrypt = line == "[ENCRYPT]";
+            continue;
+        }
+        let Some((name, value)) = line.split_once(" = ") else { continue };
+        if !encrypt {
+            continue;
+        }
+        if name == "COUNT" {
+            vectors.push(HashMap::new());
+            continue;
+        }
+        let vector = vectors.last_mut().expect("Field before COUNT");
+        vector.insert(name.to_string(), hex::decode(value).expect("Invalid hex field"));
+    }
+
+    vectors
+}
+
+fn block(vector: &HashMap<String, Vec<u8>>, name: &str) -> [u8; 16] {
+    vector[name].as_slice().try_into().expect("Field is not one block")
+}
+
+#[test_case(GFSBOX, 7 ; "gfsbox")]
+#[test_case(KEYSBOX, 21 ; "keysbox")]
+#[test_case(VARTXT, 128 ; "vartxt")]
+#[test_case(VARKEY, 128 ; "varkey")]
+fn test_ecb_kat(text: &str, count: usize) {
+    let vectors = parse_rsp(text);
+    assert_eq!(vectors.len(), count);
+
+    for circuit in AesCircuit::ALL {
+        let evaluator = circuit.circuit();
+        for (i, vector) in vectors.iter().enumerate() {
+            let output = evaluator.evaluate_aes(&block(vector, "KEY"), &block(vector, "PLAINTEXT"));
+            assert_eq!(output, block(vector, "CIPHERTEXT"), "{} circuit, COUNT = {}", circuit, i);
+        }
+    }
+}
+
+#[test]
+fn test_ctr_kat() {
+    let vectors = parse_rsp(CTR);
+    assert_eq!(vectors.len(), 1);
+    let vector = &vectors[0];
+    let blocks = vector["PLAINTEXT"].len() / 16;
+
+    for circuit in AesCircuit::ALL {
+        let fhe_aes = BoolFheAes::with_circuit(Cleartext, ParameterSet::Default, circuit);
+        fhe_aes.expand_key(bytes_to_wires(&block(vector, "KEY")).try_into().unwrap());
+        let keystream = fhe_aes.aes_ctr_blocks(
+            bytes_to_wires(&block(vector, "COUNTER")).try_into().unwrap(),
+            blocks,
+        );
+
+        let ciphertext: Vec<u8> = keystream
+            .iter()
+            .flat_map(|output| wires_to_bytes(output))
+            .zip(&vector["PLAINTEXT"])
+            .map(|(k, p)| k ^ p)
+            .collect();
+        assert_eq!(ciphertext, vector["CIPHERTEXT"], "{} circuit", circuit);
+    }
+}
+
+/// First vector of every ECB file and the CTR vector, encrypted under tfhe
+#[cfg(feature = "fhe-kat")]
+mod fhe {
+    use super::*;
+    use tfhe::boolean::prelude::*;
+
+    #[test]
+    fn test_fhe_kat_sample() {
+        let (client_key, server_key) = gen_keys();
+        let fhe_aes = BoolFheAes::new(server_key);
+
+        for text in [GFSBOX, KEYSBOX, VARTXT, VARKEY] {
+            let vector = &parse_rsp(text)[0];
+            fhe_aes.expand_key(BoolFheAes::encrypt_key(&client_key, &block(vector, "KEY")));
+            let output = fhe_aes.execute(BoolFheAes::encrypt_iv(&client_key, &block(vector, "PLAINTEXT")));
+            assert_eq!(BoolFheAes::decrypt_output(&client_key, &output), block(vector, "CIPHERTEXT"));
+        }
+
+        let vector = &parse_rsp(CTR)[0];
+        let blocks = vector["PLAINTEXT"].len() / 16;
+        fhe_aes.expand_key(BoolFheAes::encrypt_key(&client_key, &block(vector, "KEY")));
+        let keystream = fhe_aes.aes_ctr_blocks(
+            BoolFheAes::encrypt_iv(&client_key, &block(vector, "COUNTER")),
+            blocks,
+        );
+        let ciphertext: Vec<u8> = keystream
+            .iter()
+            .flat_map(|output| BoolFheAes::decrypt_output(&client_key, output))
+            .zip(&vector["PLAINTEXT"])
+            .map(|(k, p)| k ^ p)
+            .collect();
+        assert_eq!(ciphertext, vector["CIPHERTEXT"]);
+    }
+}
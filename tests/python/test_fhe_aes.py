@@ -0,0 +1,64 @@
+"""Tests for the Python module; run after `maturin develop --release` with pytest."""
+
+import pytest
+
+import fhe_aes
+
+KEY = bytes.fromhex("2b7e151628aed2a6abf7158809cf4f3c")
+COUNTER = bytes.fromhex("f0f1f2f3f4f5f6f7f8f9fafbfcfdfeff")
+KEYSTREAM = [
+    bytes.fromhex("ec8cdf7398607cb0f2d21675ea9ea1e4"),
+    bytes.fromhex("362b7c3c6773516318a077d7fc5073ae"),
+]
+
+
+def test_reference_aes128():
+    next_counter = (int.from_bytes(COUNTER, "big") + 1).to_bytes(16, "big")
+    assert fhe_aes.encrypt_reference_aes128([COUNTER, next_counter], KEY) == KEYSTREAM
+
+
+def test_ctr_round_trip():
+    client_key, server_key = fhe_aes.gen_keys()
+    server_key = fhe_aes.ServerKey.from_bytes(server_key.to_bytes())
+    evaluator = fhe_aes.FheAes(server_key)
+
+    key = fhe_aes.encrypt_key(client_key, KEY)
+    iv = fhe_aes.Ciphertexts.from_bytes(fhe_aes.encrypt_iv(client_key, COUNTER).to_bytes())
+    assert len(iv) == 128
+    with pytest.raises(ValueError, match="No AES key"):
+        evaluator.aes_ctr_blocks(iv, 1)
+
+    evaluator.expand_key(key)
+    blocks = evaluator.aes_ctr_blocks(iv, 2)
+    assert [fhe_aes.decrypt_output(client_key, block) for block in blocks] == KEYSTREAM
+
+    imported = fhe_aes.FheAes(server_key)
+    imported.import_expanded_key(evaluator.export_expanded_key())
+    assert fhe_aes.decrypt_output(client_key, imported.aes_ctr_blocks(iv, 1)[0]) == KEYSTREAM[0]
+
+
+def test_custom_parameter_file(tmp_path):
+    path = tmp_path / "params.json"
+    path.write_text(fhe_aes.parameters_json("tfhe-lib"))
+    client_key, server_key = fhe_aes.gen_keys(str(path))
+    assert client_key.parameter_set.startswith("custom-")
+
+    client_key = fhe_aes.ClientKey.from_bytes(client_key.to_bytes(), str(path))
+    server_key = fhe_aes.ServerKey.from_bytes(server_key.to_bytes(), str(path))
+    iv = fhe_aes.encrypt_iv(client_key, COUNTER)
+    assert len(fhe_aes.Ciphertexts.from_bytes(iv.to_bytes(), str(path))) == 128
+    assert server_key.parameter_set == client_key.parameter_set
+    with pytest.raises(ValueError, match="expected 'default'"):
+        fhe_aes.ClientKey.from_bytes(client_key.to_bytes())
+
+
+def test_invalid_arguments():
+    client_key, server_key = fhe_aes.gen_keys()
+    with pytest.raises(ValueError, match="must be 16 bytes"):
+        fhe_aes.encrypt_key(client_key, b"short")
+    with pytest.raises(ValueError, match="Unknown circuit"):
+        fhe_aes.FheAes(server_key, circuit="nope")
+    with pytest.raises(ValueError, match="Unknown parameter set"):
+        fhe_aes.gen_keys("nope")
+    with pytest.raises(ValueError):
+        fhe_aes.Ciphertexts.from_bytes(b"not an artifact")